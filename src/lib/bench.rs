@@ -0,0 +1,148 @@
+//! Rough timings for scanning, parsing and running a few representative
+//! scripts. The timings are only printed by the ignored `benchmark` test:
+//!
+//! ```text
+//! cargo test --release benchmark -- --ignored --nocapture
+//! ```
+
+use std::{
+    io,
+    time::{Duration, Instant},
+};
+
+use super::{
+    environment::Environment, interpreter::Interpreter, optimizer::optimize, parser::Parser,
+    scanner::Scanner,
+};
+
+const FIBONACCI: &str = "
+fun fib(n) {
+    if n < 2 return n;
+    return fib(n - 1) + fib(n - 2);
+}
+
+for (var i = 0; i < 15; i = i + 1) {
+    result = fib(i);
+}
+print result;
+";
+
+const STRING_BUILDING: &str = "
+text = \"\";
+for (var i = 0; i < 2000; i = i + 1) {
+    text = text + \"ab\";
+}
+print len(text);
+";
+
+const ARITHMETIC: &str = "
+total = 0;
+for (var i = 0; i < 20000; i = i + 1) {
+    total = total + (i * 2 - i / 4) * 3;
+}
+print total > 0;
+";
+
+// Resolves variables through several enclosing scopes on every iteration.
+const VARIABLE_ACCESS: &str = "
+a = 1;
+b = 2;
+fun outer() {
+    var c = 3;
+    fun inner() {
+        var sum = 0;
+        for (var i = 0; i < 5000; i = i + 1) {
+            sum = sum + a + b + c;
+        }
+        return sum;
+    }
+    return inner();
+}
+print outer();
+";
+
+const SCENARIOS: &[(&str, &str)] = &[
+    ("fibonacci", FIBONACCI),
+    ("string building", STRING_BUILDING),
+    ("arithmetic", ARITHMETIC),
+    ("variable access", VARIABLE_ACCESS),
+];
+
+struct Timings {
+    scan: Duration,
+    parse: Duration,
+    run: Duration,
+}
+
+fn time_scenario(source: &str, output: Box<dyn io::Write>) -> Timings {
+    let start = Instant::now();
+    let mut scanner = Scanner::from_source(source);
+    let tokens = scanner
+        .scan_tokens()
+        .expect("Scanner should not fail to parse source");
+    let scan = start.elapsed();
+
+    let start = Instant::now();
+    let program = optimize(
+        Parser::new(tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens"),
+    );
+    let parse = start.elapsed();
+
+    let start = Instant::now();
+    Interpreter::new(Environment::new())
+        .with_output(output)
+        .run(&program)
+        .expect("Program should run without errors");
+    let run = start.elapsed();
+
+    Timings { scan, parse, run }
+}
+
+mod tests {
+    use crate::lib::testing::SharedOutput;
+
+    use super::*;
+
+    #[test]
+    fn scenarios_produce_expected_output() {
+        for ((name, source), expected) in SCENARIOS
+            .iter()
+            .zip(["377\n", "4000\n", "true\n", "30000\n"])
+        {
+            let output = SharedOutput::default();
+
+            time_scenario(source, Box::new(output.clone()));
+
+            assert_eq!(output.contents(), expected, "{name}");
+        }
+    }
+
+    #[test]
+    #[ignore]
+    fn benchmark() {
+        const RUNS: u32 = 10;
+
+        for (name, source) in SCENARIOS {
+            let mut total = Timings {
+                scan: Duration::ZERO,
+                parse: Duration::ZERO,
+                run: Duration::ZERO,
+            };
+            for _ in 0..RUNS {
+                let timings = time_scenario(source, Box::new(io::sink()));
+                total.scan += timings.scan;
+                total.parse += timings.parse;
+                total.run += timings.run;
+            }
+
+            println!(
+                "{name:>16}: scan {:>10.2?}  parse {:>10.2?}  run {:>10.2?}",
+                total.scan / RUNS,
+                total.parse / RUNS,
+                total.run / RUNS
+            );
+        }
+    }
+}
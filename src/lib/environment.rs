@@ -1,23 +1,258 @@
-use std::collections::HashMap;
+use std::{cell::RefCell, collections::HashMap, rc::Rc};
 
-use super::parser::LiteralValue;
+use super::{interner::Symbol, value::Value};
 
+/// A single scope of variables. Lookups which miss in this scope continue in
+/// the enclosing scope, if there is one.
+///
+/// Scopes are keyed by interned identifiers, so walking the enclosing scopes
+/// only compares symbols. The parser interns the identifiers in a script up
+/// front; the methods taking `&str` intern the identifier on each call.
 pub struct Environment {
-    variables: HashMap<String, LiteralValue>,
+    variables: HashMap<Symbol, Value>,
+    enclosing: Option<Rc<RefCell<Environment>>>,
+    block: bool,
 }
 
 impl Environment {
     pub fn new() -> Self {
         Self {
             variables: HashMap::new(),
+            enclosing: None,
+            block: false,
         }
     }
 
-    pub fn resolve(&self, identifier: &String) -> &LiteralValue {
-        self.variables.get(identifier).unwrap_or(&LiteralValue::Nil)
+    pub fn new_enclosed(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            variables: HashMap::new(),
+            enclosing: Some(enclosing),
+            block: false,
+        }
+    }
+
+    /// Creates the scope of a block. Variables declared with `var` are local
+    /// to the block, but assigning to a name no scope defines yet binds it in
+    /// the nearest enclosing function (or global) scope instead, so that
+    /// `{ x = 1; } print x;` still prints 1.
+    pub fn new_block(enclosing: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            block: true,
+            ..Self::new_enclosed(enclosing)
+        }
+    }
+
+    pub fn resolve(&self, identifier: &str) -> Value {
+        self.resolve_symbol(Symbol::intern(identifier))
+    }
+
+    /// Updates the binding in the nearest scope which defines `identifier`, or
+    /// defines it in this scope if no scope does. Blocks defer new bindings to
+    /// their enclosing scope, see `new_block`.
+    pub fn assign(&mut self, identifier: &str, value: Value) {
+        self.assign_symbol(Symbol::intern(identifier), value);
+    }
+
+    /// Defines `identifier` in this scope, shadowing any enclosing binding.
+    pub fn define(&mut self, identifier: &str, value: Value) {
+        self.define_symbol(Symbol::intern(identifier), value);
+    }
+
+    /// Like `assign`, for an identifier which is already interned.
+    pub fn assign_symbol(&mut self, symbol: Symbol, value: Value) {
+        if let Some(value) = self.assign_existing(symbol, value) {
+            self.define_outside_blocks(symbol, value);
+        }
+    }
+
+    /// Like `define`, for an identifier which is already interned.
+    pub fn define_symbol(&mut self, symbol: Symbol, value: Value) {
+        self.variables.insert(symbol, value);
+    }
+
+    /// Removes `identifier` from this scope only, returning whether it was
+    /// defined here. Enclosing scopes are left untouched, so the name may
+    /// still resolve to an outer binding afterwards.
+    pub fn undefine(&mut self, identifier: &str) -> bool {
+        self.variables.remove(&Symbol::intern(identifier)).is_some()
+    }
+
+    /// Like `resolve`, for an identifier which is already interned.
+    pub fn resolve_symbol(&self, symbol: Symbol) -> Value {
+        match self.variables.get(&symbol) {
+            Some(value) => value.clone(),
+            None => match &self.enclosing {
+                Some(enclosing) => enclosing.borrow().resolve_symbol(symbol),
+                None => Value::Nil,
+            },
+        }
+    }
+
+    fn define_outside_blocks(&mut self, symbol: Symbol, value: Value) {
+        match &self.enclosing {
+            Some(enclosing) if self.block => {
+                enclosing.borrow_mut().define_outside_blocks(symbol, value)
+            }
+            _ => {
+                self.variables.insert(symbol, value);
+            }
+        }
+    }
+
+    /// Hands the value back if no scope defines `symbol`.
+    fn assign_existing(&mut self, symbol: Symbol, value: Value) -> Option<Value> {
+        if let Some(variable) = self.variables.get_mut(&symbol) {
+            *variable = value;
+            return None;
+        }
+
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow_mut().assign_existing(symbol, value),
+            None => Some(value),
+        }
+    }
+
+    /// How many scopes enclose this one, so the global scope has depth 0.
+    #[allow(dead_code)] // Complements `scope_report` for hosts inspecting scopes.
+    pub fn depth(&self) -> usize {
+        match &self.enclosing {
+            Some(enclosing) => enclosing.borrow().depth() + 1,
+            None => 0,
+        }
+    }
+
+    /// Lists the names defined in each scope, from this one out to the
+    /// global scope, for debugging shadowing and scoping problems. The names
+    /// in each scope are sorted.
+    pub fn scope_report(&self) -> Vec<Vec<String>> {
+        let mut names: Vec<String> = self
+            .variables
+            .keys()
+            .map(|symbol| symbol.name().to_string())
+            .collect();
+        names.sort();
+
+        let mut report = vec![names];
+        if let Some(enclosing) = &self.enclosing {
+            report.extend(enclosing.borrow().scope_report());
+        }
+        report
+    }
+
+    /// Copies every binding from `other` into this environment. When both
+    /// environments bind the same identifier, the value from `other` wins.
+    #[allow(dead_code)] // For hosts importing bindings; scripts can't import yet.
+    pub fn merge(&mut self, other: &Environment) {
+        for (symbol, value) in other.variables.iter() {
+            self.variables.insert(*symbol, value.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn merge_imports_bindings() {
+        let mut env = Environment::new();
+        env.assign("a", Value::Number(1.0));
+        let mut other = Environment::new();
+        other.assign("b", Value::Number(2.0));
+
+        env.merge(&other);
+
+        assert_eq!(env.resolve("a"), Value::Number(1.0));
+        assert_eq!(env.resolve("b"), Value::Number(2.0));
     }
 
-    pub fn assign(&mut self, identifier: &String, value: LiteralValue) {
-        self.variables.insert(identifier.to_string(), value);
+    #[test]
+    fn merge_overrides_conflicting_bindings() {
+        let mut env = Environment::new();
+        env.assign("a", Value::Number(1.0));
+        let mut other = Environment::new();
+        other.assign("a", Value::Number(2.0));
+
+        env.merge(&other);
+
+        assert_eq!(env.resolve("a"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn assign_updates_enclosing_binding() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().assign("a", Value::Number(1.0));
+        let mut local = Environment::new_enclosed(Rc::clone(&globals));
+
+        local.assign("a", Value::Number(2.0));
+        local.assign("b", Value::Number(3.0));
+
+        assert_eq!(globals.borrow().resolve("a"), Value::Number(2.0));
+        assert_eq!(globals.borrow().resolve("b"), Value::Nil);
+        assert_eq!(local.resolve("b"), Value::Number(3.0));
+    }
+
+    #[test]
+    fn define_shadows_enclosing_binding() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().assign("a", Value::Number(1.0));
+        let mut local = Environment::new_enclosed(Rc::clone(&globals));
+
+        local.define("a", Value::Number(2.0));
+
+        assert_eq!(local.resolve("a"), Value::Number(2.0));
+        assert_eq!(globals.borrow().resolve("a"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn undefine_removes_binding() {
+        let mut env = Environment::new();
+        env.assign("a", Value::Number(1.0));
+
+        assert!(env.undefine("a"));
+        assert!(!env.undefine("a"));
+        assert_eq!(env.resolve("a"), Value::Nil);
+    }
+
+    #[test]
+    fn undefine_only_affects_nearest_scope() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().assign("a", Value::Number(1.0));
+        let mut local = Environment::new_enclosed(Rc::clone(&globals));
+        local.define("a", Value::Number(2.0));
+
+        assert!(local.undefine("a"));
+        assert_eq!(local.resolve("a"), Value::Number(1.0));
+        assert!(!local.undefine("a"));
+        assert_eq!(local.resolve("a"), Value::Number(1.0));
+    }
+
+    #[test]
+    fn block_defers_new_bindings_to_enclosing_scope() {
+        let function = Rc::new(RefCell::new(Environment::new()));
+        let block = Rc::new(RefCell::new(Environment::new_block(Rc::clone(&function))));
+        let mut inner = Environment::new_block(Rc::clone(&block));
+
+        inner.assign("a", Value::Number(1.0));
+        inner.define("b", Value::Number(2.0));
+
+        assert_eq!(function.borrow().resolve("a"), Value::Number(1.0));
+        assert_eq!(block.borrow().resolve("b"), Value::Nil);
+        assert_eq!(inner.resolve("b"), Value::Number(2.0));
+    }
+
+    #[test]
+    fn scope_report_lists_names_per_scope() {
+        let globals = Rc::new(RefCell::new(Environment::new()));
+        globals.borrow_mut().define("x", Value::Number(1.0));
+        globals.borrow_mut().define("f", Value::Nil);
+        let outer = Rc::new(RefCell::new(Environment::new_block(Rc::clone(&globals))));
+        outer.borrow_mut().define("x", Value::Number(2.0));
+        let mut inner = Environment::new_block(Rc::clone(&outer));
+        inner.define("y", Value::Number(3.0));
+
+        assert_eq!(inner.depth(), 2);
+        assert_eq!(inner.scope_report(), [vec!["y"], vec!["x"], vec!["f", "x"]]);
+        assert_eq!(globals.borrow().depth(), 0);
     }
 }
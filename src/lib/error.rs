@@ -1 +1,234 @@
-pub type CompilerResult<T> = Result<T, String>;
+use std::fmt::{self, Display, Formatter};
+
+use super::interpreter::Flow;
+
+pub type CompilerError = String;
+pub type CompilerResult<T> = Result<T, CompilerError>;
+
+#[derive(Debug, PartialEq)]
+pub enum RuntimeError {
+    IterationLimit(usize),
+    /// Function calls nested more deeply than the interpreter allows, e.g.
+    /// because of runaway recursion.
+    CallDepthLimit(usize),
+    /// `nil` was used with `<`, `<=`, `>` or `>=`. It can only be compared
+    /// for (in)equality.
+    NilComparison,
+    /// A native function failed, e.g. because an argument was out of range.
+    Native(String),
+    /// A function was called with the wrong number of arguments.
+    Arity {
+        name: String,
+        expected: usize,
+        got: usize,
+    },
+    /// A call named an argument which the function has no parameter for.
+    UnexpectedArgument {
+        name: String,
+        argument: String,
+    },
+    /// A call passed an argument both by position and by name.
+    DuplicateArgument {
+        name: String,
+        argument: String,
+    },
+    /// A native was passed an argument of the wrong type. `position` counts
+    /// from 1.
+    TypeMismatch {
+        name: String,
+        position: usize,
+        expected: &'static str,
+        got: &'static str,
+    },
+    /// A file native failed to read or write `path`.
+    Io {
+        action: &'static str,
+        path: String,
+        message: String,
+    },
+    /// A native needed a capability the interpreter doesn't allow, e.g. file
+    /// access in sandbox mode.
+    CapabilityDenied {
+        name: String,
+        capability: &'static str,
+    },
+    /// A function with optional or rest parameters was called with too few or
+    /// too many arguments. A function with a rest parameter has no `max`.
+    ArityRange {
+        name: String,
+        min: usize,
+        max: Option<usize>,
+        got: usize,
+    },
+    /// Not a failure as such: the script called `exit(code)`, which unwinds
+    /// all the way up to whoever is running the interpreter.
+    Exit(i32),
+    /// Not a failure either: a `return`, `break` or `continue` inside a block
+    /// expression, unwinding through the expressions around it to the
+    /// statement which contains them, where it's turned back into control
+    /// flow before it escapes. Holds the flow the block ended with.
+    Unwind(Flow),
+    /// A value which isn't a function was called, e.g. `5()`. Holds the
+    /// value's type.
+    NotCallable(&'static str),
+    /// A binary operator was applied to operands of types it doesn't support,
+    /// e.g. `true - 1`.
+    InvalidOperands {
+        operation: &'static str,
+        left: &'static str,
+        right: &'static str,
+    },
+    /// A unary operator was applied to an operand of a type it doesn't
+    /// support, e.g. `-"a"`.
+    InvalidOperand {
+        operation: &'static str,
+        operand: &'static str,
+    },
+    /// A property was read from a value which doesn't have properties.
+    NoProperty {
+        type_name: &'static str,
+        name: String,
+    },
+    /// A value was indexed by something it can't be indexed by, e.g. `[1]["a"]`.
+    InvalidIndex {
+        target: &'static str,
+        index: &'static str,
+    },
+    /// A list or tuple was indexed by a fractional number.
+    NonIntegerIndex {
+        kind: &'static str,
+        index: f32,
+    },
+    IndexOutOfBounds {
+        kind: &'static str,
+        index: f32,
+        length: usize,
+    },
+    /// Repeating a string, with `*` or `repeat`, would have built a string
+    /// longer than `max` bytes.
+    StringTooLong {
+        max: usize,
+    },
+    /// Destructuring was given something other than a tuple with one value
+    /// per variable.
+    Destructure {
+        value: String,
+        expected: usize,
+    },
+    /// `break` or `continue` was used outside of a loop, or with a label no
+    /// enclosing loop has. Loops can't be broken out of from inside a
+    /// function.
+    NoEnclosingLoop {
+        keyword: &'static str,
+        label: Option<String>,
+    },
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            RuntimeError::IterationLimit(limit) => {
+                write!(f, "Loop exceeded the limit of {limit} iterations")
+            }
+            RuntimeError::CallDepthLimit(limit) => {
+                write!(f, "Calls exceeded the limit of {limit} nested calls")
+            }
+            RuntimeError::NilComparison => {
+                write!(f, "nil can only be compared with == and !=")
+            }
+            RuntimeError::Native(message) => write!(f, "{message}"),
+            RuntimeError::Arity {
+                name,
+                expected,
+                got,
+            } => write!(f, "{name} expected {expected} arguments but got {got}"),
+            RuntimeError::UnexpectedArgument { name, argument } => {
+                write!(f, "{name} has no parameter named {argument}")
+            }
+            RuntimeError::DuplicateArgument { name, argument } => {
+                write!(f, "{name} got more than one value for {argument}")
+            }
+            RuntimeError::TypeMismatch {
+                name,
+                position,
+                expected,
+                got,
+            } => write!(
+                f,
+                "{name} expected {expected} for argument {position} but got {got}"
+            ),
+            RuntimeError::Io {
+                action,
+                path,
+                message,
+            } => write!(f, "Unable to {action} {path}: {message}"),
+            RuntimeError::CapabilityDenied { name, capability } => {
+                write!(
+                    f,
+                    "{name} requires {capability} access, which is not allowed"
+                )
+            }
+            RuntimeError::ArityRange {
+                name,
+                min,
+                max: Some(max),
+                got,
+            } => write!(f, "{name} expected {min} to {max} arguments but got {got}"),
+            RuntimeError::ArityRange {
+                name,
+                min,
+                max: None,
+                got,
+            } => write!(f, "{name} expected at least {min} arguments but got {got}"),
+            RuntimeError::Exit(code) => write!(f, "Exited with code {code}"),
+            RuntimeError::Unwind(_) => write!(f, "Control flow escaped a block expression"),
+            RuntimeError::NotCallable(type_name) => {
+                write!(f, "Cannot call a value of type {type_name}")
+            }
+            RuntimeError::InvalidOperands {
+                operation,
+                left,
+                right,
+            } => write!(f, "Cannot {operation} {left} and {right}"),
+            RuntimeError::InvalidOperand { operation, operand } => {
+                write!(f, "Cannot {operation} {operand}")
+            }
+            RuntimeError::NoProperty { type_name, name } => {
+                write!(f, "{type_name} has no property {name}")
+            }
+            RuntimeError::InvalidIndex { target, index } => {
+                write!(f, "Cannot index {target} with {index}")
+            }
+            RuntimeError::NonIntegerIndex { kind, index } => {
+                write!(f, "{kind} index must be an integer, got {index}")
+            }
+            RuntimeError::IndexOutOfBounds {
+                kind,
+                index,
+                length,
+            } => write!(
+                f,
+                "Index {index} is out of bounds for {kind} of length {length}"
+            ),
+            RuntimeError::StringTooLong { max } => {
+                write!(f, "Cannot build a string longer than {max} bytes")
+            }
+            RuntimeError::Destructure { value, expected } => {
+                write!(f, "Cannot destructure {value} into {expected} variables")
+            }
+            RuntimeError::NoEnclosingLoop {
+                keyword,
+                label: None,
+            } => write!(f, "Cannot {keyword} outside of a loop"),
+            RuntimeError::NoEnclosingLoop {
+                keyword,
+                label: Some(label),
+            } => write!(
+                f,
+                "Cannot {keyword} {label} outside of a loop labeled {label}"
+            ),
+        }
+    }
+}
+
+pub type RuntimeResult<T> = Result<T, RuntimeError>;
@@ -0,0 +1,376 @@
+//! Reprints source code with a consistent layout: one statement per line,
+//! blocks indented by four spaces and operators surrounded by spaces.
+//! Comments are kept, as are single blank lines between statements.
+//!
+//! The formatter works on tokens scanned with trivia preserved rather than on
+//! the parse tree, since the parser drops comments and desugars `for` loops.
+
+use super::{
+    error::CompilerResult,
+    scanner::{Keyword, Scanner, Token, TokenDirection, BYTE_ORDER_MARK},
+};
+
+const INDENT: &str = "    ";
+
+/// Formats `source`, failing only if it can't be scanned. Each token is
+/// reprinted exactly as it was written, so literals keep their spelling.
+pub fn format_source(source: &str) -> CompilerResult<String> {
+    let mut scanner = Scanner::from_source(source).with_preserve_trivia(true);
+    let tokens = scanner.scan_tokens()?;
+    // The scanner skips a leading byte order mark, so its spans start after it.
+    let source = source.strip_prefix(BYTE_ORDER_MARK).unwrap_or(source);
+    let chars: Vec<char> = source.chars().collect();
+
+    let items: Vec<(&Token, String)> = (0..tokens.len())
+        .filter_map(|index| {
+            let token = tokens.get(index)?;
+            let span = tokens.span(index)?;
+            Some((token, chars[span.start..span.end].iter().collect()))
+        })
+        .collect();
+
+    let mut formatter = Formatter::new();
+    for (index, (token, text)) in items.iter().enumerate() {
+        let upcoming = items[index + 1..]
+            .iter()
+            .map(|(token, _)| *token)
+            .filter(|token| !token.is_trivia());
+        formatter.token(token, text, upcoming);
+    }
+
+    Ok(formatter.finish())
+}
+
+/// An open brace or the top level of the program. Only blocks are laid out
+/// over several lines; map literals and empty braces stay inline.
+struct Frame {
+    block: bool,
+    /// The body of a `do` loop, whose closing brace is followed by `while`.
+    do_body: bool,
+    /// How many parentheses and brackets are open within the frame, so that
+    /// the semicolons in a `for` loop's header don't end the line.
+    nesting: usize,
+}
+
+struct Formatter<'a> {
+    output: String,
+    frames: Vec<Frame>,
+    previous: Option<&'a Token>,
+    /// Whether the previous token was a sign, e.g. the `-` in `-1`.
+    sign: bool,
+    /// Whether the previous closing brace ended a block.
+    closed_block: bool,
+    do_body_closed: bool,
+    /// Newlines seen in the source since the previous token or comment.
+    newlines: usize,
+    line_pending: bool,
+}
+
+impl<'a> Formatter<'a> {
+    fn new() -> Self {
+        Self {
+            output: String::new(),
+            frames: vec![Frame {
+                block: true,
+                do_body: false,
+                nesting: 0,
+            }],
+            previous: None,
+            sign: false,
+            closed_block: false,
+            do_body_closed: false,
+            newlines: 0,
+            line_pending: true,
+        }
+    }
+
+    fn token(
+        &mut self,
+        token: &'a Token,
+        text: &str,
+        mut upcoming: impl Iterator<Item = &'a Token>,
+    ) {
+        match token {
+            Token::Whitespace => {
+                self.newlines += text.matches('\n').count();
+                return;
+            }
+            Token::Comment(_) => {
+                self.comment(text);
+                return;
+            }
+            _ => {}
+        }
+
+        if *token == Token::Brace(TokenDirection::Right) && self.frames.len() > 1 {
+            let frame = self.frames.pop().expect("Frames should not be empty");
+            self.closed_block = frame.block;
+            self.do_body_closed = frame.do_body;
+            if frame.block {
+                self.line_pending = true;
+            }
+        } else if self.line_pending && self.continues_after_block(token) {
+            self.line_pending = false;
+        }
+
+        if self.line_pending {
+            let closing = *token == Token::Brace(TokenDirection::Right);
+            self.start_line(!closing);
+        } else if self.needs_space(token) {
+            self.output.push(' ');
+        }
+        self.output.push_str(text);
+
+        let frame = self.frames.last_mut().expect("Frames should not be empty");
+        match token {
+            Token::SemiColon if frame.nesting == 0 => self.line_pending = true,
+            Token::Brace(TokenDirection::Right) if self.closed_block => self.line_pending = true,
+            Token::Paren(TokenDirection::Left) | Token::Bracket(TokenDirection::Left) => {
+                frame.nesting += 1;
+            }
+            Token::Paren(TokenDirection::Right) | Token::Bracket(TokenDirection::Right) => {
+                frame.nesting = frame.nesting.saturating_sub(1);
+            }
+            Token::Brace(TokenDirection::Left) => {
+                let first = upcoming.next();
+                let inline = matches!(first, Some(Token::Brace(TokenDirection::Right)) | None)
+                    || (starts_expression(self.previous)
+                        && matches!(first, Some(Token::Identifier(_) | Token::String(_)))
+                        && matches!(upcoming.next(), Some(Token::Colon)));
+                self.frames.push(Frame {
+                    block: !inline,
+                    do_body: self.previous == Some(&Token::Keyword(Keyword::Do)),
+                    nesting: 0,
+                });
+                self.line_pending = !inline;
+            }
+            _ => {}
+        }
+
+        if *token != Token::Brace(TokenDirection::Right) {
+            self.closed_block = false;
+        }
+        self.sign =
+            matches!(token, Token::Minus | Token::Plus) && !self.previous.is_some_and(ends_value);
+        self.previous = Some(token);
+        self.newlines = 0;
+    }
+
+    /// Whether `token` belongs on the same line as the block which was just
+    /// closed, e.g. `} else {`, `} while x;` or `});`.
+    fn continues_after_block(&self, token: &Token) -> bool {
+        if !self.closed_block {
+            return false;
+        }
+        match token {
+            Token::Keyword(Keyword::Else) => true,
+            Token::Keyword(Keyword::While) => self.do_body_closed,
+            Token::SemiColon
+            | Token::Comma
+            | Token::Dot
+            | Token::QuestionDot
+            | Token::Paren(TokenDirection::Right)
+            | Token::Bracket(TokenDirection::Right) => true,
+            _ => false,
+        }
+    }
+
+    /// Keeps a comment on its own line if it was on one in the source, and
+    /// otherwise at the end of the line it followed.
+    fn comment(&mut self, text: &str) {
+        let own_line = self.newlines > 0 || self.output.is_empty();
+        if own_line {
+            self.start_line(true);
+        } else {
+            self.output.push(' ');
+        }
+        self.output.push_str(text);
+        // A block comment on a line of its own is laid out like a line comment.
+        self.line_pending |= own_line || text.starts_with("//");
+        self.newlines = 0;
+    }
+
+    /// Starts a new, indented line, keeping a blank line from the source if
+    /// `blank_line` allows it. Blocks never start with a blank line.
+    fn start_line(&mut self, blank_line: bool) {
+        if !self.output.is_empty() {
+            self.output.push('\n');
+            if blank_line && self.newlines > 1 && !self.output.ends_with("{\n") {
+                self.output.push('\n');
+            }
+        }
+        let depth = self.frames.iter().filter(|frame| frame.block).count() - 1;
+        self.output.push_str(&INDENT.repeat(depth));
+        self.line_pending = false;
+    }
+
+    fn needs_space(&self, token: &Token) -> bool {
+        let Some(previous) = self.previous else {
+            return false;
+        };
+        let inline_brace = self.frames.last().is_some_and(|frame| !frame.block);
+        match previous {
+            Token::Paren(TokenDirection::Left)
+            | Token::Bracket(TokenDirection::Left)
+            | Token::Dot
+            | Token::QuestionDot
+            | Token::Ellipsis
+            | Token::At
+            | Token::Bang => return false,
+            Token::Brace(TokenDirection::Left) if inline_brace => return false,
+            Token::Minus | Token::Plus if self.sign => return false,
+            _ => {}
+        }
+        match token {
+            Token::SemiColon
+            | Token::Comma
+            | Token::Colon
+            | Token::Dot
+            | Token::QuestionDot
+            | Token::Paren(TokenDirection::Right)
+            | Token::Bracket(TokenDirection::Right) => false,
+            Token::Brace(TokenDirection::Right) => false,
+            Token::Paren(TokenDirection::Left) => {
+                !ends_value(previous)
+                    && !matches!(
+                        previous,
+                        Token::Keyword(Keyword::Function | Keyword::Super | Keyword::This)
+                    )
+            }
+            Token::Bracket(TokenDirection::Left) => !ends_value(previous),
+            _ => true,
+        }
+    }
+
+    fn finish(mut self) -> String {
+        let trimmed = self.output.trim_end().len();
+        self.output.truncate(trimmed);
+        if !self.output.is_empty() {
+            self.output.push('\n');
+        }
+        self.output
+    }
+}
+
+/// Whether a brace after `previous` would be in the position of an
+/// expression, where it could start a map literal.
+fn starts_expression(previous: Option<&Token>) -> bool {
+    match previous {
+        None => false,
+        Some(token) => {
+            !ends_value(token)
+                && !matches!(
+                    token,
+                    Token::SemiColon
+                        | Token::Brace(_)
+                        | Token::Keyword(Keyword::Else | Keyword::Do)
+                )
+        }
+    }
+}
+
+/// Whether `token` can be the last token of an operand, so that a following
+/// `(` or `[` is a call or index rather than a grouping or list.
+fn ends_value(token: &Token) -> bool {
+    matches!(
+        token,
+        Token::Identifier(_)
+            | Token::Number(_)
+            | Token::String(_)
+            | Token::Interpolation(_)
+            | Token::Paren(TokenDirection::Right)
+            | Token::Bracket(TokenDirection::Right)
+            | Token::Brace(TokenDirection::Right)
+            | Token::Keyword(Keyword::True | Keyword::False | Keyword::Nil | Keyword::This)
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn formats_messy_program() {
+        let source = "fun   add(a,b){return a+b;}
+var x=-1;if x<2{print add(x ,2);}else{print{a:1,\"b\":[1,2]};}
+
+
+for(var i=0;i<3;i=i+1)print i;";
+
+        let formatted = format_source(source).expect("Source should format");
+
+        assert_eq!(
+            formatted,
+            "fun add(a, b) {
+    return a + b;
+}
+var x = -1;
+if x < 2 {
+    print add(x, 2);
+} else {
+    print {a: 1, \"b\": [1, 2]};
+}
+
+for (var i = 0; i < 3; i = i + 1) print i;
+"
+        );
+    }
+
+    #[test]
+    fn keeps_comments() {
+        let source = "// setup\nx = 1; // one\n{ /* inner */ y = x-1;\n\n  // last\n}";
+
+        let formatted = format_source(source).expect("Source should format");
+
+        assert_eq!(
+            formatted,
+            "// setup
+x = 1; // one
+{ /* inner */
+    y = x - 1;
+
+    // last
+}
+"
+        );
+    }
+
+    #[test]
+    fn formats_calls_lambdas_and_loops() {
+        let source = "xs = map([1,2], fun(x){return x*2;});do{x=x-1;}while x>0;f(a:-1,...);";
+
+        let formatted = format_source(source).expect("Source should format");
+
+        assert_eq!(
+            formatted,
+            "xs = map([1, 2], fun(x) {
+    return x * 2;
+});
+do {
+    x = x - 1;
+} while x > 0;
+f(a: -1, ...);
+"
+        );
+    }
+
+    #[test]
+    fn formatting_is_idempotent() {
+        let source = "fun f(a, b = 1, ...rest) {\n    return {value: a};\n}\nprint f(1)[\"value\"], !true, f(2)?.value;\n";
+
+        assert_eq!(format_source(source).unwrap(), source);
+    }
+
+    #[test]
+    fn drops_byte_order_mark() {
+        assert_eq!(format_source("\u{feff}print 1;").unwrap(), "print 1;\n");
+    }
+
+    #[test]
+    fn scanner_errors_are_returned() {
+        assert_eq!(
+            format_source("print \"oops;"),
+            Err("Unterminated string starting on line 1".to_string())
+        );
+    }
+}
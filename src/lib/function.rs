@@ -0,0 +1,54 @@
+use std::{
+    cell::RefCell,
+    cmp::Ordering,
+    fmt::{self, Debug, Formatter},
+    rc::Rc,
+};
+
+use super::{environment::Environment, parser::Function};
+
+/// A function declared in a script, along with the scope it was declared in so
+/// that its body can see the variables which surrounded it.
+#[derive(Clone)]
+pub struct UserFunction {
+    pub declaration: Rc<Function>,
+    pub closure: Rc<RefCell<Environment>>,
+}
+
+impl UserFunction {
+    pub fn new(declaration: Rc<Function>, closure: Rc<RefCell<Environment>>) -> Self {
+        Self {
+            declaration,
+            closure,
+        }
+    }
+
+    pub fn name(&self) -> &str {
+        &self.declaration.name
+    }
+
+    pub fn arity(&self) -> usize {
+        self.declaration.parameters.len()
+    }
+}
+
+impl Debug for UserFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "Function({})", self.name())
+    }
+}
+
+// Functions are only equal to themselves, i.e. the same declaration closed
+// over the same scope.
+impl PartialEq for UserFunction {
+    fn eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.declaration, &other.declaration)
+            && Rc::ptr_eq(&self.closure, &other.closure)
+    }
+}
+
+impl PartialOrd for UserFunction {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        None
+    }
+}
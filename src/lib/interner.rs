@@ -0,0 +1,75 @@
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    rc::Rc,
+};
+
+/// A cheap handle to an interned identifier. Symbols interned from equal
+/// strings are equal, so scopes can be keyed by symbols and compared without
+/// hashing the identifier again at every level.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Symbol(u32);
+
+#[derive(Default)]
+struct Interner {
+    symbols: HashMap<Rc<str>, Symbol>,
+    names: Vec<Rc<str>>,
+}
+
+thread_local! {
+    static INTERNER: RefCell<Interner> = RefCell::new(Interner::default());
+}
+
+impl Symbol {
+    /// Returns the symbol for `name`, interning it the first time it is seen.
+    /// Interned names live for as long as the thread does.
+    pub fn intern(name: &str) -> Self {
+        INTERNER.with(|interner| {
+            let mut interner = interner.borrow_mut();
+            if let Some(symbol) = interner.symbols.get(name) {
+                return *symbol;
+            }
+
+            let symbol = Symbol(interner.names.len() as u32);
+            let name: Rc<str> = Rc::from(name);
+            interner.names.push(Rc::clone(&name));
+            interner.symbols.insert(name, symbol);
+            symbol
+        })
+    }
+
+    pub fn name(self) -> Rc<str> {
+        INTERNER.with(|interner| Rc::clone(&interner.borrow().names[self.0 as usize]))
+    }
+}
+
+// Symbols show as the name they stand for, so that dumps of the syntax tree
+// stay readable.
+impl Debug for Symbol {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}", self.name())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn equal_names_intern_to_equal_symbols() {
+        let first = Symbol::intern("count");
+        let second = Symbol::intern(&String::from("count"));
+
+        assert_eq!(first, second);
+        assert_ne!(first, Symbol::intern("total"));
+    }
+
+    #[test]
+    fn symbols_remember_their_name() {
+        let symbol = Symbol::intern("remembered");
+
+        assert_eq!(&*symbol.name(), "remembered");
+        assert_eq!(format!("{symbol:?}"), "\"remembered\"");
+    }
+}
@@ -1,302 +1,3191 @@
+use std::{
+    borrow::Cow,
+    cell::RefCell,
+    env,
+    io::{self, Write},
+    rc::Rc,
+};
+
 use super::{
     environment::Environment,
-    parser::{Declaration, Expression, LiteralValue, Operator, Program, Statement},
+    error::{RuntimeError, RuntimeResult},
+    function::UserFunction,
+    interner::Symbol,
+    map::Map,
+    natives::{
+        self, define_natives, Caller, Capability, NativeBody, NativeFn, NativeFunction, Parameters,
+    },
+    parser::{
+        BinaryOperator, Block, ConditionalBranch, Declaration, Expression, Function, LiteralValue,
+        Program, Statement, UnaryOperator,
+    },
+    scanner::Excerpt,
+    value::Value,
+    visitor::Visitor,
 };
 
+/// Controls which values are considered falsy by conditions and `!`.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum TruthinessMode {
+    /// Only `nil` and `false` are falsy, as in Lox.
+    #[allow(dead_code)] // Not selectable from the command line yet.
+    Lox,
+    /// `nil`, `false`, `0` and `""` are falsy.
+    #[default]
+    Loose,
+}
+
+/// The values a function is called with.
+#[derive(Debug, Default, PartialEq)]
+pub struct Arguments {
+    positional: Vec<Value>,
+    /// `name: value` arguments, in the order they were written.
+    named: Vec<(String, Value)>,
+}
+
+impl From<Vec<Value>> for Arguments {
+    fn from(positional: Vec<Value>) -> Self {
+        Self {
+            positional,
+            named: vec![],
+        }
+    }
+}
+
+/// How control leaves a declaration once it has been evaluated.
+#[derive(Debug, PartialEq)]
+pub enum Flow {
+    /// Carry on with the next declaration.
+    Normal,
+    /// Unwind to the nearest function call, which evaluates to the value.
+    Return(Value),
+    /// Unwind to the innermost loop, or the loop with the label, and exit it.
+    Break(Option<String>),
+    /// Unwind to the innermost loop, or the loop with the label, and start
+    /// its next iteration.
+    Continue(Option<String>),
+    /// Unwind to the nearest function call, which calls the function with the
+    /// arguments in its place. See `Interpreter::with_tail_calls`.
+    TailCall(UserFunction, Arguments),
+}
+
+impl Flow {
+    /// The value of a function body which finished with this flow. Loops
+    /// can't be broken out of or continued from inside one. Tail calls must
+    /// be made by the caller.
+    fn into_value(self) -> RuntimeResult<Value> {
+        match self {
+            Flow::Normal => Ok(Value::Nil),
+            Flow::Return(value) => Ok(value),
+            Flow::TailCall(..) => unreachable!("Tail calls should be made by the caller"),
+            Flow::Break(label) => Err(RuntimeError::NoEnclosingLoop {
+                keyword: "break",
+                label,
+            }),
+            Flow::Continue(label) => Err(RuntimeError::NoEnclosingLoop {
+                keyword: "continue",
+                label,
+            }),
+        }
+    }
+}
+
+/// Whether a `break` or `continue` with the target label applies to a loop
+/// with the given label. Unlabeled jumps apply to the innermost loop.
+fn targets_loop(target: &Option<String>, label: &Option<String>) -> bool {
+    target.is_none() || target == label
+}
+
+/// Turns `RuntimeError::Unwind` back into the flow the block expression ended
+/// with, so that it carries on from the statement containing it.
+fn resume_flow(error: RuntimeError) -> RuntimeResult<Flow> {
+    match error {
+        RuntimeError::Unwind(flow) => Ok(flow),
+        error => Err(error),
+    }
+}
+
+/// Looks up the value of an environment variable by name.
+pub type EnvVars = Box<dyn Fn(&str) -> Option<String>>;
+
 pub struct Interpreter {
-    environment: Environment,
+    environment: Rc<RefCell<Environment>>,
+    output: Box<dyn Write>,
+    error_output: Box<dyn Write>,
+    env_vars: EnvVars,
+    file_access: bool,
+    sandbox: bool,
+    warnings: bool,
+    truthiness_mode: TruthinessMode,
+    precision: Option<usize>,
+    max_iterations: Option<usize>,
+    max_call_depth: Option<usize>,
+    trace: bool,
+    trace_output: Box<dyn Write>,
+    echo_expressions: bool,
+    tail_calls: bool,
+    call_depth: usize,
+    failed_declaration: Option<String>,
+    returned: bool,
 }
 
 impl Interpreter {
-    pub fn new(environment: Environment) -> Self {
+    pub fn new(mut environment: Environment) -> Self {
+        define_natives(&mut environment);
+
         Self {
-            environment,
+            environment: Rc::new(RefCell::new(environment)),
+            output: Box::new(io::stdout()),
+            error_output: Box::new(io::stderr()),
+            env_vars: Box::new(|name| env::var(name).ok()),
+            file_access: true,
+            sandbox: false,
+            warnings: false,
+            truthiness_mode: TruthinessMode::default(),
+            precision: None,
+            max_iterations: None,
+            max_call_depth: None,
+            trace: false,
+            trace_output: Box::new(io::stderr()),
+            echo_expressions: false,
+            tail_calls: true,
+            call_depth: 0,
+            failed_declaration: None,
+            returned: false,
+        }
+    }
+
+    /// Sets where printed output is written to. Defaults to stdout.
+    #[allow(dead_code)] // The CLI always prints to stdout.
+    pub fn with_output(mut self, output: Box<dyn Write>) -> Self {
+        self.output = output;
+        self
+    }
+
+    /// Sets where `eprint` writes to. Defaults to stderr.
+    #[allow(dead_code)] // The CLI leaves `eprint` writing to stderr.
+    pub fn with_error_output(mut self, error_output: Box<dyn Write>) -> Self {
+        self.error_output = error_output;
+        self
+    }
+
+    /// Sets where `env(name)` looks up environment variables. Defaults to the
+    /// process's environment.
+    #[allow(dead_code)] // The CLI always reads the process's environment.
+    pub fn with_env_vars(mut self, env_vars: EnvVars) -> Self {
+        self.env_vars = env_vars;
+        self
+    }
+
+    /// Controls whether `read_file` and `write_file` may be used. Enabled by
+    /// default.
+    #[allow(dead_code)] // The CLI only denies file access as part of `--sandbox`.
+    pub fn with_file_access(mut self, file_access: bool) -> Self {
+        self.file_access = file_access;
+        self
+    }
+
+    /// Sandbox mode is for running untrusted scripts: natives which access
+    /// files, environment variables or the process, such as `read_file`,
+    /// `env` and `exit`, fail with `RuntimeError::CapabilityDenied` instead.
+    pub fn with_sandbox(mut self, sandbox: bool) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Warns on the error output about expression statements, such as
+    /// `1 + 2;`, whose value is discarded without having had any effect.
+    /// Statements which call a function are assumed to have an effect.
+    pub fn with_warnings(mut self, warnings: bool) -> Self {
+        self.warnings = warnings;
+        self
+    }
+
+    #[allow(dead_code)] // No command line flag selects a truthiness mode yet.
+    pub fn with_truthiness_mode(mut self, truthiness_mode: TruthinessMode) -> Self {
+        self.truthiness_mode = truthiness_mode;
+        self
+    }
+
+    /// Sets the number of decimal places numbers are printed with. By default
+    /// numbers are printed in their shortest form.
+    pub fn with_precision(mut self, precision: usize) -> Self {
+        self.precision = Some(precision);
+        self
+    }
+
+    /// Limits the number of iterations any single loop may run for before
+    /// raising `RuntimeError::IterationLimit`. Loops are unlimited by default.
+    pub fn with_max_iterations(mut self, max_iterations: usize) -> Self {
+        self.max_iterations = Some(max_iterations);
+        self
+    }
+
+    /// Limits how deeply function calls may nest before raising
+    /// `RuntimeError::CallDepthLimit`, rather than overflowing the stack. Tail
+    /// calls don't nest. Calls are unlimited by default.
+    pub fn with_max_call_depth(mut self, max_call_depth: usize) -> Self {
+        self.max_call_depth = Some(max_call_depth);
+        self
+    }
+
+    /// Logs each declaration before it runs, and the value of each expression
+    /// statement after it runs, to stderr or the trace output.
+    pub fn with_trace(mut self, trace: bool) -> Self {
+        self.trace = trace;
+        self
+    }
+
+    #[allow(dead_code)] // The CLI traces to stderr.
+    pub fn with_trace_output(mut self, trace_output: Box<dyn Write>) -> Self {
+        self.trace_output = trace_output;
+        self
+    }
+
+    /// Prints the value of each expression statement, as a REPL does. Otherwise
+    /// expression statements are only evaluated for their side effects.
+    pub fn with_echo_expressions(mut self, echo_expressions: bool) -> Self {
+        self.echo_expressions = echo_expressions;
+        self
+    }
+
+    /// Controls whether `return f(...);` inside a function replaces the current
+    /// call with the call to `f` rather than nesting it, so that tail
+    /// recursion runs in constant stack space. Enabled by default.
+    #[allow(dead_code)] // Tail calls can't be turned off from the command line.
+    pub fn with_tail_calls(mut self, tail_calls: bool) -> Self {
+        self.tail_calls = tail_calls;
+        self
+    }
+
+    /// Exposes a Rust function to scripts as the global `name`, replacing any
+    /// existing binding. Calls with other than `arity` arguments fail before
+    /// `function` is called.
+    #[allow(dead_code)] // For hosts embedding the interpreter; the CLI has none to add.
+    pub fn register_native(&mut self, name: &str, arity: usize, function: NativeFn) {
+        let native = NativeFunction {
+            name: Cow::Owned(name.to_string()),
+            parameters: Parameters::Count(arity),
+            function: NativeBody::Pure(function),
+        };
+        self.environment
+            .borrow_mut()
+            .assign(name, Value::NativeFunction(native));
+    }
+
+    /// Removes a binding from the current scope, returning whether it existed.
+    pub fn undefine(&mut self, identifier: &str) -> bool {
+        self.environment.borrow_mut().undefine(identifier)
+    }
+
+    pub fn run(&mut self, program: &Program) -> RuntimeResult<()> {
+        self.failed_declaration = None;
+        self.returned = false;
+        // A top level `return` simply stops the program.
+        let flow = self.evaluate_declarations(program.get_declarations(), program.excerpts())?;
+        self.returned = matches!(flow, Flow::Return(_));
+        flow.into_value()?;
+        Ok(())
+    }
+
+    /// Whether the last `run` was stopped by a top level `return`, so that
+    /// the REPL can skip the rest of the input.
+    pub fn returned(&self) -> bool {
+        self.returned
+    }
+
+    /// Runs `program` as a script: once its top level has run, a `main`
+    /// function it defined is called, with `ARGS` if `main` takes a parameter.
+    /// Scripts without a `main` run exactly as with `run`.
+    pub fn run_script(&mut self, program: &Program) -> RuntimeResult<()> {
+        self.failed_declaration = None;
+        match self.evaluate_declarations(program.get_declarations(), program.excerpts())? {
+            Flow::Return(_) => return Ok(()),
+            flow => flow.into_value()?,
+        };
+
+        let Value::Function(main) = self.environment.borrow().resolve("main") else {
+            return Ok(());
+        };
+        let arguments = match main.arity() {
+            0 => vec![],
+            _ => vec![self.environment.borrow().resolve("ARGS")],
+        };
+        self.call_function(&main, arguments.into())?;
+        Ok(())
+    }
+
+    #[allow(dead_code)] // For hosts which want the value of the last expression.
+    pub fn run_capturing(&mut self, program: &Program) -> RuntimeResult<Option<Value>> {
+        self.failed_declaration = None;
+        let Some((last, rest)) = program.get_declarations().split_last() else {
+            return Ok(None);
+        };
+        match self.evaluate_declarations(rest, program.excerpts())? {
+            Flow::Return(_) => return Ok(None),
+            flow => flow.into_value()?,
+        };
+
+        match last {
+            Declaration::Statement(Statement::Expression(expression)) => {
+                match self.evaluate_expression(expression) {
+                    Ok(value) => Ok(Some(value)),
+                    Err(error) => match resume_flow(error)? {
+                        Flow::Return(_) => Ok(None),
+                        flow => flow.into_value().map(|_| None),
+                    },
+                }
+            }
+            _ => {
+                let excerpt = program.excerpts().get(rest.len()).and_then(Option::as_ref);
+                self.evaluate_declaration(last, excerpt)?;
+                Ok(None)
+            }
+        }
+    }
+
+    /// Evaluates the declarations in order, quoting them by their excerpts
+    /// when they fail.
+    fn evaluate_declarations(
+        &mut self,
+        declarations: &[Declaration],
+        excerpts: &[Option<Excerpt>],
+    ) -> RuntimeResult<Flow> {
+        for (index, declaration) in declarations.iter().enumerate() {
+            let excerpt = excerpts.get(index).and_then(Option::as_ref);
+            match self.evaluate_declaration(declaration, excerpt)? {
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
         }
+
+        Ok(Flow::Normal)
     }
 
-    pub fn run(&mut self, program: &Program) {
-        self.evaluate_declarations(program.get_declarations());
+    /// Evaluates the declarations with `environment` as the current scope,
+    /// restoring the previous scope afterwards, even if evaluation fails.
+    fn evaluate_declarations_in(
+        &mut self,
+        block: &Block,
+        environment: Environment,
+    ) -> RuntimeResult<Flow> {
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = self.evaluate_declarations(block.get_declarations(), block.excerpts());
+        self.environment = previous;
+        result
     }
 
-    pub fn evaluate_declarations(&mut self, declarations: &Vec<Declaration>) {
-        for declaration in declarations.iter() {
-            self.evaluate_declaration(declaration);
+    /// The source of the innermost declaration which failed during the last
+    /// run, e.g. `print a - b;`, for giving errors some context.
+    pub fn failed_declaration(&self) -> Option<&str> {
+        self.failed_declaration.as_deref()
+    }
+
+    /// Evaluates the declaration, which was written as `excerpt`. Declarations
+    /// nested directly in it, such as the body of an `if` without braces,
+    /// share its excerpt.
+    fn evaluate_declaration(
+        &mut self,
+        declaration: &Declaration,
+        excerpt: Option<&Excerpt>,
+    ) -> RuntimeResult<Flow> {
+        // Blocks are not traced themselves since each of their declarations is.
+        if self.trace && !matches!(declaration, Declaration::Block(_)) {
+            writeln!(self.trace_output, "trace: {declaration:?}").expect("Failed to write trace");
+        }
+
+        let result = self
+            .evaluate_declaration_inner(declaration, excerpt)
+            .or_else(resume_flow);
+        // The innermost declaration fails first, and the ones around it don't
+        // replace it as the error unwinds through them. `exit` isn't a failure.
+        match &result {
+            Err(error) => {
+                if self.failed_declaration.is_none() && !matches!(error, RuntimeError::Exit(_)) {
+                    self.failed_declaration = excerpt.map(Excerpt::text);
+                }
+            }
+            Ok(_) => self.warn_if_without_effect(declaration, excerpt),
+        }
+        result
+    }
+
+    /// Warns, if enabled, that `declaration` is an expression statement whose
+    /// value was thrown away without having had any effect. The declaration
+    /// is quoted as it was written.
+    fn warn_if_without_effect(&mut self, declaration: &Declaration, excerpt: Option<&Excerpt>) {
+        if !self.warnings || self.echo_expressions {
+            return;
+        }
+        if let Declaration::Statement(Statement::Expression(expression)) = declaration {
+            if !expression.has_side_effects() {
+                let warning = "warning: expression statement has no effect";
+                match excerpt {
+                    Some(excerpt) => writeln!(self.error_output, "{warning}: `{}`", excerpt.text()),
+                    None => writeln!(self.error_output, "{warning}"),
+                }
+                .expect("Failed to write error output");
+            }
         }
     }
 
-    fn evaluate_declaration(&mut self, declaration: &Declaration) {
+    fn evaluate_declaration_inner(
+        &mut self,
+        declaration: &Declaration,
+        excerpt: Option<&Excerpt>,
+    ) -> RuntimeResult<Flow> {
         match declaration {
+            Declaration::Variable { identifier, value } => {
+                let value = self.evaluate_expression(value)?;
+                self.environment
+                    .borrow_mut()
+                    .define_symbol(*identifier, value);
+                Ok(Flow::Normal)
+            }
+            Declaration::Destructuring { identifiers, value } => {
+                let values = match self.evaluate_expression(value)? {
+                    Value::Tuple(values) if values.len() == identifiers.len() => values,
+                    value => {
+                        return Err(RuntimeError::Destructure {
+                            value: value.to_string(),
+                            expected: identifiers.len(),
+                        })
+                    }
+                };
+                let mut environment = self.environment.borrow_mut();
+                for (identifier, value) in identifiers.iter().zip(values) {
+                    environment.define(identifier, value);
+                }
+                Ok(Flow::Normal)
+            }
             Declaration::VariableAssignment { identifier, value } => {
+                let value = self.evaluate_expression(value)?;
                 self.environment
-                    .assign(identifier, self.evaluate_expression(value));
-            },
-            Declaration::Statement(statement) => {
-                self.evaluate_statement(statement);
-            },
+                    .borrow_mut()
+                    .assign_symbol(*identifier, value);
+                Ok(Flow::Normal)
+            }
+            Declaration::Function(declaration) => {
+                let function =
+                    UserFunction::new(Rc::clone(declaration), Rc::clone(&self.environment));
+                self.environment
+                    .borrow_mut()
+                    .define(&declaration.name, Value::Function(function));
+                Ok(Flow::Normal)
+            }
+            Declaration::Statement(statement) => self.evaluate_statement(statement, excerpt),
             Declaration::Block(block) => {
-                let declarations = block.get_declarations();
-                self.evaluate_declarations(declarations);
-            },
+                let environment = Environment::new_block(Rc::clone(&self.environment));
+                self.evaluate_declarations_in(block, environment)
+            }
+            // Classes are parsed but can't be instantiated yet.
+            Declaration::Class { .. } => Ok(Flow::Normal),
+            Declaration::Annotated { declaration, .. } => {
+                self.evaluate_declaration(declaration, excerpt)
+            }
         }
     }
 
-    fn evaluate_statement(&mut self, statement: &Statement) {
+    fn evaluate_statement(
+        &mut self,
+        statement: &Statement,
+        excerpt: Option<&Excerpt>,
+    ) -> RuntimeResult<Flow> {
         match statement {
-            Statement::If { condition, declaration } => self.if_statement(condition, declaration),
-            Statement::Print(expression) => self.print(expression),
-            Statement::Expression(expression) => self.evaluate_expression_statement(expression),
+            Statement::If {
+                branches,
+                else_declaration,
+            } => self.if_statement(branches, else_declaration.as_deref(), excerpt),
+            Statement::DoWhile {
+                body,
+                condition,
+                label,
+            } => self.do_while_statement(body, condition, label),
+            Statement::While {
+                condition,
+                body,
+                increment,
+                label,
+            } => self.while_statement(condition, body, increment.as_ref(), label, excerpt),
+            Statement::Break(label) => Ok(Flow::Break(label.clone())),
+            Statement::Continue(label) => Ok(Flow::Continue(label.clone())),
+            Statement::Print(expressions) => {
+                self.print(expressions)?;
+                Ok(Flow::Normal)
+            }
+            Statement::Return(Some(Expression::Call {
+                callee,
+                arguments,
+                named,
+            })) if self.tail_calls
+                && self.call_depth > 0
+                && !matches!(**callee, Expression::Get { .. }) =>
+            {
+                self.tail_call(callee, arguments, named)
+            }
+            Statement::Return(value) => {
+                let value = match value {
+                    Some(value) => self.evaluate_expression(value)?,
+                    None => Value::Nil,
+                };
+                Ok(Flow::Return(value))
+            }
+            Statement::Delete(identifier) => {
+                self.undefine(identifier);
+                Ok(Flow::Normal)
+            }
+            Statement::Expression(expression) => {
+                let value = self.evaluate_expression_statement(expression)?;
+                if self.trace {
+                    writeln!(self.trace_output, "trace: => {value:?}")
+                        .expect("Failed to write trace");
+                }
+                Ok(Flow::Normal)
+            }
         }
     }
 
-    fn if_statement(&mut self, condition: &Expression, declaration: &Declaration) {
-        let condition_value = self.evaluate_expression(condition);
+    fn if_statement(
+        &mut self,
+        branches: &[ConditionalBranch],
+        else_declaration: Option<&Declaration>,
+        excerpt: Option<&Excerpt>,
+    ) -> RuntimeResult<Flow> {
+        for branch in branches.iter() {
+            let condition = self.evaluate_expression(&branch.condition)?;
+            if self.is_truthy(&condition) {
+                return self.evaluate_declaration(&branch.declaration, excerpt);
+            }
+        }
 
-        match condition_value {
-            LiteralValue::Boolean(value) => if value {
-                self.evaluate_declaration(declaration);
-            },
-            LiteralValue::String(value) => if !value.eq("") {
-                self.evaluate_declaration(declaration);
-            },
-            LiteralValue::Number(value) => if value != 0.0 {
-                self.evaluate_declaration(declaration);
-            },
-            LiteralValue::Identifier(_) => panic!("Unexpected unresolved identifier"),
-            LiteralValue::Nil => {},
+        match else_declaration {
+            Some(declaration) => self.evaluate_declaration(declaration, excerpt),
+            None => Ok(Flow::Normal),
         }
     }
 
-    fn print(&self, expression: &Expression) {
-        let result = self.evaluate_expression(expression);
-        println!("{}", result.to_string())
-    }
+    fn do_while_statement(
+        &mut self,
+        body: &Block,
+        condition: &Expression,
+        label: &Option<String>,
+    ) -> RuntimeResult<Flow> {
+        let mut iterations = 0;
 
-    fn evaluate_expression_statement(&self, expression: &Expression) {
-        let result = self.evaluate_expression(expression);
-        print!("{result:?}\n");
+        loop {
+            iterations += 1;
+            self.check_iteration_limit(iterations)?;
+            match self.evaluate_declarations(body.get_declarations(), body.excerpts())? {
+                Flow::Break(target) if targets_loop(&target, label) => return Ok(Flow::Normal),
+                Flow::Continue(target) if targets_loop(&target, label) => {}
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+
+            let condition = self.evaluate_expression(condition)?;
+            if !self.is_truthy(&condition) {
+                return Ok(Flow::Normal);
+            }
+        }
     }
 
-    fn evaluate_expression(&self, expression: &Expression) -> LiteralValue {
-        match expression {
-            Expression::Binary {
-                left,
-                right,
-                operator,
-            } => self.evaluate_binary_expression(left, right, operator),
-            Expression::Grouping(expression) => self.evaluate_expression(expression),
-            Expression::Literal(LiteralValue::Identifier(identifier)) => {
-                self.environment.resolve(identifier).clone()
+    fn while_statement(
+        &mut self,
+        condition: &Expression,
+        body: &Declaration,
+        increment: Option<&Expression>,
+        label: &Option<String>,
+        excerpt: Option<&Excerpt>,
+    ) -> RuntimeResult<Flow> {
+        let mut iterations = 0;
+
+        loop {
+            let condition = self.evaluate_expression(condition)?;
+            if !self.is_truthy(&condition) {
+                return Ok(Flow::Normal);
+            }
+
+            iterations += 1;
+            self.check_iteration_limit(iterations)?;
+            match self.evaluate_declaration(body, excerpt)? {
+                Flow::Break(target) if targets_loop(&target, label) => return Ok(Flow::Normal),
+                Flow::Continue(target) if targets_loop(&target, label) => {}
+                Flow::Normal => {}
+                flow => return Ok(flow),
+            }
+
+            if let Some(increment) = increment {
+                self.evaluate_expression(increment)?;
             }
-            Expression::Literal(literal_value) => literal_value.clone(),
-            Expression::Unary { right, operator } => {
-                self.evaluate_unary_expression(right, operator)
+        }
+    }
+
+    fn check_iteration_limit(&self, iterations: usize) -> RuntimeResult<()> {
+        match self.max_iterations {
+            Some(max_iterations) if iterations > max_iterations => {
+                Err(RuntimeError::IterationLimit(max_iterations))
             }
+            _ => Ok(()),
+        }
+    }
+
+    fn is_truthy(&self, value: &Value) -> bool {
+        value.is_truthy(self.truthiness_mode)
+    }
+
+    fn print(&mut self, expressions: &[Expression]) -> RuntimeResult<()> {
+        let mut values = vec![];
+        for expression in expressions {
+            values.push(self.evaluate_expression(expression)?);
+        }
+        let output = self.stringify_all(&values);
+        writeln!(self.output, "{output}").expect("Failed to write output");
+        Ok(())
+    }
+
+    fn stringify_all(&self, values: &[Value]) -> String {
+        let outputs: Vec<String> = values.iter().map(|value| self.stringify(value)).collect();
+        outputs.join(" ")
+    }
+
+    fn stringify(&self, value: &Value) -> String {
+        match self.precision {
+            Some(precision) => format!("{value:.precision$}"),
+            None => value.to_string(),
+        }
+    }
+
+    fn evaluate_expression_statement(&mut self, expression: &Expression) -> RuntimeResult<Value> {
+        let result = self.evaluate_expression(expression)?;
+        if self.echo_expressions {
+            // Unlike `print`, echoed strings are escaped so that control
+            // characters are visible.
+            let output = match result {
+                Value::String(_) => result.escaped(),
+                _ => self.stringify(&result),
+            };
+            writeln!(self.output, "{output}").expect("Failed to write output");
         }
+        Ok(result)
+    }
+
+    fn evaluate_expression(&mut self, expression: &Expression) -> RuntimeResult<Value> {
+        expression.accept(self)
     }
 
     fn evaluate_binary_expression(
-        &self,
+        &mut self,
         left: &Expression,
         right: &Expression,
-        operator: &Operator,
-    ) -> LiteralValue {
-        let left_value = self.evaluate_expression(left);
-        let right_value = self.evaluate_expression(right);
+        operator: &BinaryOperator,
+    ) -> RuntimeResult<Value> {
+        let left_value = self.evaluate_expression(left)?;
+        let right_value = self.evaluate_expression(right)?;
+
+        let is_ordering = matches!(
+            operator,
+            BinaryOperator::Greater
+                | BinaryOperator::GreaterEqual
+                | BinaryOperator::Less
+                | BinaryOperator::LessEqual
+        );
+        if is_ordering && (left_value.is_nil() || right_value.is_nil()) {
+            return Err(RuntimeError::NilComparison);
+        }
 
         match operator {
-            Operator::BangEquals => LiteralValue::Boolean(left_value != right_value),
-            Operator::EqualsEquals => LiteralValue::Boolean(left_value == right_value),
-            Operator::Greater => LiteralValue::Boolean(left_value > right_value),
-            Operator::GreaterEqual => LiteralValue::Boolean(left_value >= right_value),
-            Operator::Less => LiteralValue::Boolean(left_value < right_value),
-            Operator::LessEqual => LiteralValue::Boolean(left_value > right_value),
-            Operator::Minus => left_value - right_value,
-            Operator::Plus => left_value + right_value,
-            Operator::Slash => left_value / right_value,
-            Operator::Star => left_value * right_value,
-            Operator::Bang => panic!("Invalid binary operator"),
+            BinaryOperator::BangEquals => Ok(Value::Boolean(left_value != right_value)),
+            BinaryOperator::EqualsEquals => Ok(Value::Boolean(left_value == right_value)),
+            BinaryOperator::Greater => Ok(Value::Boolean(left_value > right_value)),
+            BinaryOperator::GreaterEqual => Ok(Value::Boolean(left_value >= right_value)),
+            BinaryOperator::Less => Ok(Value::Boolean(left_value < right_value)),
+            BinaryOperator::LessEqual => Ok(Value::Boolean(left_value <= right_value)),
+            BinaryOperator::Minus => left_value - right_value,
+            BinaryOperator::Plus => left_value + right_value,
+            BinaryOperator::Slash => left_value / right_value,
+            BinaryOperator::Star => left_value * right_value,
         }
     }
 
-    fn evaluate_unary_expression(&self, right: &Expression, operator: &Operator) -> LiteralValue {
-        match operator {
-            Operator::BangEquals => panic!("Invalid unary operator"),
-            Operator::EqualsEquals => panic!("Invalid unary operator"),
-            Operator::Greater => panic!("Invalid unary operator"),
-            Operator::GreaterEqual => panic!("Invalid unary operator"),
-            Operator::Less => panic!("Invalid unary operator"),
-            Operator::LessEqual => panic!("Invalid unary operator"),
-            Operator::Minus => match self.evaluate_expression(right) {
-                LiteralValue::Boolean(_) => panic!("Boolean values cannot be negated"),
-                LiteralValue::String(_) => panic!("String values cannot be negated"),
-                LiteralValue::Number(value) => LiteralValue::Number(-value.clone()),
-                LiteralValue::Nil => panic!("Nil values cannot be negated"),
-                LiteralValue::Identifier(_) => panic!("Unexpected unresolved identifier"),
-            },
-            Operator::Plus => self.evaluate_expression(right),
-            Operator::Slash => panic!("Invalid unary operator"),
-            Operator::Star => panic!("Invalid unary operator"),
-            Operator::Bang => match self.evaluate_expression(right) {
-                LiteralValue::Boolean(value) => LiteralValue::Boolean(!value),
-                LiteralValue::String(value) => LiteralValue::Boolean(value.len() == 0),
-                LiteralValue::Number(value) => LiteralValue::Boolean(value == 0.0),
-                LiteralValue::Nil => LiteralValue::Boolean(true),
-                LiteralValue::Identifier(_) => panic!("Unexpected unresolved identifier"),
+    fn evaluate_unary_expression(
+        &mut self,
+        right: &Expression,
+        operator: &UnaryOperator,
+    ) -> RuntimeResult<Value> {
+        Ok(match operator {
+            UnaryOperator::Minus => match self.evaluate_expression(right)? {
+                Value::Number(value) => Value::Number(-value),
+                value => {
+                    return Err(RuntimeError::InvalidOperand {
+                        operation: "negate",
+                        operand: value.type_name(),
+                    })
+                }
             },
-        }
+            UnaryOperator::Plus => self.evaluate_expression(right)?,
+            UnaryOperator::Bang => {
+                let value = self.evaluate_expression(right)?;
+                Value::Boolean(!self.is_truthy(&value))
+            }
+        })
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use crate::lib::environment::Environment;
-    use crate::lib::parser::Parser;
-    use crate::lib::scanner::Scanner;
-    use crate::{expr, tokens};
+    fn evaluate_arguments(
+        &mut self,
+        arguments: &[Expression],
+        named: &[(String, Expression)],
+    ) -> RuntimeResult<Arguments> {
+        let mut evaluated = Arguments::default();
+        for argument in arguments {
+            evaluated
+                .positional
+                .push(self.evaluate_expression(argument)?);
+        }
+        for (name, value) in named {
+            let value = self.evaluate_expression(value)?;
+            evaluated.named.push((name.clone(), value));
+        }
+        Ok(evaluated)
+    }
 
-    use super::*;
+    /// Missing map keys evaluate to `nil`, like undefined variables.
+    fn get_property(&self, object: Value, name: &str) -> RuntimeResult<Value> {
+        match object {
+            Value::Map(map) => Ok(map.get(name).cloned().unwrap_or(Value::Nil)),
+            object => Err(RuntimeError::NoProperty {
+                type_name: object.type_name(),
+                name: name.to_string(),
+            }),
+        }
+    }
 
-    #[test]
-    fn one_equals_equals_one() {
-        let expression = expr!("1==1");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+    /// Evaluates `return callee(arguments);` in a function. Calls to script
+    /// functions are handed back to `call_function`, which makes them in
+    /// place of the current call.
+    fn tail_call(
+        &mut self,
+        callee: &Expression,
+        arguments: &[Expression],
+        named: &[(String, Expression)],
+    ) -> RuntimeResult<Flow> {
+        let callee = self.evaluate_callee(callee)?;
+        let arguments = self.evaluate_arguments(arguments, named)?;
+        match callee {
+            Value::Function(function) => Ok(Flow::TailCall(function, arguments)),
+            callee => Ok(Flow::Return(self.call_value(callee, arguments)?)),
+        }
+    }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+    fn evaluate_callee(&mut self, callee: &Expression) -> RuntimeResult<Value> {
+        let callee = self.evaluate_expression(callee)?;
+        if !matches!(callee, Value::NativeFunction(_) | Value::Function(_)) {
+            return Err(RuntimeError::NotCallable(callee.type_name()));
+        }
+        Ok(callee)
+    }
 
-        assert_eq!(format!("{result:?}"), "Boolean(true)");
+    fn call_value(&mut self, callee: Value, arguments: Arguments) -> RuntimeResult<Value> {
+        match callee {
+            Value::NativeFunction(native) => {
+                reject_named_arguments(&native.name, &arguments)?;
+                native.call(self, &arguments.positional)
+            }
+            Value::Function(function) => self.call_function(&function, arguments),
+            callee => Err(RuntimeError::NotCallable(callee.type_name())),
+        }
     }
 
-    #[test]
-    fn one_equals_equals_two() {
-        let expression = expr!("1==2");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+    fn call_function(
+        &mut self,
+        function: &UserFunction,
+        arguments: Arguments,
+    ) -> RuntimeResult<Value> {
+        let mut function = function.clone();
+        let mut arguments = arguments;
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+        if let Some(max_call_depth) = self.max_call_depth {
+            if self.call_depth >= max_call_depth {
+                return Err(RuntimeError::CallDepthLimit(max_call_depth));
+            }
+        }
 
-        assert_eq!(format!("{result:?}"), "Boolean(false)");
-    }
+        // Each tail call replaces the previous call rather than nesting in it.
+        loop {
+            let (values, rest) = bind_arguments(&function, arguments)?;
+            let environment = Environment::new_enclosed(Rc::clone(&function.closure));
+            let previous =
+                std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
 
-    #[test]
-    fn one_equals_equals_true() {
-        let expression = expr!("1==true");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+            self.call_depth += 1;
+            let declaration = &function.declaration;
+            let flow = match self.define_parameters(declaration, values, rest) {
+                Ok(()) => {
+                    let body = &declaration.body;
+                    self.evaluate_declarations(body.get_declarations(), body.excerpts())
+                }
+                Err(error) => resume_flow(error),
+            };
+            self.call_depth -= 1;
+            self.environment = previous;
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+            match flow? {
+                Flow::TailCall(next, next_arguments) => {
+                    function = next;
+                    arguments = next_arguments;
+                }
+                flow => return flow.into_value(),
+            }
+        }
+    }
+}
 
-        assert_eq!(format!("{result:?}"), "Boolean(false)");
+impl Interpreter {
+    /// Defines the parameters of a call in the current scope, evaluating the
+    /// defaults of those which weren't passed. Defaults can refer to the
+    /// parameters before them.
+    fn define_parameters(
+        &mut self,
+        function: &Function,
+        values: Vec<Option<Value>>,
+        rest: Vec<Value>,
+    ) -> RuntimeResult<()> {
+        let first_default = function.required_parameters();
+        for (index, (parameter, value)) in function.parameters.iter().zip(values).enumerate() {
+            let value = match value {
+                Some(value) => value,
+                None => self.evaluate_expression(&function.defaults[index - first_default])?,
+            };
+            self.environment.borrow_mut().define(parameter, value);
+        }
+        if let Some(parameter) = &function.rest {
+            let list = Value::List(rest.into_iter().collect());
+            self.environment.borrow_mut().define(parameter, list);
+        }
+        Ok(())
     }
+}
 
-    #[test]
-    fn one_bang_equals_one() {
-        let expression = expr!("1!=1");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+impl Caller for Interpreter {
+    fn call(&mut self, callee: Value, arguments: Vec<Value>) -> RuntimeResult<Value> {
+        self.call_value(callee, arguments.into())
+    }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+    fn is_truthy(&self, value: &Value) -> bool {
+        Interpreter::is_truthy(self, value)
+    }
 
-        assert_eq!(format!("{result:?}"), "Boolean(false)");
+    fn print_error(&mut self, values: &[Value]) {
+        let output = self.stringify_all(values);
+        writeln!(self.error_output, "{output}").expect("Failed to write error output");
     }
 
-    #[test]
-    fn one_bang_equals_two() {
-        let expression = expr!("1!=2");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+    fn env_var(&self, name: &str) -> Option<String> {
+        (self.env_vars)(name)
+    }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+    fn allows(&self, capability: Capability) -> bool {
+        !self.sandbox && (capability != Capability::Files || self.file_access)
+    }
 
-        assert_eq!(format!("{result:?}"), "Boolean(true)");
+    fn scope_report(&self) -> Vec<Vec<String>> {
+        self.environment.borrow().scope_report()
     }
+}
 
-    #[test]
-    fn one_greater_two() {
-        let expression = expr!("1>2");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+impl Visitor<RuntimeResult<Value>> for Interpreter {
+    fn visit_assignment(&mut self, identifier: Symbol, value: &Expression) -> RuntimeResult<Value> {
+        let value = self.evaluate_expression(value)?;
+        self.environment
+            .borrow_mut()
+            .assign_symbol(identifier, value.clone());
+        Ok(value)
+    }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+    fn visit_binary(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+        operator: &BinaryOperator,
+    ) -> RuntimeResult<Value> {
+        self.evaluate_binary_expression(left, right, operator)
+    }
 
-        assert_eq!(format!("{result:?}"), "Boolean(false)");
+    fn visit_coalesce(&mut self, left: &Expression, right: &Expression) -> RuntimeResult<Value> {
+        match self.evaluate_expression(left)? {
+            Value::Nil => self.evaluate_expression(right),
+            value => Ok(value),
+        }
     }
 
-    #[test]
-    fn string_star_number() {
-        let expression = expr!("\"Hello \"*3");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+    fn visit_call(
+        &mut self,
+        callee: &Expression,
+        arguments: &[Expression],
+        named: &[(String, Expression)],
+    ) -> RuntimeResult<Value> {
+        // Built-in methods for the receiver's type take precedence over its
+        // properties.
+        if let Expression::Get {
+            object,
+            name,
+            optional,
+        } = callee
+        {
+            let receiver = self.evaluate_expression(object)?;
+            if *optional && receiver == Value::Nil {
+                return Ok(Value::Nil);
+            }
+            let arguments = self.evaluate_arguments(arguments, named)?;
+            if let Some(method) = natives::method(&receiver, name) {
+                reject_named_arguments(&method.name, &arguments)?;
+                return method.call_method(self, receiver, &arguments.positional);
+            }
+            let callee = self.get_property(receiver, name)?;
+            return self.call_value(callee, arguments);
+        }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+        // Fail before evaluating the arguments, which may have side effects.
+        let callee = self.evaluate_callee(callee)?;
+        let arguments = self.evaluate_arguments(arguments, named)?;
+        self.call_value(callee, arguments)
+    }
 
-        assert_eq!(format!("{result:?}"), "String(\"Hello Hello Hello \")");
+    fn visit_get(
+        &mut self,
+        object: &Expression,
+        name: &str,
+        optional: bool,
+    ) -> RuntimeResult<Value> {
+        match self.evaluate_expression(object)? {
+            Value::Nil if optional => Ok(Value::Nil),
+            object => self.get_property(object, name),
+        }
     }
 
-    #[test]
-    fn string_star_negative_number() {
-        let expression = expr!("\"Hello \"*-3");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+    fn visit_grouping(&mut self, expression: &Expression) -> RuntimeResult<Value> {
+        self.evaluate_expression(expression)
+    }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+    fn visit_index(&mut self, target: &Expression, index: &Expression) -> RuntimeResult<Value> {
+        let target = self.evaluate_expression(target)?;
+        let index = self.evaluate_expression(index)?;
 
-        assert_eq!(format!("{result:?}"), "String(\"\")");
+        match (target, index) {
+            (Value::List(list), Value::Number(index)) => {
+                index_values("list", &list.borrow(), index)
+            }
+            (Value::Tuple(values), Value::Number(index)) => index_values("tuple", &values, index),
+            (Value::Map(map), Value::String(key)) => {
+                Ok(map.get(&key).cloned().unwrap_or(Value::Nil))
+            }
+            (target, index) => Err(RuntimeError::InvalidIndex {
+                target: target.type_name(),
+                index: index.type_name(),
+            }),
+        }
     }
 
-    #[test]
-    fn string_star_float() {
-        let expression = expr!("\"Hello \"*3.9");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+    fn visit_list(&mut self, elements: &[Expression]) -> RuntimeResult<Value> {
+        Ok(Value::List(
+            elements
+                .iter()
+                .map(|element| self.evaluate_expression(element))
+                .collect::<RuntimeResult<_>>()?,
+        ))
+    }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+    fn visit_tuple(&mut self, elements: &[Expression]) -> RuntimeResult<Value> {
+        Ok(Value::Tuple(
+            elements
+                .iter()
+                .map(|element| self.evaluate_expression(element))
+                .collect::<RuntimeResult<_>>()?,
+        ))
+    }
 
-        assert_eq!(format!("{result:?}"), "String(\"Hello Hello Hello \")");
+    fn visit_map(&mut self, entries: &[(String, Expression)]) -> RuntimeResult<Value> {
+        let mut map = Map::new();
+        for (key, value) in entries {
+            let value = self.evaluate_expression(value)?;
+            map.insert(key.to_string(), value);
+        }
+        Ok(Value::Map(map))
     }
 
-    #[test]
-    fn complex_expression() {
-        let expression = expr!("!false == 5 > (1 - 2 + 5 / 2) * 100 - 10");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+    fn visit_interpolation(&mut self, parts: &[Expression]) -> RuntimeResult<Value> {
+        let mut string = String::new();
+        for part in parts {
+            let value = self.evaluate_expression(part)?;
+            string.push_str(&self.stringify(&value));
+        }
+        Ok(Value::String(string))
+    }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+    fn visit_literal(&mut self, value: &LiteralValue) -> RuntimeResult<Value> {
+        Ok(Value::from(value))
+    }
 
-        assert_eq!(format!("{result:?}"), "Boolean(false)");
+    fn visit_variable(&mut self, identifier: Symbol) -> RuntimeResult<Value> {
+        Ok(self.environment.borrow().resolve_symbol(identifier))
     }
 
-    #[test]
-    fn regression_number_multiply_string() {
-        let expression = expr!("3*\"Hello \"");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
-        };
+    fn visit_lambda(&mut self, function: &Rc<Function>) -> RuntimeResult<Value> {
+        Ok(Value::Function(UserFunction::new(
+            Rc::clone(function),
+            Rc::clone(&self.environment),
+        )))
+    }
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+    fn visit_block(&mut self, body: &Block, value: Option<&Expression>) -> RuntimeResult<Value> {
+        let environment = Environment::new_block(Rc::clone(&self.environment));
+        let previous = std::mem::replace(&mut self.environment, Rc::new(RefCell::new(environment)));
+        let result = match self.evaluate_declarations(body.get_declarations(), body.excerpts()) {
+            Ok(Flow::Normal) => match value {
+                Some(value) => self.evaluate_expression(value),
+                None => Ok(Value::Nil),
+            },
+            // Anything else leaves the statement around the block too.
+            Ok(flow) => Err(RuntimeError::Unwind(flow)),
+            Err(error) => Err(error),
+        };
+        self.environment = previous;
+        result
+    }
+
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Expression,
+        else_branch: Option<&Expression>,
+    ) -> RuntimeResult<Value> {
+        let condition = self.evaluate_expression(condition)?;
+        if self.is_truthy(&condition) {
+            self.evaluate_expression(then_branch)
+        } else {
+            match else_branch {
+                Some(else_branch) => self.evaluate_expression(else_branch),
+                None => Ok(Value::Nil),
+            }
+        }
+    }
+
+    fn visit_unary(
+        &mut self,
+        right: &Expression,
+        operator: &UnaryOperator,
+    ) -> RuntimeResult<Value> {
+        self.evaluate_unary_expression(right, operator)
+    }
+}
+
+/// Orders the arguments of a call to `function` to match its parameters,
+/// putting each named argument in the place of the parameter with its name.
+/// Parameters with defaults which weren't passed are left as `None`, and the
+/// positional arguments left over are returned for a rest parameter.
+fn bind_arguments(
+    function: &UserFunction,
+    arguments: Arguments,
+) -> RuntimeResult<(Vec<Option<Value>>, Vec<Value>)> {
+    let parameters = &function.declaration.parameters;
+    let variadic = function.declaration.rest.is_some();
+    let required = function.declaration.required_parameters();
+    let given = arguments.positional.len() + arguments.named.len();
+    let arity_error = || {
+        let name = function.name().to_string();
+        if required == parameters.len() && !variadic {
+            RuntimeError::Arity {
+                name,
+                expected: required,
+                got: given,
+            }
+        } else {
+            RuntimeError::ArityRange {
+                name,
+                min: required,
+                max: (!variadic).then_some(parameters.len()),
+                got: given,
+            }
+        }
+    };
+    if arguments.positional.len() > parameters.len() && !variadic {
+        return Err(arity_error());
+    }
+
+    let mut positional = arguments.positional;
+    let rest = positional.split_off(parameters.len().min(positional.len()));
+    let mut values: Vec<Option<Value>> = positional.into_iter().map(Some).collect();
+    values.resize(parameters.len(), None);
+    for (name, value) in arguments.named {
+        let Some(index) = parameters.iter().position(|parameter| *parameter == name) else {
+            return Err(RuntimeError::UnexpectedArgument {
+                name: function.name().to_string(),
+                argument: name,
+            });
+        };
+        if values[index].is_some() {
+            return Err(RuntimeError::DuplicateArgument {
+                name: function.name().to_string(),
+                argument: name,
+            });
+        }
+        values[index] = Some(value);
+    }
+
+    if values[..required].iter().any(Option::is_none) {
+        return Err(arity_error());
+    }
+    Ok((values, rest))
+}
+
+/// Natives don't have parameter names, so they can only be called with
+/// positional arguments.
+fn reject_named_arguments(name: &str, arguments: &Arguments) -> RuntimeResult<()> {
+    match arguments.named.first() {
+        Some((argument, _)) => Err(RuntimeError::UnexpectedArgument {
+            name: name.to_string(),
+            argument: argument.clone(),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Negative indices count back from the end, so `-1` is the last element.
+fn index_values(kind: &'static str, values: &[Value], index: f32) -> RuntimeResult<Value> {
+    if index.fract() != 0.0 {
+        return Err(RuntimeError::NonIntegerIndex { kind, index });
+    }
+    let position = if index < 0.0 {
+        values.len() as f32 + index
+    } else {
+        index
+    };
+    match (position >= 0.0)
+        .then(|| values.get(position as usize))
+        .flatten()
+    {
+        Some(value) => Ok(value.clone()),
+        None => Err(RuntimeError::IndexOutOfBounds {
+            kind,
+            index,
+            length: values.len(),
+        }),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use crate::lib::environment::Environment;
+    use crate::lib::list::List;
+    use crate::lib::optimizer::optimize;
+    use crate::lib::parser::Parser;
+    use crate::lib::scanner::Scanner;
+    use crate::lib::testing::{SharedOutput, TempDir};
+    use crate::lib::value::MAX_REPEAT_LENGTH;
+    use crate::{expr, tokens};
+
+    use super::*;
+
+    fn run_with_output(source: &str) -> String {
+        let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let mut interpreter =
+            Interpreter::new(Environment::new()).with_output(Box::new(output.clone()));
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        output.contents()
+    }
+
+    /// Runs `source` with the global `path` set to `path`, returning the
+    /// result along with the output.
+    fn run_with_path(source: &str, path: &str) -> (RuntimeResult<()>, String) {
+        let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut environment = Environment::new();
+        environment.assign("path", Value::String(path.to_string()));
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(environment).with_output(Box::new(output.clone()));
+
+        let result = interpreter.run(&program);
+
+        (result, output.contents())
+    }
+
+    fn run_with_error(source: &str) -> RuntimeError {
+        let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter =
+            Interpreter::new(Environment::new()).with_output(Box::new(io::sink()));
+
+        interpreter
+            .run(&program)
+            .expect_err("Program should fail at runtime")
+    }
+
+    #[test]
+    fn one_equals_equals_one() {
+        let expression = expr!("1==1");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "Boolean(true)");
+    }
+
+    #[test]
+    fn one_equals_equals_two() {
+        let expression = expr!("1==2");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "Boolean(false)");
+    }
+
+    #[test]
+    fn one_equals_equals_true() {
+        let expression = expr!("1==true");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "Boolean(false)");
+    }
+
+    #[test]
+    fn one_bang_equals_one() {
+        let expression = expr!("1!=1");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "Boolean(false)");
+    }
+
+    #[test]
+    fn one_bang_equals_two() {
+        let expression = expr!("1!=2");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "Boolean(true)");
+    }
+
+    #[test]
+    fn one_greater_two() {
+        let expression = expr!("1>2");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "Boolean(false)");
+    }
+
+    #[test]
+    fn negative_number() {
+        let expression = expr!("-99");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(result, Value::Number(-99.0));
+    }
+
+    #[test]
+    fn number_minus_number() {
+        let expression = expr!("5-3");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(result, Value::Number(2.0));
+    }
+
+    #[test]
+    fn number_minus_negative_number() {
+        let expression = expr!("5--3");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(result, Value::Number(8.0));
+    }
+
+    #[test]
+    fn not_negative_number() {
+        let expression = expr!("!-99");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(result, Value::Boolean(false));
+    }
+
+    #[test]
+    fn string_star_number() {
+        let expression = expr!("\"Hello \"*3");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "String(\"Hello Hello Hello \")");
+    }
+
+    #[test]
+    fn string_star_negative_number() {
+        let expression = expr!("\"Hello \"*-3");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "String(\"\")");
+    }
+
+    #[test]
+    fn string_star_float() {
+        let expression = expr!("\"Hello \"*3.9");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "String(\"Hello Hello Hello \")");
+    }
+
+    #[test]
+    fn complex_expression() {
+        let expression = expr!("!false == 5 > (1 - 2 + 5 / 2) * 100 - 10");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "Boolean(false)");
+    }
+
+    #[test]
+    fn regression_number_multiply_string() {
+        let expression = expr!("3*\"Hello \"");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "String(\"Hello Hello Hello \")");
+    }
+
+    #[test]
+    fn multiplying_a_string_rejects_huge_results() {
+        for source in ["\"ab\" * 1e20", "1e20 * \"ab\""] {
+            let expression = expr!(source);
+            let mut interpreter = Interpreter::new(Environment::new());
+
+            let result = Interpreter::evaluate_expression(&mut interpreter, &expression);
+
+            assert_eq!(
+                result,
+                Err(RuntimeError::StringTooLong {
+                    max: MAX_REPEAT_LENGTH
+                }),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn if_runs_body_only_when_condition_is_truthy() {
+        let output = run_with_output(
+            "if true print \"true\"; if false print \"false\";
+            if 1 < 2 { print \"block\"; } if nil { print \"nil\"; }",
+        );
+
+        assert_eq!(output, "true\nblock\n");
+    }
+
+    #[test]
+    fn else_if_chain_selects_matching_branch() {
+        for (n, expected) in [(1.0, "one"), (3.0, "three"), (5.0, "five"), (9.0, "other")] {
+            let tokens = tokens!(
+                "if n == 1 branch = \"one\";
+                else if n == 2 branch = \"two\";
+                else if n == 3 branch = \"three\";
+                else if n == 4 branch = \"four\";
+                else if n == 5 branch = \"five\";
+                else branch = \"other\";"
+            )
+            .expect("Scanner should not fail to parse source");
+            let program = Parser::new(&tokens)
+                .parse()
+                .expect("Parser should not fail to parse tokens");
+            let mut environment = Environment::new();
+            environment.assign("n", Value::Number(n));
+            let mut interpreter = Interpreter::new(environment);
+
+            interpreter
+                .run(&program)
+                .expect("Program should run without errors");
+
+            assert_eq!(
+                interpreter.environment.borrow().resolve("branch"),
+                Value::String(expected.to_string())
+            );
+        }
+    }
+
+    #[test]
+    fn do_while_runs_body_once_when_condition_is_false() {
+        let tokens = tokens!("count = 0; do { count = count + 1; } while false;")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(
+            interpreter.environment.borrow().resolve("count"),
+            Value::Number(1.0)
+        );
+    }
+
+    #[test]
+    fn do_while_repeats_until_condition_is_false() {
+        let tokens = tokens!("count = 0; do { count = count + 1; } while count < 5;")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(
+            interpreter.environment.borrow().resolve("count"),
+            Value::Number(5.0)
+        );
+    }
+
+    #[test]
+    fn run_capturing_returns_last_expression_value() {
+        let tokens = tokens!("x = 1; 1 + 2;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = interpreter
+            .run_capturing(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(result, Some(Value::Number(3.0)));
+    }
+
+    #[test]
+    fn run_capturing_returns_none_for_trailing_statement() {
+        let tokens = tokens!("1 + 2; print 1;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = interpreter
+            .run_capturing(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(result, None);
+    }
+
+    #[test]
+    fn bang_zero_depends_on_truthiness_mode() {
+        let expression = expr!("!0");
+        let mut loose = Interpreter::new(Environment::new());
+        let mut lox =
+            Interpreter::new(Environment::new()).with_truthiness_mode(TruthinessMode::Lox);
+
+        let loose_result = Interpreter::evaluate_expression(&mut loose, &expression)
+            .expect("Expression should evaluate without errors");
+        let lox_result = Interpreter::evaluate_expression(&mut lox, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(loose_result, Value::Boolean(true));
+        assert_eq!(lox_result, Value::Boolean(false));
+    }
+
+    #[test]
+    fn bang_empty_string_depends_on_truthiness_mode() {
+        let expression = expr!("!\"\"");
+        let mut loose = Interpreter::new(Environment::new());
+        let mut lox =
+            Interpreter::new(Environment::new()).with_truthiness_mode(TruthinessMode::Lox);
+
+        let loose_result = Interpreter::evaluate_expression(&mut loose, &expression)
+            .expect("Expression should evaluate without errors");
+        let lox_result = Interpreter::evaluate_expression(&mut lox, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(loose_result, Value::Boolean(true));
+        assert_eq!(lox_result, Value::Boolean(false));
+    }
+
+    #[test]
+    fn list_literal() {
+        let expression = expr!("[1 + 1, \"a\", [true],]");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result}"), "[2, a, [true]]");
+    }
+
+    #[test]
+    fn call_native_format() {
+        let expression = expr!("format(\"{} + {} = {}\", 1, 2, 1 + 2)");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(result, Value::String("1 + 2 = 3".to_string()));
+    }
+
+    #[test]
+    fn stringify_default_precision() {
+        let interpreter = Interpreter::new(Environment::new());
+
+        let result = interpreter.stringify(&Value::Number(1.23456));
+
+        assert_eq!(result, "1.23456");
+    }
+
+    #[test]
+    fn stringify_with_precision() {
+        let interpreter = Interpreter::new(Environment::new()).with_precision(2);
+
+        let result = interpreter.stringify(&Value::Number(1.23456));
+
+        assert_eq!(result, "1.23");
+    }
+
+    #[test]
+    fn print_uses_precision() {
+        let tokens = tokens!("print 3.14159;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        for (precision, expected) in [(Some(2), "3.14\n"), (None, "3.14159\n")] {
+            let output = SharedOutput::default();
+            let interpreter =
+                Interpreter::new(Environment::new()).with_output(Box::new(output.clone()));
+            let mut interpreter = match precision {
+                Some(precision) => interpreter.with_precision(precision),
+                None => interpreter,
+            };
+
+            interpreter
+                .run(&program)
+                .expect("Program should run without errors");
+
+            assert_eq!(output.contents(), expected);
+        }
+    }
+
+    #[test]
+    fn assignment_expression_yields_value() {
+        let output = run_with_output("print (a = 1) + (b = 2); print a; print b;");
+
+        assert_eq!(output, "3\n1\n2\n");
+    }
+
+    #[test]
+    fn binary_operands_evaluate_left_to_right() {
+        // Left to right: x = 1 then x = 5, giving 1 * 10 + 5. Right to left
+        // would give x = 0 then x = 1, printing 10.
+        let output = run_with_output("x = 0; print (x = x + 1) * 10 + (x = x * 5); print x;");
+
+        assert_eq!(output, "15\n5\n");
+    }
+
+    #[test]
+    fn chained_assignment() {
+        let output = run_with_output("a = b = 3; print a + b;");
+
+        assert_eq!(output, "6\n");
+    }
+
+    #[test]
+    fn while_loop() {
+        let output = run_with_output("i = 0; while i < 3 { print i; i = i + 1; }");
+
+        assert_eq!(output, "0\n1\n2\n");
+    }
+
+    #[test]
+    fn for_loop() {
+        let output = run_with_output("for (i = 0; i < 3; i = i + 1) print i;");
+
+        assert_eq!(output, "0\n1\n2\n");
+    }
+
+    #[test]
+    fn infinite_loop_hits_iteration_limit() {
+        let tokens = tokens!("while true {}").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new()).with_max_iterations(100);
+
+        let result = interpreter.run(&program);
+
+        assert_eq!(result, Err(RuntimeError::IterationLimit(100)));
+    }
+
+    #[test]
+    fn infinite_do_while_hits_iteration_limit() {
+        let tokens = tokens!("do {} while true;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new()).with_max_iterations(100);
+
+        let result = interpreter.run(&program);
+
+        assert_eq!(result, Err(RuntimeError::IterationLimit(100)));
+    }
+
+    #[test]
+    fn loop_within_iteration_limit() {
+        let tokens = tokens!("for (i = 0; i < 100; i = i + 1) {}")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new()).with_max_iterations(100);
+
+        let result = interpreter.run(&program);
+
+        assert_eq!(result, Ok(()));
+    }
+
+    #[test]
+    fn regression_divison_order() {
+        let expression = expr!("1+2/4");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression)
+            .expect("Expression should evaluate without errors");
+
+        assert_eq!(format!("{result:?}"), "Number(1.5)");
+    }
+
+    #[test]
+    fn function_call_returns_value() {
+        let output = run_with_output("fun add(a, b) { return a + b; } print add(1, 2);");
+
+        assert_eq!(output, "3\n");
+    }
+
+    #[test]
+    fn function_without_return_evaluates_to_nil() {
+        let output = run_with_output("fun noop() {} print noop();");
+
+        assert_eq!(output, "nil\n");
+    }
+
+    #[test]
+    fn return_unwinds_out_of_loops() {
+        let output = run_with_output(
+            "fun first_over(xs, n) { for (var i = 0; i < len(xs); i = i + 1) if xs[i] > n return xs[i]; }
+            print first_over([1, 5, 9], 3);",
+        );
+
+        assert_eq!(output, "5\n");
+    }
+
+    #[test]
+    fn recursive_function() {
+        let output = run_with_output(
+            "fun fib(n) { if n < 2 return n; return fib(n - 1) + fib(n - 2); } print fib(10);",
+        );
+
+        assert_eq!(output, "55\n");
+    }
+
+    #[test]
+    fn function_parameters_and_vars_are_local() {
+        let output = run_with_output(
+            "x = 1; y = 2; fun f(x) { var y = x * 10; return y; } print f(5); print x; print y;",
+        );
+
+        assert_eq!(output, "50\n1\n2\n");
+    }
+
+    #[test]
+    fn assigning_parameter_leaves_outer_variable_unchanged() {
+        let output = run_with_output(
+            "x = 1;
+            fun f(x) { x = x + 10; return x; }
+            fun g(x = 2) { x = x * 3; return x; }
+            print f(5); print g(); print x;",
+        );
+
+        assert_eq!(output, "15\n6\n1\n");
+    }
+
+    #[test]
+    fn function_closes_over_declaring_scope() {
+        let output = run_with_output(
+            "fun counter() { var count = 0; fun increment() { count = count + 1; return count; } return increment; }
+            next = counter(); next(); print next();",
+        );
+
+        assert_eq!(output, "2\n");
+    }
+
+    #[test]
+    fn index_list() {
+        let output = run_with_output("xs = [1, [2, 3]]; print xs[1][0]; print xs[0] + xs[1][1];");
+
+        assert_eq!(output, "2\n4\n");
+    }
+
+    #[test]
+    fn index_out_of_bounds() {
+        assert_eq!(
+            run_with_error("print [1, 2][2];"),
+            RuntimeError::IndexOutOfBounds {
+                kind: "list",
+                index: 2.0,
+                length: 2
+            }
+        );
+    }
+
+    #[test]
+    fn negative_index_counts_from_end() {
+        let output = run_with_output("xs = [1, 2, 3]; print xs[-1], xs[-3]; print (1, 2)[-2];");
+
+        assert_eq!(output, "3 1\n1\n");
+        assert_eq!(
+            run_with_error("print [1, 2, 3][-4];"),
+            RuntimeError::IndexOutOfBounds {
+                kind: "list",
+                index: -4.0,
+                length: 3
+            }
+        );
+    }
+
+    #[test]
+    fn invalid_index() {
+        assert_eq!(
+            run_with_error("print [1][\"a\"];"),
+            RuntimeError::InvalidIndex {
+                target: "list",
+                index: "string"
+            }
+        );
+        assert_eq!(
+            run_with_error("print (1, 2)[0.5];"),
+            RuntimeError::NonIntegerIndex {
+                kind: "tuple",
+                index: 0.5
+            }
+        );
+    }
+
+    #[test]
+    fn delete_removes_binding() {
+        let output = run_with_output("x = 1; delete x; print x;");
+
+        assert_eq!(output, "nil\n");
+    }
+
+    #[test]
+    fn delete_falls_through_to_enclosing_scope() {
+        let output = run_with_output(
+            "x = 1; fun f(x) { delete x; print x; delete x; print x; } f(2); print x;",
+        );
+
+        assert_eq!(output, "1\n1\n1\n");
+    }
+
+    #[test]
+    fn tuple_literal() {
+        let output = run_with_output("print (1, \"a\", (true,)); print (1);");
+
+        assert_eq!(output, "(1, a, (true,))\n1\n");
+    }
+
+    #[test]
+    fn index_tuple() {
+        let output = run_with_output("t = (1, (2, 3)); print t[1][0] + t[0];");
+
+        assert_eq!(output, "3\n");
+    }
+
+    #[test]
+    fn destructure_tuple() {
+        let output = run_with_output(
+            "fun divmod(a, b) { return (a / b, a - b * 2); } var (q, r) = divmod(5, 2); print q; print r;",
+        );
+
+        assert_eq!(output, "2.5\n1\n");
+    }
+
+    #[test]
+    fn destructure_length_mismatch() {
+        assert_eq!(
+            run_with_error("var (a, b, c) = (1, 2);"),
+            RuntimeError::Destructure {
+                value: "(1, 2)".to_string(),
+                expected: 3
+            }
+        );
+    }
+
+    #[test]
+    fn exit_propagates_code() {
+        let tokens =
+            tokens!("print 1; exit(3); print 2;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let mut interpreter =
+            Interpreter::new(Environment::new()).with_output(Box::new(output.clone()));
+
+        let result = interpreter.run(&program);
+
+        assert_eq!(result, Err(RuntimeError::Exit(3)));
+        assert_eq!(output.contents(), "1\n");
+    }
+
+    #[test]
+    fn exit_propagates_out_of_functions() {
+        let tokens = tokens!("fun f() { while true exit(4); } f();")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = interpreter.run(&program);
+
+        assert_eq!(result, Err(RuntimeError::Exit(4)));
+    }
+
+    #[test]
+    fn function_call_checks_arity() {
+        for (arguments, got) in [("1", 1), ("1, 2, 3", 3)] {
+            let source = format!("fun add(a, b) {{ return a + b; }} add({arguments});");
+            let tokens = tokens!(&source).expect("Scanner should not fail to parse source");
+            let program = Parser::new(&tokens)
+                .parse()
+                .expect("Parser should not fail to parse tokens");
+            let mut interpreter = Interpreter::new(Environment::new());
+
+            let result = interpreter.run(&program);
+
+            assert_eq!(
+                result,
+                Err(RuntimeError::Arity {
+                    name: "add".to_string(),
+                    expected: 2,
+                    got
+                }),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn directives_do_not_affect_evaluation() {
+        let output = run_with_output("@test fun f() { return 1; } @inline print f();");
+
+        assert_eq!(output, "1\n");
+    }
+
+    #[test]
+    fn nil_ordering_is_an_error() {
+        for source in ["nil < 1", "1 <= nil", "nil > nil", "nil >= 1"] {
+            let expression = expr!(source);
+            let mut interpreter = Interpreter::new(Environment::new());
+
+            let result = Interpreter::evaluate_expression(&mut interpreter, &expression);
+
+            assert_eq!(result, Err(RuntimeError::NilComparison), "{source}");
+        }
+    }
+
+    #[test]
+    fn nil_equality() {
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let equal = Interpreter::evaluate_expression(&mut interpreter, &expr!("nil == nil"));
+        let not_equal = Interpreter::evaluate_expression(&mut interpreter, &expr!("nil != 1"));
+
+        assert_eq!(equal, Ok(Value::Boolean(true)));
+        assert_eq!(not_equal, Ok(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn less_equal() {
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let equal = Interpreter::evaluate_expression(&mut interpreter, &expr!("1 <= 1"));
+        let greater = Interpreter::evaluate_expression(&mut interpreter, &expr!("2 <= 1"));
+
+        assert_eq!(equal, Ok(Value::Boolean(true)));
+        assert_eq!(greater, Ok(Value::Boolean(false)));
+    }
+
+    #[test]
+    fn string_methods() {
+        let output = run_with_output("print \"hi\".upper(); print \"  Hi \".trim().lower().len();");
+
+        assert_eq!(output, "HI\n2\n");
+    }
+
+    #[test]
+    fn list_methods() {
+        let output = run_with_output("print [1, 2].len(); print [1, 2].contains(3);");
+
+        assert_eq!(output, "2\nfalse\n");
+    }
+
+    #[test]
+    fn unknown_method() {
+        let error = run_with_error("print 1.0.upper();");
+
+        assert_eq!(
+            error,
+            RuntimeError::NoProperty {
+                type_name: "number",
+                name: "upper".to_string()
+            }
+        );
+        assert_eq!(error.to_string(), "number has no property upper");
+    }
+
+    #[test]
+    fn map_display_and_iteration_order_is_stable() {
+        let output = run_with_output(
+            "m = {zebra: 1, apple: 2, mango: 3, apple: 4}; print m; print m.keys(); print m.values();",
+        );
+
+        assert_eq!(
+            output,
+            "{zebra: 1, apple: 4, mango: 3}\n[zebra, apple, mango]\n[1, 4, 3]\n"
+        );
+    }
+
+    #[test]
+    fn map_access() {
+        let output =
+            run_with_output("m = {a: 1, \"b\": 2}; print m.a + m[\"b\"]; print m.c; print len(m);");
+
+        assert_eq!(output, "3\nnil\n2\n");
+    }
+
+    #[test]
+    fn call_native_between() {
+        let output = run_with_output("print between(5, 1, 10); print between(0, 1, 10);");
+
+        assert_eq!(output, "true\nfalse\n");
+    }
+
+    fn run_script_with_output(source: &str, environment: Environment) -> String {
+        let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(environment).with_output(Box::new(output.clone()));
+
+        interpreter
+            .run_script(&program)
+            .expect("Program should run without errors");
+
+        output.contents()
+    }
+
+    #[test]
+    fn run_script_calls_main_after_top_level() {
+        let output = run_script_with_output(
+            "fun main() { print \"main\"; } print \"top level\";",
+            Environment::new(),
+        );
+
+        assert_eq!(output, "top level\nmain\n");
+    }
+
+    #[test]
+    fn run_script_passes_args_to_main() {
+        let mut environment = Environment::new();
+        environment.define(
+            "ARGS",
+            Value::List(List::from(vec![Value::String("a".to_string())])),
+        );
+
+        let output = run_script_with_output("fun main(args) { print args; }", environment);
+
+        assert_eq!(output, "[a]\n");
+    }
+
+    #[test]
+    fn run_script_without_main_runs_top_level() {
+        let output = run_script_with_output("print 1; print 2;", Environment::new());
+
+        assert_eq!(output, "1\n2\n");
+    }
+
+    #[test]
+    fn trace_logs_declarations_and_values() {
+        let tokens = tokens!("x = 1; x + 1;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let trace = SharedOutput::default();
+        let mut interpreter = Interpreter::new(Environment::new())
+            .with_output(Box::new(io::sink()))
+            .with_trace(true)
+            .with_trace_output(Box::new(trace.clone()));
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(
+            trace.contents(),
+            "trace: VariableAssignment { identifier: \"x\", value: Literal(Number(1.0)) }\n\
+             trace: Statement(Expression(Binary { left: Variable(\"x\"), right: Literal(Number(1.0)), operator: Plus }))\n\
+             trace: => Number(2.0)\n"
+        );
+    }
+
+    #[test]
+    fn trace_is_off_by_default() {
+        let tokens = tokens!("x = 1; x + 1;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let trace = SharedOutput::default();
+        let mut interpreter = Interpreter::new(Environment::new())
+            .with_output(Box::new(io::sink()))
+            .with_trace_output(Box::new(trace.clone()));
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(trace.contents(), "");
+    }
+
+    #[test]
+    fn print_multiple_values() {
+        let output = run_with_output("print 1, 2, 3; print \"x\"; print \"a\" + \"b\", nil;");
+
+        assert_eq!(output, "1 2 3\nx\nab nil\n");
+    }
+
+    #[test]
+    fn expression_statements_are_silent_by_default() {
+        let output = run_with_output("42; \"a\" + \"b\";");
+
+        assert_eq!(output, "");
+    }
+
+    #[test]
+    fn echo_expressions_prints_values() {
+        let tokens =
+            tokens!("42; \"a\" + \"b\"; x = 1;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(Environment::new())
+            .with_output(Box::new(output.clone()))
+            .with_echo_expressions(true);
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(output.contents(), "42\n\"ab\"\n");
+    }
+
+    #[test]
+    fn echo_escapes_strings_but_print_does_not() {
+        let tokens = tokens!("print \"a\\nb\"; \"a\\nb\";")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(Environment::new())
+            .with_output(Box::new(output.clone()))
+            .with_echo_expressions(true);
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(output.contents(), "a\nb\n\"a\\nb\"\n");
+    }
+
+    #[test]
+    fn calling_a_non_function_is_an_error() {
+        for (source, type_name) in [("5()", "number"), ("nil()", "nil"), ("\"x\"(1)", "string")] {
+            let expression = expr!(source);
+            let mut interpreter = Interpreter::new(Environment::new());
+
+            let result = Interpreter::evaluate_expression(&mut interpreter, &expression);
+
+            assert_eq!(
+                result,
+                Err(RuntimeError::NotCallable(type_name)),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn repeat_native_and_method() {
+        let output = run_with_output("print repeat(\"abc\", 1.5); print \"ab\".repeat(2.5);");
+
+        assert_eq!(output, "abca\nababa\n");
+    }
+
+    #[test]
+    fn mismatched_operands_are_errors() {
+        for (source, operation, left, right) in [
+            ("true - 1", "subtract", "boolean", "number"),
+            ("\"a\" - \"b\"", "subtract", "string", "string"),
+            ("1 + nil", "add", "number", "nil"),
+            ("[1] + (1,)", "add", "list", "tuple"),
+            ("nil / 2", "divide", "nil", "number"),
+            ("[1] * 2", "multiply", "list", "number"),
+        ] {
+            let expression = expr!(source);
+            let mut interpreter = Interpreter::new(Environment::new());
+
+            let result = Interpreter::evaluate_expression(&mut interpreter, &expression);
+
+            assert_eq!(
+                result,
+                Err(RuntimeError::InvalidOperands {
+                    operation,
+                    left,
+                    right
+                }),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn negating_a_non_number_is_an_error() {
+        let expression = expr!("-\"a\"");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        let result = Interpreter::evaluate_expression(&mut interpreter, &expression);
+
+        assert_eq!(
+            result,
+            Err(RuntimeError::InvalidOperand {
+                operation: "negate",
+                operand: "string"
+            })
+        );
+        assert_eq!(result.unwrap_err().to_string(), "Cannot negate string");
+    }
+
+    #[test]
+    fn string_concatenation_with_other_types() {
+        let output = run_with_output(
+            "print \"a\" + 1 + true + nil + [1]; print 1 + \"a\"; print true + \"a\"; print nil + \"a\";",
+        );
+
+        assert_eq!(output, "a1truenil[1]\n1a\ntruea\nnila\n");
+    }
+
+    #[test]
+    fn arithmetic_uses_resolved_variable_values() {
+        let output = run_with_output("x = 2; y = \"a\"; print x * 3 + 1, y + x, (x);");
+
+        assert_eq!(output, "7 a2 2\n");
+    }
+
+    #[test]
+    fn arithmetic_with_undefined_variable_sees_nil() {
+        assert_eq!(
+            run_with_error("print 1 + undefined;"),
+            RuntimeError::InvalidOperands {
+                operation: "add",
+                left: "number",
+                right: "nil"
+            }
+        );
+    }
+
+    #[test]
+    fn environment_holds_evaluated_values() {
+        let tokens = tokens!("a = 1; b = a; c = [a, \"x\"];")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        let environment = interpreter.environment.borrow();
+        assert_eq!(environment.resolve("b"), Value::Number(1.0));
+        assert_eq!(
+            environment.resolve("c"),
+            Value::List(List::from(vec![
+                Value::Number(1.0),
+                Value::String("x".to_string())
+            ]))
+        );
+    }
+
+    #[test]
+    fn block_expression_evaluates_to_last_expression() {
+        let output = run_with_output("var x = { var t = 1; t + 1 }; print x, t;");
+
+        assert_eq!(output, "2 nil\n");
+    }
+
+    #[test]
+    fn block_expression_without_value_is_nil() {
+        let output = run_with_output("var x = { var t = 1; t + 1; }; print x;");
+
+        assert_eq!(output, "nil\n");
+    }
+
+    #[test]
+    fn return_inside_block_expression_returns_from_function() {
+        let output = run_with_output(
+            "fun f(x) { var y = { if x return 1; 2 }; print \"after\"; return y + 10; }
+            print f(true), f(false);",
+        );
+
+        assert_eq!(output, "after\n1 12\n");
+    }
+
+    #[test]
+    fn break_and_continue_inside_block_expression_apply_to_loop() {
+        let output = run_with_output(
+            "for (var i = 0; i < 5; i = i + 1) {
+                var v = { if i == 1 continue; if i == 3 break; i * 10 };
+                print v;
+            }",
+        );
+
+        assert_eq!(output, "0\n20\n");
+    }
+
+    #[test]
+    fn break_inside_block_expression_outside_loop_is_an_error() {
+        assert_eq!(
+            run_with_error("var x = { break; };"),
+            RuntimeError::NoEnclosingLoop {
+                keyword: "break",
+                label: None
+            }
+        );
+    }
 
-        assert_eq!(format!("{result:?}"), "String(\"Hello Hello Hello \")");
+    #[test]
+    fn break_and_continue_innermost_loop() {
+        let output = run_with_output(
+            "for (var i = 0; i < 5; i = i + 1) { if i == 1 continue; if i == 3 break; print i; }",
+        );
+
+        assert_eq!(output, "0\n2\n");
     }
 
     #[test]
-    fn regression_divison_order() {
-        let expression = expr!("1+2/4");
-        let interpreter = Interpreter {
-            environment: Environment::new(),
+    fn labeled_break_escapes_nested_loops() {
+        let output = run_with_output(
+            "outer: for (var i = 0; i < 3; i = i + 1) {
+                var j = 0;
+                while true {
+                    if i == 1 break outer;
+                    if j == 2 break;
+                    print i, j;
+                    j = j + 1;
+                }
+            }
+            print \"done\";",
+        );
+
+        assert_eq!(output, "0 0\n0 1\ndone\n");
+    }
+
+    #[test]
+    fn labeled_continue_skips_to_outer_iteration() {
+        let output = run_with_output(
+            "i = 0;
+            outer: do {
+                i = i + 1;
+                for (var j = 0; j < 3; j = j + 1) {
+                    if j == 1 continue outer;
+                    print i, j;
+                }
+            } while i < 2;",
+        );
+
+        assert_eq!(output, "1 0\n2 0\n");
+    }
+
+    #[test]
+    fn break_outside_loop_is_an_error() {
+        assert_eq!(
+            run_with_error("fun f() { break; } while true { f(); }"),
+            RuntimeError::NoEnclosingLoop {
+                keyword: "break",
+                label: None
+            }
+        );
+        assert_eq!(
+            run_with_error("while true { continue missing; }"),
+            RuntimeError::NoEnclosingLoop {
+                keyword: "continue",
+                label: Some("missing".to_string())
+            }
+        );
+    }
+
+    #[test]
+    fn functions_compare_by_identity() {
+        let output = run_with_output(
+            "fun f() {} fun g() {} h = f;
+            print f == f, f == h, f == g, f != g;
+            print f == 1, nil == f, f == \"f\", len == len, len == f;",
+        );
+
+        assert_eq!(
+            output,
+            "true true false true\nfalse false false true false\n"
+        );
+    }
+
+    #[test]
+    fn closures_from_separate_calls_are_distinct() {
+        let output = run_with_output(
+            "fun make() { fun inner() {} return inner; }
+            a = make(); b = make();
+            print a == a, a == b;",
+        );
+
+        assert_eq!(output, "true false\n");
+    }
+
+    #[test]
+    fn block_runs_in_child_scope() {
+        let output = run_with_output(
+            "var a = \"outer\";
+            { var a = \"inner\"; var b = 1; c = 2; print a, b; }
+            print a, b, c;",
+        );
+
+        assert_eq!(output, "inner 1\nouter nil 2\n");
+    }
+
+    #[test]
+    fn tail_recursion_runs_in_constant_stack() {
+        let output = run_with_output(
+            "fun countdown(n) { if n == 0 return \"done\"; return countdown(n - 1); }
+            print countdown(100000);",
+        );
+
+        assert_eq!(output, "done\n");
+    }
+
+    #[test]
+    fn tail_calls_between_functions() {
+        let output = run_with_output(
+            "fun even(n) { if n == 0 return true; return odd(n - 1); }
+            fun odd(n) { if n == 0 return false; return even(n - 1); }
+            print even(10), odd(7), even(7);",
+        );
+
+        assert_eq!(output, "true true false\n");
+    }
+
+    #[test]
+    fn tail_calls_can_be_disabled() {
+        let tokens = tokens!("fun f(n) { if n == 0 return 0; return f(n - 1); } print f(50);")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let run = |tail_calls| {
+            let output = SharedOutput::default();
+            let result = Interpreter::new(Environment::new())
+                .with_output(Box::new(output.clone()))
+                .with_max_call_depth(10)
+                .with_tail_calls(tail_calls)
+                .run(&program);
+            (result, output.contents())
         };
 
-        let result = Interpreter::evaluate_expression(&interpreter, &expression);
+        assert_eq!(run(true), (Ok(()), "0\n".to_string()));
+        assert_eq!(
+            run(false),
+            (Err(RuntimeError::CallDepthLimit(10)), "".to_string())
+        );
+    }
 
-        assert_eq!(format!("{result:?}"), "Number(1.5)");
+    #[test]
+    fn immediately_invoked_lambda() {
+        let output = run_with_output("print fun(x) { return x + 1; }(4); fun() { print 1; }();");
+
+        assert_eq!(output, "5\n1\n");
+    }
+
+    #[test]
+    fn lambda_closes_over_scope() {
+        let output = run_with_output(
+            "fun adder(n) { return fun(x) { return x + n; }; }
+            add2 = adder(2);
+            print add2(3), add2;",
+        );
+
+        assert_eq!(output, "5 <fn lambda>\n");
+    }
+
+    #[test]
+    fn map_filter_and_reduce() {
+        let output = run_with_output(
+            "print map([1, 2, 3], fun(x) { return x * 2; });
+            large = filter([1, 2, 3, 4], fun(x) { return x > 2; });
+            print large, reduce(large, fun(total, x) { return total + x; }, 10);
+            print map([\"a\", [1, 2]], len);",
+        );
+
+        assert_eq!(output, "[2, 4, 6]\n[3, 4] 17\n[1, 2]\n");
+    }
+
+    #[test]
+    fn map_propagates_callback_errors() {
+        assert_eq!(
+            run_with_error("map([1], fun(x) { return -\"a\"; });"),
+            RuntimeError::InvalidOperand {
+                operation: "negate",
+                operand: "string"
+            }
+        );
+    }
+
+    #[test]
+    fn push_and_pop_modify_lists_in_place() {
+        let output = run_with_output(
+            "a = [1];
+            b = a;
+            print push(a, 2), len(b);
+            a.push(3);
+            print b;
+            print pop(b), a.pop(), a, pop([]);",
+        );
+
+        assert_eq!(output, "[1, 2] 2\n[1, 2, 3]\n3 2 [1] nil\n");
+    }
+
+    #[test]
+    fn list_pushed_into_itself_prints_and_compares() {
+        let output = run_with_output("a = []; push(a, a); print a, [a], a == a, a <= a;");
+
+        assert_eq!(output, "[[...]] [[[...]]] true true\n");
+    }
+
+    #[test]
+    fn distinct_lists_containing_themselves_compare_structurally() {
+        let output = run_with_output(
+            "a = [1]; push(a, a); b = [1]; push(b, b); c = [2]; push(c, c);
+            print a == b, a == c, a != c;",
+        );
+
+        assert_eq!(output, "true false true\n");
+    }
+
+    #[test]
+    fn list_concatenation_builds_a_new_list() {
+        let output = run_with_output("a = [1]; b = a + [2]; push(b, 3); print a, b;");
+
+        assert_eq!(output, "[1] [1, 2, 3]\n");
+    }
+
+    #[test]
+    fn lists_passed_to_functions_are_shared() {
+        let output = run_with_output("fun add(list) { push(list, 1); } a = []; add(a); print a;");
+
+        assert_eq!(output, "[1]\n");
+    }
+
+    #[test]
+    fn copied_lists_are_not_shared() {
+        let output = run_with_output(
+            "a = [1, [2]]; alias = a; copied = copy(a);
+            push(a, 3); push(a[1], 4);
+            print alias; print copied;",
+        );
+
+        assert_eq!(output, "[1, [2, 4], 3]\n[1, [2]]\n");
+    }
+
+    #[test]
+    fn copy_keeps_shared_and_cyclic_lists_shared_within_the_copy() {
+        let output = run_with_output(
+            "inner = [1]; a = [inner, inner]; push(a, a);
+            b = copy(a); push(b[0], 2);
+            print b, inner;",
+        );
+
+        assert_eq!(output, "[[1, 2], [1, 2], [...]] [1]\n");
+    }
+
+    #[test]
+    fn lists_compare_structurally() {
+        let output = run_with_output(
+            "print [1, 2] == [1, 2], [1, 2] == [1, 3], [1, 2] == [1, 2, 3], [1, [2]] == [1, [2]];
+            print [1, 2] != [1, 2], [1, [2]] != [1, [3]], [] == [];
+            print [1] == 1, nil == [], [\"a\"] == \"a\";",
+        );
+
+        assert_eq!(
+            output,
+            "true false false true\nfalse true true\nfalse false false\n"
+        );
+    }
+
+    #[test]
+    fn list_containing_itself_equals_itself() {
+        let output = run_with_output("a = []; push(a, a); print a == a, a != a;");
+
+        assert_eq!(output, "true false\n");
+    }
+
+    #[test]
+    fn registered_native_is_callable_from_scripts() {
+        fn double(arguments: &[Value]) -> RuntimeResult<Value> {
+            match &arguments[0] {
+                Value::Number(value) => Ok(Value::Number(value * 2.0)),
+                value => Err(RuntimeError::Native(format!(
+                    "double expected a number but got {value}"
+                ))),
+            }
+        }
+
+        let tokens = tokens!("print double(21), double; print double(1, 2);")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let mut interpreter =
+            Interpreter::new(Environment::new()).with_output(Box::new(output.clone()));
+        interpreter.register_native("double", 1, double);
+
+        let error = interpreter
+            .run(&program)
+            .expect_err("Program should fail at runtime");
+
+        assert_eq!(output.contents(), "42 <native fn double>\n");
+        assert_eq!(
+            error,
+            RuntimeError::Arity {
+                name: "double".to_string(),
+                expected: 1,
+                got: 2
+            }
+        );
+    }
+
+    #[test]
+    fn registered_native_only_equals_itself() {
+        fn identity(arguments: &[Value]) -> RuntimeResult<Value> {
+            Ok(arguments[0].clone())
+        }
+
+        let output = SharedOutput::default();
+        let mut interpreter =
+            Interpreter::new(Environment::new()).with_output(Box::new(output.clone()));
+        let tokens = tokens!("builtin = len;").expect("Scanner should not fail to parse source");
+        interpreter
+            .run(
+                &Parser::new(&tokens)
+                    .parse()
+                    .expect("Parser should not fail to parse tokens"),
+            )
+            .expect("Program should run without errors");
+
+        // Shares the built-in's name but not its implementation.
+        interpreter.register_native("len", 1, identity);
+        let tokens = tokens!("print builtin == len, len == len, len(\"abc\");")
+            .expect("Scanner should not fail to parse source");
+        interpreter
+            .run(
+                &Parser::new(&tokens)
+                    .parse()
+                    .expect("Parser should not fail to parse tokens"),
+            )
+            .expect("Program should run without errors");
+
+        assert_eq!(output.contents(), "false true abc\n");
+    }
+
+    #[test]
+    fn for_loop_variable_is_scoped_to_the_loop() {
+        let output = run_with_output(
+            "i = \"outer\";
+            for (var i = 0; i < 2; i = i + 1) print i;
+            for (var i = 5; i < 6; i = i + 1) print i;
+            print i;
+            for (var j = 0; j < 1; j = j + 1) {}
+            print j;",
+        );
+
+        assert_eq!(output, "0\n1\n5\nouter\nnil\n");
+    }
+
+    #[test]
+    fn eprint_writes_to_error_output() {
+        let tokens = tokens!("eprint(\"warn\", 1); print \"out\"; eprint();")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let error_output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(Environment::new())
+            .with_output(Box::new(output.clone()))
+            .with_error_output(Box::new(error_output.clone()));
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(output.contents(), "out\n");
+        assert_eq!(error_output.contents(), "warn 1\n\n");
+    }
+
+    #[test]
+    fn env_reads_injected_variables() {
+        let tokens = tokens!("print env(\"HOME\"), env(\"MISSING\");")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let vars = HashMap::from([("HOME".to_string(), "/home/test".to_string())]);
+        let mut interpreter = Interpreter::new(Environment::new())
+            .with_output(Box::new(output.clone()))
+            .with_env_vars(Box::new(move |name| vars.get(name).cloned()));
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(output.contents(), "/home/test nil\n");
+    }
+
+    #[test]
+    fn write_file_then_read_file_round_trips() {
+        let dir = TempDir::new("file_natives");
+
+        let (result, output) = run_with_path(
+            "print write_file(path, \"hello\\n\");
+            print read_file(path);
+            write_file(path, 42);
+            print read_file(path);",
+            &dir.file("round_trip.txt"),
+        );
+
+        assert_eq!(result, Ok(()));
+        assert_eq!(output, "true\nhello\n\n42\n");
+    }
+
+    #[test]
+    fn read_file_reports_io_errors() {
+        let dir = TempDir::new("file_natives_missing");
+        let path = dir.file("missing.txt");
+
+        match run_with_path("read_file(path);", &path).0 {
+            Err(RuntimeError::Io {
+                action: "read",
+                path: error_path,
+                ..
+            }) => assert_eq!(error_path, path),
+            result => panic!("Expected an IO error, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn file_natives_can_be_disabled() {
+        let tokens =
+            tokens!("read_file(\"anything\");").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new()).with_file_access(false);
+
+        let error = interpreter
+            .run(&program)
+            .expect_err("Program should fail at runtime");
+
+        assert_eq!(
+            error,
+            RuntimeError::CapabilityDenied {
+                name: "read_file".to_string(),
+                capability: "file"
+            }
+        );
+    }
+
+    #[test]
+    fn sandbox_denies_capabilities() {
+        let dir = TempDir::new("sandbox");
+        let path = dir.file("sandbox.txt");
+        let scripts = [
+            ("write_file(path, 1);", "write_file", "file"),
+            ("read_file(path);", "read_file", "file"),
+            ("env(\"HOME\");", "env", "environment"),
+            ("exit(0);", "exit", "process"),
+        ];
+
+        for (source, name, capability) in scripts {
+            let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+            let program = Parser::new(&tokens)
+                .parse()
+                .expect("Parser should not fail to parse tokens");
+            let run = |sandbox| {
+                let mut environment = Environment::new();
+                environment.assign("path", Value::String(path.clone()));
+                Interpreter::new(environment)
+                    .with_env_vars(Box::new(|_| None))
+                    .with_sandbox(sandbox)
+                    .run(&program)
+            };
+
+            assert!(
+                matches!(run(false), Ok(()) | Err(RuntimeError::Exit(0))),
+                "{source}"
+            );
+            assert_eq!(
+                run(true),
+                Err(RuntimeError::CapabilityDenied {
+                    name: name.to_string(),
+                    capability
+                }),
+                "{source}"
+            );
+        }
+    }
+
+    #[test]
+    fn scopes_lists_names_in_nested_scopes() {
+        let output = run_with_output(
+            "var x = 1;
+            {
+                var x = 2;
+                fun f(a) {
+                    var b = a;
+                    var s = scopes();
+                    print s[0], s[1], len(s);
+                }
+                { var y = 3; f(y); print scopes()[0], scopes()[1]; }
+            }",
+        );
+
+        assert_eq!(output, "[a, b] [f, x] 3\n[y] [f, x]\n");
+    }
+
+    #[test]
+    fn sandbox_allows_pure_natives() {
+        let tokens = tokens!("print len(\"abc\"), chr(65), between(1, 0, 2);")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(Environment::new())
+            .with_output(Box::new(output.clone()))
+            .with_sandbox(true);
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(output.contents(), "3 A true\n");
+    }
+
+    #[test]
+    fn warnings_flag_expression_statements_without_effects() {
+        let tokens = tokens!("1 + 2; fun f() {} f(); x = 1; x; [x = 2]; -x;")
+            .expect("Scanner should not fail to parse source");
+        // Folding doesn't change how the statements are quoted.
+        let program = optimize(
+            Parser::new(&tokens)
+                .parse()
+                .expect("Parser should not fail to parse tokens"),
+        );
+        let error_output = SharedOutput::default();
+        let mut interpreter = Interpreter::new(Environment::new())
+            .with_error_output(Box::new(error_output.clone()))
+            .with_warnings(true);
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(
+            error_output.contents(),
+            "warning: expression statement has no effect: `1 + 2;`\n\
+             warning: expression statement has no effect: `x;`\n\
+             warning: expression statement has no effect: `-x;`\n"
+        );
+    }
+
+    #[test]
+    fn warnings_are_off_by_default() {
+        let tokens = tokens!("1 + 2;").expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let error_output = SharedOutput::default();
+        let mut interpreter =
+            Interpreter::new(Environment::new()).with_error_output(Box::new(error_output.clone()));
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        assert_eq!(error_output.contents(), "");
+    }
+
+    #[test]
+    fn named_arguments_match_parameters_by_name() {
+        let output = run_with_output(
+            "fun point(x, y, z) { return (x, y, z); }
+            print point(z: 3, x: 1, y: 2);
+            print point(1, z: 3, y: 2);
+            fun add(a, b) { return a + b; }
+            fun twice(a) { return add(b: a, a: a); }
+            print twice(4);",
+        );
+
+        assert_eq!(output, "(1, 2, 3)\n(1, 2, 3)\n8\n");
+    }
+
+    #[test]
+    fn named_arguments_are_checked() {
+        let source = "fun f(a, b) {}";
+
+        assert_eq!(
+            run_with_error(&format!("{source} f(1, c: 2);")),
+            RuntimeError::UnexpectedArgument {
+                name: "f".to_string(),
+                argument: "c".to_string()
+            }
+        );
+        assert_eq!(
+            run_with_error(&format!("{source} f(1, a: 2);")),
+            RuntimeError::DuplicateArgument {
+                name: "f".to_string(),
+                argument: "a".to_string()
+            }
+        );
+        assert_eq!(
+            run_with_error(&format!("{source} f(b: 2);")),
+            RuntimeError::Arity {
+                name: "f".to_string(),
+                expected: 2,
+                got: 1
+            }
+        );
+        assert_eq!(
+            run_with_error("len(value: \"abc\");"),
+            RuntimeError::UnexpectedArgument {
+                name: "len".to_string(),
+                argument: "value".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn default_parameters_are_used_when_not_passed() {
+        let output = run_with_output(
+            "fun greet(name, greeting = \"Hello\") { return greeting + \", \" + name; }
+            print greet(\"Ada\");
+            print greet(\"Ada\", \"Hi\");
+            fun range(start, end = start + 2, step = 1) { return (start, end, step); }
+            print range(1);
+            print range(1, step: 2);",
+        );
+
+        assert_eq!(output, "Hello, Ada\nHi, Ada\n(1, 3, 1)\n(1, 3, 2)\n");
+    }
+
+    #[test]
+    fn default_parameters_widen_arity() {
+        let source = "fun f(a, b = 1) {}";
+
+        assert_eq!(
+            run_with_error(&format!("{source} f();")),
+            RuntimeError::ArityRange {
+                name: "f".to_string(),
+                min: 1,
+                max: Some(2),
+                got: 0
+            }
+        );
+        assert_eq!(
+            run_with_error(&format!("{source} f(1, 2, 3);")),
+            RuntimeError::ArityRange {
+                name: "f".to_string(),
+                min: 1,
+                max: Some(2),
+                got: 3
+            }
+        );
+    }
+
+    #[test]
+    fn rest_parameter_collects_extra_arguments() {
+        let output = run_with_output(
+            "fun sum(...xs) {
+                var total = 0;
+                for (var i = 0; i < len(xs); i = i + 1) { total = total + xs[i]; }
+                return total;
+            }
+            print sum();
+            print sum(1);
+            print sum(1, 2, 3);
+            fun tag(name, ...values) { return (name, values); }
+            print tag(\"a\");
+            print tag(\"a\", 1, 2);",
+        );
+
+        assert_eq!(output, "0\n1\n6\n(a, [])\n(a, [1, 2])\n");
+    }
+
+    #[test]
+    fn rest_parameter_requires_at_least_the_other_parameters() {
+        let error = run_with_error("fun f(a, ...rest) {} f();");
+
+        assert_eq!(
+            error,
+            RuntimeError::ArityRange {
+                name: "f".to_string(),
+                min: 1,
+                max: None,
+                got: 0
+            }
+        );
+        assert_eq!(
+            error.to_string(),
+            "f expected at least 1 arguments but got 0"
+        );
+    }
+
+    #[test]
+    fn interpolated_strings() {
+        let output = run_with_output(
+            "name = \"Ada\";
+            print \"x=${1+1}\";
+            print \"Hello ${name}! ${len(\"${name}s\")} ${[1, nil]}\";
+            print \"\\${name}\";",
+        );
+
+        assert_eq!(output, "x=2\nHello Ada! 4 [1, nil]\n${name}\n");
+    }
+
+    #[test]
+    fn failed_declaration_is_the_innermost_one() {
+        let tokens = tokens!("fun f(a, b) { print a - b; } f(1, \"x\");")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter =
+            Interpreter::new(Environment::new()).with_output(Box::new(io::sink()));
+
+        interpreter
+            .run(&program)
+            .expect_err("Program should fail at runtime");
+
+        assert_eq!(interpreter.failed_declaration(), Some("print a - b;"));
+
+        interpreter
+            .run(&Program::new())
+            .expect("Program should run without errors");
+
+        assert_eq!(interpreter.failed_declaration(), None);
+    }
+
+    #[test]
+    fn failed_declaration_is_quoted_as_written_before_folding() {
+        let tokens = tokens!("fun f(x) { return 2e0 * 3  -  x; } f(\"s\");")
+            .expect("Scanner should not fail to parse source");
+        let program = optimize(
+            Parser::new(&tokens)
+                .parse()
+                .expect("Parser should not fail to parse tokens"),
+        );
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        interpreter
+            .run(&program)
+            .expect_err("Program should fail at runtime");
+
+        assert_eq!(
+            interpreter.failed_declaration(),
+            Some("return 2e0 * 3  -  x;")
+        );
+    }
+
+    #[test]
+    fn failed_declaration_is_quoted_by_its_first_line() {
+        let tokens = tokens!("var x = \"s\";\nif x - 1 > 0 {\n    print x;\n}")
+            .expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        interpreter
+            .run(&program)
+            .expect_err("Program should fail at runtime");
+
+        assert_eq!(interpreter.failed_declaration(), Some("if x - 1 > 0 { ..."));
+    }
+
+    #[test]
+    fn coalesce_uses_right_operand_only_for_nil() {
+        let output = run_with_output("print nil ?? 5, 3 ?? 5, false ?? 5, nil ?? nil ?? 7;");
+
+        assert_eq!(output, "5 3 false 7\n");
+    }
+
+    #[test]
+    fn coalesce_short_circuits() {
+        let output = run_with_output(
+            "fun fallback() { print \"evaluated\"; return 5; }
+            print 3 ?? fallback();",
+        );
+
+        assert_eq!(output, "3\n");
+    }
+
+    #[test]
+    fn optional_get_on_nil_is_nil() {
+        let output = run_with_output("var m = nil; print nil?.x, m?.key, m?.len(missing());");
+
+        assert_eq!(output, "nil nil nil\n");
+    }
+
+    #[test]
+    fn optional_get_on_a_map_reads_the_key() {
+        let output = run_with_output("var m = {key: 1}; print m?.key, m?.missing, m?.len();");
+
+        assert_eq!(output, "1 nil 1\n");
+    }
+
+    #[test]
+    fn optional_get_only_guards_against_nil() {
+        let error = run_with_error("print 1?.x;");
+
+        assert_eq!(
+            error,
+            RuntimeError::NoProperty {
+                type_name: "number",
+                name: "x".to_string()
+            }
+        );
+    }
+
+    #[test]
+    fn if_expression_yields_the_value_of_the_branch_taken() {
+        let output = run_with_output(
+            "var c = true;
+            var x = if c { 1 } else { 2 };
+            var y = if !c { 1 } else { var t = 2; t * 2 };
+            print x, y;",
+        );
+
+        assert_eq!(output, "1 4\n");
+    }
+
+    #[test]
+    fn if_expression_chains_and_defaults_to_nil() {
+        let output = run_with_output(
+            "fun sign(n) { return if n < 0 { -1 } else if n == 0 { 0 } else { 1 }; }
+            print sign(-5), sign(0), sign(5), if nil { 1 };",
+        );
+
+        assert_eq!(output, "-1 0 1 nil\n");
+    }
+
+    #[test]
+    fn return_and_break_inside_if_expression_branch() {
+        let output = run_with_output(
+            "fun f(x) { var y = if x { return 1; } else { 2 }; print \"after\"; return y + 10; }
+            print f(true), f(false);
+            var n = 0;
+            while true { n = n + 1; var v = if true { break; } else { 1 }; }
+            print n;",
+        );
+
+        assert_eq!(output, "after\n1 12\n1\n");
+    }
+
+    #[test]
+    fn return_inside_if_expression_in_native_callback_returns_from_callback() {
+        let output = run_with_output(
+            "fun f(xs) {
+                var ys = map(xs, fun(x) { var y = if x > 1 { return x * 10; } else { x }; return y + 100; });
+                print \"after\";
+                return ys;
+            }
+            print f([1, 2, 3]);",
+        );
+
+        assert_eq!(output, "after\n[101, 20, 30]\n");
     }
 }
@@ -0,0 +1,182 @@
+use std::{
+    cell::{Ref, RefCell},
+    cmp::Ordering,
+    collections::HashSet,
+    fmt::{self, Debug, Formatter},
+    rc::Rc,
+};
+
+use super::value::Value;
+
+/// A list of values. Lists are shared rather than copied: assigning a list to
+/// another variable, or passing it to a function, refers to the same list, so
+/// pushing to or popping from it through one is visible through all of them.
+/// Operations which build a new list, such as `+` and `map`, never alias their
+/// operands.
+#[derive(Clone, Default)]
+pub struct List(Rc<RefCell<Vec<Value>>>);
+
+impl List {
+    pub fn new(values: Vec<Value>) -> Self {
+        Self(Rc::new(RefCell::new(values)))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, Vec<Value>> {
+        self.0.borrow()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.borrow().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.borrow().is_empty()
+    }
+
+    pub fn push(&self, value: Value) {
+        self.0.borrow_mut().push(value);
+    }
+
+    pub fn pop(&self) -> Option<Value> {
+        self.0.borrow_mut().pop()
+    }
+
+    /// Copies the elements into a new `Vec`, which isn't shared with the list.
+    pub fn to_vec(&self) -> Vec<Value> {
+        self.0.borrow().clone()
+    }
+
+    /// Marks the list as being displayed until the returned guard is dropped.
+    /// Returns `None` if it already is, i.e. the list contains itself and
+    /// should be displayed as `[...]` rather than recursed into.
+    pub fn start_display(&self) -> Option<Visit> {
+        Visit::start(Walk::Display, self, self)
+    }
+
+    /// Identifies the list: clones of it share its address, copies don't.
+    pub fn address(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+/// A recursive walk over nested lists, which can loop forever on a list that
+/// contains itself unless it keeps track of the lists it's in the middle of.
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+enum Walk {
+    Display,
+    Equality,
+    Ordering,
+}
+
+thread_local! {
+    /// The lists (or pairs of lists) each walk is currently inside of.
+    static VISITING: RefCell<HashSet<(Walk, usize, usize)>> = RefCell::default();
+}
+
+/// Proof that a walk has entered a list, which leaves it again when dropped.
+pub struct Visit((Walk, usize, usize));
+
+impl Visit {
+    fn start(walk: Walk, left: &List, right: &List) -> Option<Self> {
+        let key = (walk, left.address(), right.address());
+        VISITING
+            .with(|visiting| visiting.borrow_mut().insert(key))
+            .then_some(Self(key))
+    }
+}
+
+impl Drop for Visit {
+    fn drop(&mut self) {
+        VISITING.with(|visiting| visiting.borrow_mut().remove(&self.0));
+    }
+}
+
+impl From<Vec<Value>> for List {
+    fn from(values: Vec<Value>) -> Self {
+        Self::new(values)
+    }
+}
+
+impl FromIterator<Value> for List {
+    fn from_iter<T: IntoIterator<Item = Value>>(iter: T) -> Self {
+        Self::new(iter.into_iter().collect())
+    }
+}
+
+impl Debug for List {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self.start_display() {
+            Some(_visit) => self.0.borrow().fmt(f),
+            None => write!(f, "[...]"),
+        }
+    }
+}
+
+// Lists are equal when their elements are, recursing into nested lists. A list
+// is always equal to itself. Comparing a pair of lists again from inside
+// themselves can't find any difference the outer comparison won't, so the pair
+// is treated as equal there, which stops lists containing themselves (or each
+// other) from recursing forever.
+impl PartialEq for List {
+    fn eq(&self, other: &Self) -> bool {
+        if Rc::ptr_eq(&self.0, &other.0) {
+            return true;
+        }
+        match Visit::start(Walk::Equality, self, other) {
+            Some(_visit) => *self.0.borrow() == *other.0.borrow(),
+            None => true,
+        }
+    }
+}
+
+// Comparing a pair of lists again from inside themselves can't tell them
+// apart any further, so the pair is treated as equal at that point.
+impl PartialOrd for List {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        match Visit::start(Walk::Ordering, self, other) {
+            Some(_visit) => self.0.borrow().partial_cmp(&*other.0.borrow()),
+            None => Some(Ordering::Equal),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn clones_share_elements() {
+        let list = List::new(vec![Value::Number(1.0)]);
+        let alias = list.clone();
+
+        alias.push(Value::Number(2.0));
+
+        assert_eq!(list.len(), 2);
+        assert_eq!(list.pop(), Some(Value::Number(2.0)));
+        assert_eq!(alias.to_vec(), [Value::Number(1.0)]);
+    }
+
+    #[test]
+    fn list_containing_itself_displays_as_ellipsis() {
+        let list = List::new(vec![Value::Number(1.0)]);
+        list.push(Value::List(list.clone()));
+
+        assert_eq!(Value::List(list.clone()).to_string(), "[1, [...]]");
+        assert_eq!(format!("{list:?}"), "[Number(1.0), List([...])]");
+        assert_eq!(list.partial_cmp(&list), Some(Ordering::Equal));
+    }
+
+    #[test]
+    fn distinct_cyclic_lists_compare_structurally() {
+        let left = List::new(vec![Value::Number(1.0)]);
+        left.push(Value::List(left.clone()));
+        let right = List::new(vec![Value::Number(1.0)]);
+        right.push(Value::List(right.clone()));
+        let other = List::new(vec![Value::Number(2.0)]);
+        other.push(Value::List(other.clone()));
+
+        assert_eq!(left, right);
+        assert_ne!(left, other);
+        assert_eq!(left.partial_cmp(&other), Some(Ordering::Less));
+    }
+}
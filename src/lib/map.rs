@@ -0,0 +1,93 @@
+use std::cmp::Ordering;
+
+use super::value::Value;
+
+/// A map from string keys to values which remembers the order keys were
+/// first inserted in, so that displaying and iterating over it is
+/// deterministic.
+#[derive(Debug, Clone, Default)]
+pub struct Map(Vec<(String, Value)>);
+
+impl Map {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn get(&self, key: &str) -> Option<&Value> {
+        self.0
+            .iter()
+            .find(|(existing, _)| existing == key)
+            .map(|(_, value)| value)
+    }
+
+    /// Inserts or updates `key`. Updating a key keeps its original position.
+    pub fn insert(&mut self, key: String, value: Value) {
+        match self.0.iter_mut().find(|(existing, _)| *existing == key) {
+            Some((_, existing)) => *existing = value,
+            None => self.0.push((key, value)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&String, &Value)> {
+        self.0.iter().map(|(key, value)| (key, value))
+    }
+}
+
+// Maps with the same entries are equal regardless of insertion order.
+impl PartialEq for Map {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len()
+            && self
+                .iter()
+                .all(|(key, value)| other.get(key) == Some(value))
+    }
+}
+
+impl PartialOrd for Map {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn insert_preserves_first_insertion_order() {
+        let mut map = Map::new();
+        map.insert("b".to_string(), Value::Number(1.0));
+        map.insert("a".to_string(), Value::Number(2.0));
+        map.insert("b".to_string(), Value::Number(3.0));
+
+        let entries: Vec<_> = map.iter().collect();
+
+        assert_eq!(
+            entries,
+            [
+                (&"b".to_string(), &Value::Number(3.0)),
+                (&"a".to_string(), &Value::Number(2.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn equality_ignores_order() {
+        let mut left = Map::new();
+        left.insert("a".to_string(), Value::Number(1.0));
+        left.insert("b".to_string(), Value::Number(2.0));
+        let mut right = Map::new();
+        right.insert("b".to_string(), Value::Number(2.0));
+        right.insert("a".to_string(), Value::Number(1.0));
+
+        assert_eq!(left, right);
+    }
+}
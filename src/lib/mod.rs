@@ -1,6 +1,20 @@
+#[cfg(test)]
+mod bench;
 pub mod environment;
 pub mod error;
+pub mod formatter;
+pub mod function;
+pub mod interner;
 pub mod interpreter;
+pub mod list;
+pub mod map;
+pub mod natives;
+pub mod optimizer;
 pub mod parser;
+pub mod prelude;
 pub mod scanner;
+#[cfg(test)]
+mod testing;
 pub mod utils;
+pub mod value;
+pub mod visitor;
@@ -0,0 +1,1055 @@
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    fmt::{self, Debug, Formatter},
+    fs, ptr,
+};
+
+use super::{
+    environment::Environment,
+    error::{RuntimeError, RuntimeResult},
+    list::List,
+    value::{check_repeat_length, group_digits, Value},
+};
+
+pub type NativeFunctionResult = RuntimeResult<Value>;
+
+/// A native which computes its result from its arguments alone.
+pub type NativeFn = fn(&[Value]) -> NativeFunctionResult;
+
+/// Lets natives call back into the interpreter running them, e.g. to call a
+/// function they were passed.
+pub trait Caller {
+    fn call(&mut self, callee: Value, arguments: Vec<Value>) -> RuntimeResult<Value>;
+    fn is_truthy(&self, value: &Value) -> bool;
+    /// Writes the values to the error output, formatted as `print` would.
+    fn print_error(&mut self, values: &[Value]);
+    /// Looks up an environment variable of the host.
+    fn env_var(&self, name: &str) -> Option<String>;
+    /// Whether natives may use `capability`.
+    fn allows(&self, capability: Capability) -> bool;
+    /// Lists the names defined in each scope the native was called from,
+    /// from the innermost out to the global scope.
+    fn scope_report(&self) -> Vec<Vec<String>>;
+}
+
+/// Something natives can do beyond computing values, which a sandboxed
+/// interpreter denies.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Capability {
+    /// Reading and writing files.
+    Files,
+    /// Affecting the process running the script, e.g. by exiting it.
+    Process,
+    /// Reading environment variables.
+    Environment,
+}
+
+impl Capability {
+    pub fn name(self) -> &'static str {
+        match self {
+            Capability::Files => "file",
+            Capability::Process => "process",
+            Capability::Environment => "environment",
+        }
+    }
+}
+
+/// Fails with `RuntimeError::CapabilityDenied` unless the native `name` may
+/// use `capability`.
+fn require(name: &str, caller: &dyn Caller, capability: Capability) -> RuntimeResult<()> {
+    if caller.allows(capability) {
+        return Ok(());
+    }
+    Err(RuntimeError::CapabilityDenied {
+        name: name.to_string(),
+        capability: capability.name(),
+    })
+}
+
+/// The Rust implementation of a native.
+#[derive(Clone, Copy)]
+pub enum NativeBody {
+    /// Computes its result from its arguments alone.
+    Pure(NativeFn),
+    /// Needs the interpreter, typically to call a function argument.
+    Callback(fn(&mut dyn Caller, &[Value]) -> NativeFunctionResult),
+}
+
+impl PartialEq for NativeBody {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (NativeBody::Pure(left), NativeBody::Pure(right)) => ptr::fn_addr_eq(*left, *right),
+            (NativeBody::Callback(left), NativeBody::Callback(right)) => {
+                ptr::fn_addr_eq(*left, *right)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The type a typed native requires one of its arguments to have.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ParameterType {
+    Any,
+    Number,
+    String,
+    List,
+    /// A user defined or native function.
+    Function,
+}
+
+impl ParameterType {
+    pub fn name(self) -> &'static str {
+        match self {
+            ParameterType::Any => "any value",
+            ParameterType::Number => "number",
+            ParameterType::String => "string",
+            ParameterType::List => "list",
+            ParameterType::Function => "function",
+        }
+    }
+
+    pub fn accepts(self, value: &Value) -> bool {
+        matches!(
+            (self, value),
+            (ParameterType::Any, _)
+                | (ParameterType::Number, Value::Number(_))
+                | (ParameterType::String, Value::String(_))
+                | (ParameterType::List, Value::List(_))
+                | (
+                    ParameterType::Function,
+                    Value::Function(_) | Value::NativeFunction(_)
+                )
+        )
+    }
+}
+
+/// The arguments a native accepts, which are checked before it's called so
+/// that natives don't each have to validate them.
+#[derive(Clone, Copy)]
+pub enum Parameters {
+    /// Any number of arguments, which the native validates itself.
+    Variadic,
+    /// Exactly this many arguments, of any type.
+    Count(usize),
+    /// Exactly one argument of each type, in order.
+    Typed(&'static [ParameterType]),
+}
+
+impl Parameters {
+    /// The number of arguments required, or `None` for variadic natives.
+    #[allow(dead_code)] // Natives check their own arity in `check_arguments`.
+    pub fn arity(self) -> Option<usize> {
+        match self {
+            Parameters::Variadic => None,
+            Parameters::Count(count) => Some(count),
+            Parameters::Typed(types) => Some(types.len()),
+        }
+    }
+}
+
+/// A function implemented in Rust which can be called from scripts.
+#[derive(Clone)]
+pub struct NativeFunction {
+    /// Borrowed for the built-in natives, owned for those registered by the
+    /// host.
+    pub name: Cow<'static, str>,
+    pub parameters: Parameters,
+    pub function: NativeBody,
+}
+
+impl NativeFunction {
+    pub fn call(&self, caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+        self.check_arguments(arguments)?;
+        match self.function {
+            NativeBody::Pure(function) => function(arguments),
+            NativeBody::Callback(function) => function(caller, arguments),
+        }
+    }
+
+    /// Calls the function as a method, passing `receiver` as the first
+    /// argument. The parameters of a method don't include its receiver.
+    pub fn call_method(
+        &self,
+        caller: &mut dyn Caller,
+        receiver: Value,
+        arguments: &[Value],
+    ) -> NativeFunctionResult {
+        let arguments: Vec<Value> = [receiver]
+            .into_iter()
+            .chain(arguments.iter().cloned())
+            .collect();
+        self.check_arguments(&arguments[1..])?;
+        match self.function {
+            NativeBody::Pure(function) => function(&arguments),
+            NativeBody::Callback(function) => function(caller, &arguments),
+        }
+    }
+
+    fn check_arguments(&self, arguments: &[Value]) -> RuntimeResult<()> {
+        let types = match self.parameters {
+            Parameters::Variadic => return Ok(()),
+            Parameters::Count(count) => return self.check_arity(count, arguments),
+            Parameters::Typed(types) => types,
+        };
+        self.check_arity(types.len(), arguments)?;
+
+        match types
+            .iter()
+            .zip(arguments)
+            .position(|(expected, argument)| !expected.accepts(argument))
+        {
+            Some(index) => Err(RuntimeError::TypeMismatch {
+                name: self.name.to_string(),
+                position: index + 1,
+                expected: types[index].name(),
+                got: arguments[index].type_name(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    fn check_arity(&self, expected: usize, arguments: &[Value]) -> RuntimeResult<()> {
+        if arguments.len() == expected {
+            return Ok(());
+        }
+        Err(RuntimeError::Arity {
+            name: self.name.to_string(),
+            expected,
+            got: arguments.len(),
+        })
+    }
+}
+
+impl Debug for NativeFunction {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        write!(f, "NativeFunction({})", self.name)
+    }
+}
+
+// Natives are equal when they have the same name and implementation, so a
+// native registered under a built-in's name isn't mistaken for the built-in.
+impl PartialEq for NativeFunction {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name && self.function == other.function
+    }
+}
+
+impl PartialOrd for NativeFunction {
+    fn partial_cmp(&self, _other: &Self) -> Option<Ordering> {
+        None
+    }
+}
+
+const NATIVES: &[NativeFunction] = &[
+    NativeFunction {
+        name: Cow::Borrowed("format"),
+        parameters: Parameters::Variadic,
+        function: NativeBody::Pure(format),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("len"),
+        parameters: Parameters::Count(1),
+        function: NativeBody::Pure(len),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("exit"),
+        parameters: Parameters::Typed(&[ParameterType::Number]),
+        function: NativeBody::Callback(exit),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("between"),
+        parameters: Parameters::Typed(&[
+            ParameterType::Number,
+            ParameterType::Number,
+            ParameterType::Number,
+        ]),
+        function: NativeBody::Pure(between),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("repeat"),
+        parameters: Parameters::Typed(&[ParameterType::String, ParameterType::Number]),
+        function: NativeBody::Pure(repeat),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("push"),
+        parameters: Parameters::Typed(&[ParameterType::List, ParameterType::Any]),
+        function: NativeBody::Pure(push),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("pop"),
+        parameters: Parameters::Typed(&[ParameterType::List]),
+        function: NativeBody::Pure(pop),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("copy"),
+        parameters: Parameters::Count(1),
+        function: NativeBody::Pure(copy),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("eprint"),
+        parameters: Parameters::Variadic,
+        function: NativeBody::Callback(eprint),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("scopes"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Callback(scopes),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("env"),
+        parameters: Parameters::Typed(&[ParameterType::String]),
+        function: NativeBody::Callback(env),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("read_file"),
+        parameters: Parameters::Typed(&[ParameterType::String]),
+        function: NativeBody::Callback(read_file),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("write_file"),
+        parameters: Parameters::Typed(&[ParameterType::String, ParameterType::Any]),
+        function: NativeBody::Callback(write_file),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("format_number"),
+        parameters: Parameters::Typed(&[ParameterType::Number]),
+        function: NativeBody::Pure(format_number),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("chr"),
+        parameters: Parameters::Typed(&[ParameterType::Number]),
+        function: NativeBody::Pure(chr),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("ord"),
+        parameters: Parameters::Typed(&[ParameterType::String]),
+        function: NativeBody::Pure(ord),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("map"),
+        parameters: Parameters::Typed(&[ParameterType::List, ParameterType::Function]),
+        function: NativeBody::Callback(map),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("filter"),
+        parameters: Parameters::Typed(&[ParameterType::List, ParameterType::Function]),
+        function: NativeBody::Callback(filter),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("reduce"),
+        parameters: Parameters::Typed(&[
+            ParameterType::List,
+            ParameterType::Function,
+            ParameterType::Any,
+        ]),
+        function: NativeBody::Callback(reduce),
+    },
+];
+
+/// Methods available on strings, e.g. `"hi".upper()`. Methods are only
+/// looked up for receivers of their type, so they don't check the receiver.
+const STRING_METHODS: &[NativeFunction] = &[
+    NativeFunction {
+        name: Cow::Borrowed("len"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(len),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("upper"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(upper),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("lower"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(lower),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("trim"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(trim),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("repeat"),
+        parameters: Parameters::Typed(&[ParameterType::Number]),
+        function: NativeBody::Pure(repeat),
+    },
+];
+
+/// Methods available on lists, e.g. `[1, 2].len()`.
+const LIST_METHODS: &[NativeFunction] = &[
+    NativeFunction {
+        name: Cow::Borrowed("len"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(len),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("contains"),
+        parameters: Parameters::Count(1),
+        function: NativeBody::Pure(contains),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("push"),
+        parameters: Parameters::Count(1),
+        function: NativeBody::Pure(push),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("pop"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(pop),
+    },
+];
+
+/// Methods available on maps. Keys and values are listed in insertion order.
+const MAP_METHODS: &[NativeFunction] = &[
+    NativeFunction {
+        name: Cow::Borrowed("len"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(len),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("keys"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(keys),
+    },
+    NativeFunction {
+        name: Cow::Borrowed("values"),
+        parameters: Parameters::Count(0),
+        function: NativeBody::Pure(values),
+    },
+];
+
+/// Looks up a built-in method for the type of `receiver`.
+pub fn method(receiver: &Value, name: &str) -> Option<&'static NativeFunction> {
+    let methods = match receiver {
+        Value::String(_) => STRING_METHODS,
+        Value::List(_) => LIST_METHODS,
+        Value::Map(_) => MAP_METHODS,
+        _ => &[],
+    };
+
+    methods.iter().find(|method| method.name == name)
+}
+
+pub fn define_natives(environment: &mut Environment) {
+    for native in NATIVES {
+        environment.assign(&native.name, Value::NativeFunction(native.clone()));
+    }
+}
+
+/// Replaces each `{}` in the template with the next argument.
+fn format(arguments: &[Value]) -> NativeFunctionResult {
+    let (template, values) = match arguments.split_first() {
+        Some((Value::String(template), values)) => (template, values),
+        _ => {
+            return Err(RuntimeError::Native(
+                "format expected a template string as its first argument".to_string(),
+            ))
+        }
+    };
+
+    let placeholders = template.matches("{}").count();
+    if placeholders != values.len() {
+        return Err(RuntimeError::Native(format!(
+            "format template has {placeholders} placeholders but got {} arguments",
+            values.len()
+        )));
+    }
+
+    let mut result = String::new();
+    for (i, part) in template.split("{}").enumerate() {
+        if i > 0 {
+            result += &values[i - 1].to_string();
+        }
+        result += part;
+    }
+
+    Ok(Value::String(result))
+}
+
+/// Returns the number of elements in a list or tuple, entries in a map, or
+/// characters in a string.
+fn len(arguments: &[Value]) -> NativeFunctionResult {
+    match &arguments[0] {
+        Value::List(list) => Ok(Value::Number(list.len() as f32)),
+        Value::Tuple(values) => Ok(Value::Number(values.len() as f32)),
+        Value::Map(map) => Ok(Value::Number(map.len() as f32)),
+        Value::String(value) => Ok(Value::Number(value.chars().count() as f32)),
+        value => Err(RuntimeError::Native(format!(
+            "len expected a list, tuple, map or string but got {value}"
+        ))),
+    }
+}
+
+fn upper(arguments: &[Value]) -> NativeFunctionResult {
+    map_string(&arguments[0], str::to_uppercase)
+}
+
+fn lower(arguments: &[Value]) -> NativeFunctionResult {
+    map_string(&arguments[0], str::to_lowercase)
+}
+
+fn trim(arguments: &[Value]) -> NativeFunctionResult {
+    map_string(&arguments[0], |value| value.trim().to_string())
+}
+
+fn map_string(value: &Value, f: fn(&str) -> String) -> NativeFunctionResult {
+    Ok(Value::String(f(value.as_string().unwrap_or_default())))
+}
+
+/// Repeats a string `count` times. Unlike `*`, a fractional count isn't
+/// truncated: the fractional part repeats that proportion of the string, so
+/// `repeat("abc", 1.5)` is `"abca"`.
+fn repeat(arguments: &[Value]) -> NativeFunctionResult {
+    let string = arguments[0].as_string().unwrap_or_default();
+    let count = arguments[1].as_number().unwrap_or_default();
+    if count < 0.0 || count.is_nan() {
+        return Err(RuntimeError::Native(format!(
+            "repeat expected a non-negative count but got {count}"
+        )));
+    }
+
+    check_repeat_length(string, count)?;
+
+    let chars = string.chars().count();
+    let prefix = (count.fract() * chars as f32) as usize;
+    let mut result = string.repeat(count as usize);
+    result.extend(string.chars().take(prefix));
+
+    Ok(Value::String(result))
+}
+
+/// Returns the number as a string with its digits grouped by commas, e.g.
+/// `"1,000,000"`, which is easier to read than the usual `1000000`.
+fn format_number(arguments: &[Value]) -> NativeFunctionResult {
+    let value = arguments[0].as_number().unwrap_or_default();
+    Ok(Value::String(group_digits(value)))
+}
+
+/// Returns the one character string for a Unicode code point.
+fn chr(arguments: &[Value]) -> NativeFunctionResult {
+    let code = arguments[0].as_number().unwrap_or_default();
+    let character = match code {
+        code if code.fract() == 0.0 && code >= 0.0 => char::from_u32(code as u32),
+        _ => None,
+    };
+
+    match character {
+        Some(character) => Ok(Value::String(character.to_string())),
+        None => Err(RuntimeError::Native(format!(
+            "chr expected a valid code point but got {code}"
+        ))),
+    }
+}
+
+/// Returns the Unicode code point of a one character string.
+fn ord(arguments: &[Value]) -> NativeFunctionResult {
+    let mut chars = arguments[0].as_string().unwrap_or_default().chars();
+
+    match (chars.next(), chars.next()) {
+        (Some(character), None) => Ok(Value::Number(character as u32 as f32)),
+        _ => Err(RuntimeError::Native(format!(
+            "ord expected a single character string but got {}",
+            arguments[0].escaped()
+        ))),
+    }
+}
+
+fn keys(arguments: &[Value]) -> NativeFunctionResult {
+    let Value::Map(map) = &arguments[0] else {
+        unreachable!("keys is only a method of maps")
+    };
+    Ok(Value::List(
+        map.iter()
+            .map(|(key, _)| Value::String(key.to_string()))
+            .collect(),
+    ))
+}
+
+fn values(arguments: &[Value]) -> NativeFunctionResult {
+    let Value::Map(map) = &arguments[0] else {
+        unreachable!("values is only a method of maps")
+    };
+    Ok(Value::List(
+        map.iter().map(|(_, value)| value.clone()).collect(),
+    ))
+}
+
+/// Returns whether the list contains an element equal to the second argument.
+fn contains(arguments: &[Value]) -> NativeFunctionResult {
+    let list = list_argument(arguments);
+    Ok(Value::Boolean(list.borrow().contains(&arguments[1])))
+}
+
+/// Appends the second argument to the list in place, returning the list.
+fn push(arguments: &[Value]) -> NativeFunctionResult {
+    let list = list_argument(arguments);
+    list.push(arguments[1].clone());
+    Ok(Value::List(list.clone()))
+}
+
+/// Removes and returns the last element of the list, or nil if it's empty.
+fn pop(arguments: &[Value]) -> NativeFunctionResult {
+    Ok(list_argument(arguments).pop().unwrap_or(Value::Nil))
+}
+
+/// Returns the list the first parameter of a native or method of lists is
+/// typed as.
+fn list_argument(arguments: &[Value]) -> &List {
+    let Value::List(list) = &arguments[0] else {
+        unreachable!("The first argument should have been checked to be a list")
+    };
+    list
+}
+
+/// Returns a deep copy of the value, whose lists aren't shared with it.
+fn copy(arguments: &[Value]) -> NativeFunctionResult {
+    Ok(arguments[0].deep_clone())
+}
+
+/// Returns whether `x` is within the inclusive range `lo..=hi`, as a
+/// readable alternative to `lo <= x and x <= hi`.
+fn between(arguments: &[Value]) -> NativeFunctionResult {
+    let [x, lo, hi] = [0, 1, 2].map(|index| arguments[index].as_number().unwrap_or_default());
+    Ok(Value::Boolean(lo <= x && x <= hi))
+}
+
+/// Prints its arguments to the error output rather than the program's output,
+/// e.g. for diagnostics.
+fn eprint(caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+    caller.print_error(arguments);
+    Ok(Value::Nil)
+}
+
+/// Returns a list of the names defined in each scope around the call, from
+/// the innermost out to the global scope, for debugging scoping problems.
+fn scopes(caller: &mut dyn Caller, _arguments: &[Value]) -> NativeFunctionResult {
+    let scopes = caller
+        .scope_report()
+        .into_iter()
+        .map(|names| Value::List(names.into_iter().map(Value::String).collect()));
+    Ok(Value::List(scopes.collect()))
+}
+
+/// Returns the value of an environment variable, or nil if it isn't set.
+fn env(caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+    require("env", caller, Capability::Environment)?;
+    let name = arguments[0].as_string().unwrap_or_default();
+    Ok(caller.env_var(name).map_or(Value::Nil, Value::String))
+}
+
+/// Returns the contents of a file as a string.
+fn read_file(caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+    let path = file_path("read_file", caller, arguments)?;
+    fs::read_to_string(path)
+        .map(Value::String)
+        .map_err(|error| io_error("read", path, error))
+}
+
+/// Writes the second argument to a file, replacing its contents, and returns
+/// true. Values other than strings are written as `print` would show them.
+fn write_file(caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+    let path = file_path("write_file", caller, arguments)?;
+    fs::write(path, arguments[1].to_string())
+        .map(|()| Value::Boolean(true))
+        .map_err(|error| io_error("write", path, error))
+}
+
+/// Returns the path file natives are called with, provided file access is
+/// allowed.
+fn file_path<'a>(
+    name: &str,
+    caller: &dyn Caller,
+    arguments: &'a [Value],
+) -> RuntimeResult<&'a str> {
+    require(name, caller, Capability::Files)?;
+    Ok(arguments[0].as_string().unwrap_or_default())
+}
+
+fn io_error(action: &'static str, path: &str, error: std::io::Error) -> RuntimeError {
+    RuntimeError::Io {
+        action,
+        path: path.to_string(),
+        message: error.to_string(),
+    }
+}
+
+/// Returns a new list with `f(element)` for each element of the list.
+fn map(caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+    let (values, function) = list_and_function(arguments);
+    let mut result = Vec::with_capacity(values.len());
+    for value in values {
+        result.push(caller.call(function.clone(), vec![value])?);
+    }
+    Ok(Value::List(List::new(result)))
+}
+
+/// Returns a new list with the elements for which `predicate(element)` is
+/// truthy.
+fn filter(caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+    let (values, predicate) = list_and_function(arguments);
+    let mut result = vec![];
+    for value in values {
+        let keep = caller.call(predicate.clone(), vec![value.clone()])?;
+        if caller.is_truthy(&keep) {
+            result.push(value);
+        }
+    }
+    Ok(Value::List(List::new(result)))
+}
+
+/// Folds the list into a single value, starting from `initial` and replacing
+/// it with `f(accumulator, element)` for each element in turn.
+fn reduce(caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+    let (values, function) = list_and_function(arguments);
+    let mut accumulator = arguments[2].clone();
+    for value in values {
+        accumulator = caller.call(function.clone(), vec![accumulator, value])?;
+    }
+    Ok(accumulator)
+}
+
+/// Splits the arguments of a higher-order native into the list's elements
+/// and the function. The elements are copied out of the list, so the
+/// function may modify the list without affecting the iteration.
+fn list_and_function(arguments: &[Value]) -> (Vec<Value>, &Value) {
+    (list_argument(arguments).to_vec(), &arguments[1])
+}
+
+/// Stops the script with the given exit code. Rather than exiting the process
+/// itself, this unwinds with `RuntimeError::Exit` so embedders can decide
+/// what to do.
+fn exit(caller: &mut dyn Caller, arguments: &[Value]) -> NativeFunctionResult {
+    require("exit", caller, Capability::Process)?;
+    let code = arguments[0].as_number().unwrap_or_default();
+    if code.fract() != 0.0 {
+        return Err(RuntimeError::Native(format!(
+            "exit expected an integer exit code but got {code}"
+        )));
+    }
+    Err(RuntimeError::Exit(code as i32))
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::{interpreter::Interpreter, value::MAX_REPEAT_LENGTH};
+
+    use super::*;
+
+    #[test]
+    fn format_replaces_placeholders() {
+        let result = format(&[
+            Value::String("{} + {} = {}".to_string()),
+            Value::Number(1.0),
+            Value::Number(2.0),
+            Value::Number(3.0),
+        ]);
+
+        assert_eq!(result, Ok(Value::String("1 + 2 = 3".to_string())));
+    }
+
+    #[test]
+    fn format_placeholder_count_mismatch() {
+        let result = format(&[Value::String("{} + {}".to_string()), Value::Number(1.0)]);
+
+        assert_eq!(
+            result,
+            Err(RuntimeError::Native(
+                "format template has 2 placeholders but got 1 arguments".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn format_requires_template() {
+        let result = format(&[Value::Number(1.0)]);
+
+        assert_eq!(
+            result,
+            Err(RuntimeError::Native(
+                "format expected a template string as its first argument".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn len_counts_list_elements_and_characters() {
+        let list = Value::List(List::from(vec![Value::Nil, Value::Nil]));
+
+        assert_eq!(len(&[list]), Ok(Value::Number(2.0)));
+        assert_eq!(
+            len(&[Value::String("héllo".to_string())]),
+            Ok(Value::Number(5.0))
+        );
+        assert_eq!(
+            len(&[Value::Number(1.0)]),
+            Err(RuntimeError::Native(
+                "len expected a list, tuple, map or string but got 1".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn format_number_groups_digits() {
+        assert_eq!(
+            format_number(&[Value::Number(1000000.0)]),
+            Ok(Value::String("1,000,000".to_string()))
+        );
+        assert_eq!(
+            format_number(&[Value::Number(-1234.25)]),
+            Ok(Value::String("-1,234.25".to_string()))
+        );
+    }
+
+    #[test]
+    fn chr_and_ord_convert_code_points() {
+        assert_eq!(
+            chr(&[Value::Number(65.0)]),
+            Ok(Value::String("A".to_string()))
+        );
+        assert_eq!(
+            chr(&[Value::Number(233.0)]),
+            Ok(Value::String("é".to_string()))
+        );
+        assert_eq!(
+            ord(&[Value::String("A".to_string())]),
+            Ok(Value::Number(65.0))
+        );
+        assert_eq!(
+            ord(&[Value::String("é".to_string())]),
+            Ok(Value::Number(233.0))
+        );
+    }
+
+    #[test]
+    fn chr_rejects_invalid_code_points() {
+        for code in [-1.0, 65.5, 55296.0, 1114112.0] {
+            assert_eq!(
+                chr(&[Value::Number(code)]),
+                Err(RuntimeError::Native(format!(
+                    "chr expected a valid code point but got {code}"
+                )))
+            );
+        }
+    }
+
+    #[test]
+    fn ord_requires_a_single_character() {
+        for (value, shown) in [
+            (Value::String("".to_string()), "\"\""),
+            (Value::String("AB".to_string()), "\"AB\""),
+        ] {
+            assert_eq!(
+                ord(&[value]),
+                Err(RuntimeError::Native(format!(
+                    "ord expected a single character string but got {shown}"
+                )))
+            );
+        }
+    }
+
+    #[test]
+    fn exit_unwinds_with_code() {
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        assert_eq!(
+            exit(&mut interpreter, &[Value::Number(3.0)]),
+            Err(RuntimeError::Exit(3))
+        );
+        assert_eq!(
+            exit(&mut interpreter, &[Value::Number(1.5)]),
+            Err(RuntimeError::Native(
+                "exit expected an integer exit code but got 1.5".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn method_lookup_depends_on_receiver_type() {
+        let string = Value::String("hi".to_string());
+        let list = Value::List(List::from(vec![]));
+
+        assert_eq!(method(&string, "upper").map(|m| &*m.name), Some("upper"));
+        assert_eq!(method(&list, "upper").map(|m| &*m.name), None);
+        assert_eq!(method(&list, "len").map(|m| &*m.name), Some("len"));
+        assert_eq!(method(&Value::Nil, "len").map(|m| &*m.name), None);
+    }
+
+    #[test]
+    fn call_method_excludes_receiver_from_arity() {
+        let list = Value::List(List::from(vec![Value::Number(1.0)]));
+        let contains = method(&list, "contains").unwrap();
+
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        assert_eq!(
+            contains.call_method(&mut interpreter, list.clone(), &[Value::Number(1.0)]),
+            Ok(Value::Boolean(true))
+        );
+        assert_eq!(
+            contains.call_method(&mut interpreter, list, &[]),
+            Err(RuntimeError::Arity {
+                name: "contains".to_string(),
+                expected: 1,
+                got: 0
+            })
+        );
+    }
+
+    #[test]
+    fn between_is_inclusive() {
+        let between_numbers =
+            |x, lo, hi| between(&[Value::Number(x), Value::Number(lo), Value::Number(hi)]);
+
+        assert_eq!(between_numbers(5.0, 1.0, 10.0), Ok(Value::Boolean(true)));
+        assert_eq!(between_numbers(0.0, 1.0, 10.0), Ok(Value::Boolean(false)));
+        assert_eq!(between_numbers(1.0, 1.0, 10.0), Ok(Value::Boolean(true)));
+        assert_eq!(between_numbers(10.0, 1.0, 10.0), Ok(Value::Boolean(true)));
+    }
+
+    #[test]
+    fn call_checks_arity() {
+        let native = NativeFunction {
+            name: Cow::Borrowed("identity"),
+            parameters: Parameters::Count(1),
+            function: NativeBody::Pure(|arguments| Ok(arguments[0].clone())),
+        };
+
+        let result = native.call(&mut Interpreter::new(Environment::new()), &[]);
+
+        assert_eq!(
+            result,
+            Err(RuntimeError::Arity {
+                name: "identity".to_string(),
+                expected: 1,
+                got: 0
+            })
+        );
+    }
+
+    #[test]
+    fn call_checks_argument_types() {
+        let native = NativeFunction {
+            name: Cow::Borrowed("double"),
+            parameters: Parameters::Typed(&[ParameterType::Number]),
+            function: NativeBody::Pure(|arguments| {
+                Ok(Value::Number(arguments[0].as_number().unwrap() * 2.0))
+            }),
+        };
+        let mut interpreter = Interpreter::new(Environment::new());
+
+        assert_eq!(
+            native.call(&mut interpreter, &[Value::Number(2.0)]),
+            Ok(Value::Number(4.0))
+        );
+        assert_eq!(
+            native.call(&mut interpreter, &[Value::String("2".to_string())]),
+            Err(RuntimeError::TypeMismatch {
+                name: "double".to_string(),
+                position: 1,
+                expected: "number",
+                got: "string"
+            })
+        );
+        assert_eq!(
+            native.call(&mut interpreter, &[]),
+            Err(RuntimeError::Arity {
+                name: "double".to_string(),
+                expected: 1,
+                got: 0
+            })
+        );
+    }
+
+    #[test]
+    fn typed_natives_report_the_mismatched_argument() {
+        let between = NATIVES.iter().find(|n| n.name == "between").unwrap();
+
+        let error = between
+            .call(
+                &mut Interpreter::new(Environment::new()),
+                &[Value::Number(1.0), Value::Number(0.0), Value::Nil],
+            )
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "between expected number for argument 3 but got nil"
+        );
+    }
+
+    #[test]
+    fn parameter_types_accept_matching_values() {
+        let native = Value::NativeFunction(NATIVES[0].clone());
+
+        assert!(ParameterType::Any.accepts(&Value::Nil));
+        assert!(ParameterType::Function.accepts(&native));
+        assert!(ParameterType::List.accepts(&Value::List(List::from(vec![]))));
+        assert!(!ParameterType::String.accepts(&Value::Number(1.0)));
+        assert!(!ParameterType::Function.accepts(&Value::Nil));
+    }
+
+    #[test]
+    fn repeat_supports_fractional_counts() {
+        let repeat_string =
+            |count| repeat(&[Value::String("abc".to_string()), Value::Number(count)]);
+
+        assert_eq!(repeat_string(1.5), Ok(Value::String("abca".to_string())));
+        assert_eq!(repeat_string(2.0), Ok(Value::String("abcabc".to_string())));
+        assert_eq!(repeat_string(0.7), Ok(Value::String("ab".to_string())));
+        assert_eq!(repeat_string(0.0), Ok(Value::String("".to_string())));
+        assert_eq!(
+            repeat_string(-1.0),
+            Err(RuntimeError::Native(
+                "repeat expected a non-negative count but got -1".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn repeat_rejects_huge_results() {
+        let too_long = Err(RuntimeError::StringTooLong {
+            max: MAX_REPEAT_LENGTH,
+        });
+
+        assert_eq!(
+            repeat(&[Value::String("ab".to_string()), Value::Number(1e30)]),
+            too_long
+        );
+        assert_eq!(
+            repeat(&[
+                Value::String("ab".to_string()),
+                Value::Number(f32::INFINITY)
+            ]),
+            too_long
+        );
+        assert_eq!(
+            repeat(&[Value::String("".to_string()), Value::Number(1e30)]),
+            Ok(Value::String("".to_string()))
+        );
+    }
+
+    #[test]
+    fn higher_order_natives_require_a_list_and_function() {
+        let mut interpreter = Interpreter::new(Environment::new());
+        let list = Value::List(List::from(vec![Value::Number(1.0)]));
+
+        for native in ["map", "filter", "reduce"] {
+            let native = NATIVES.iter().find(|n| n.name == native).unwrap();
+            let arguments = [list.clone(), Value::Number(1.0), Value::Nil];
+            let arguments = &arguments[..native.parameters.arity().unwrap()];
+
+            assert_eq!(
+                native.call(&mut interpreter, arguments),
+                Err(RuntimeError::TypeMismatch {
+                    name: native.name.to_string(),
+                    position: 2,
+                    expected: "function",
+                    got: "number"
+                })
+            );
+        }
+    }
+}
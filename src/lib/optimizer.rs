@@ -0,0 +1,374 @@
+use std::rc::Rc;
+
+use super::parser::{
+    BinaryOperator, Block, ConditionalBranch, Declaration, Expression, Function, LiteralValue,
+    Program, Statement, UnaryOperator,
+};
+
+/// Folds constant subexpressions (e.g. `1 + 2`) into literals ahead of
+/// interpretation. Expressions which depend on variables, or which could fail
+/// at runtime, are left untouched so their behaviour doesn't change.
+pub fn optimize(program: Program) -> Program {
+    program.map_declarations(fold_declaration)
+}
+
+fn fold_declaration(declaration: Declaration) -> Declaration {
+    match declaration {
+        Declaration::Variable { identifier, value } => Declaration::Variable {
+            identifier,
+            value: fold_expression(value),
+        },
+        Declaration::Destructuring { identifiers, value } => Declaration::Destructuring {
+            identifiers,
+            value: fold_expression(value),
+        },
+        Declaration::VariableAssignment { identifier, value } => Declaration::VariableAssignment {
+            identifier,
+            value: fold_expression(value),
+        },
+        // A function shared elsewhere can't be rewritten in place, so it's
+        // left as it is.
+        Declaration::Function(function) => match Rc::try_unwrap(function) {
+            Ok(function) => Declaration::Function(Rc::new(fold_function(function))),
+            Err(function) => Declaration::Function(function),
+        },
+        Declaration::Statement(statement) => Declaration::Statement(fold_statement(statement)),
+        Declaration::Block(block) => Declaration::Block(fold_block(block)),
+        Declaration::Class { name, methods } => Declaration::Class {
+            name,
+            methods: methods.into_iter().map(fold_function).collect(),
+        },
+        Declaration::Annotated {
+            directives,
+            declaration,
+        } => Declaration::Annotated {
+            directives,
+            declaration: Box::new(fold_declaration(*declaration)),
+        },
+    }
+}
+
+fn fold_function(function: Function) -> Function {
+    Function {
+        defaults: function.defaults.into_iter().map(fold_expression).collect(),
+        body: fold_block(function.body),
+        ..function
+    }
+}
+
+fn fold_block(block: Block) -> Block {
+    block.map_declarations(fold_declaration)
+}
+
+fn fold_statement(statement: Statement) -> Statement {
+    match statement {
+        Statement::Print(expressions) => {
+            Statement::Print(expressions.into_iter().map(fold_expression).collect())
+        }
+        Statement::If {
+            branches,
+            else_declaration,
+        } => Statement::If {
+            branches: branches
+                .into_iter()
+                .map(|branch| ConditionalBranch {
+                    condition: fold_expression(branch.condition),
+                    declaration: fold_declaration(branch.declaration),
+                })
+                .collect(),
+            else_declaration: else_declaration
+                .map(|declaration| Box::new(fold_declaration(*declaration))),
+        },
+        Statement::DoWhile {
+            body,
+            condition,
+            label,
+        } => Statement::DoWhile {
+            body: fold_block(body),
+            condition: fold_expression(condition),
+            label,
+        },
+        Statement::While {
+            condition,
+            body,
+            increment,
+            label,
+        } => Statement::While {
+            condition: fold_expression(condition),
+            body: Box::new(fold_declaration(*body)),
+            increment: increment.map(fold_expression),
+            label,
+        },
+        Statement::Break(label) => Statement::Break(label),
+        Statement::Continue(label) => Statement::Continue(label),
+        Statement::Return(value) => Statement::Return(value.map(fold_expression)),
+        Statement::Delete(identifier) => Statement::Delete(identifier),
+        Statement::Expression(expression) => Statement::Expression(fold_expression(expression)),
+    }
+}
+
+fn fold_expression(expression: Expression) -> Expression {
+    match expression {
+        Expression::Assignment { identifier, value } => Expression::Assignment {
+            identifier,
+            value: Box::new(fold_expression(*value)),
+        },
+        Expression::Binary {
+            left,
+            right,
+            operator,
+        } => {
+            let left = fold_expression(*left);
+            let right = fold_expression(*right);
+
+            if let (Expression::Literal(left), Expression::Literal(right)) = (&left, &right) {
+                if let Some(value) = fold_binary(left, right, &operator) {
+                    return Expression::Literal(value);
+                }
+            }
+
+            Expression::Binary {
+                left: Box::new(left),
+                right: Box::new(right),
+                operator,
+            }
+        }
+        // A literal on the left already decides which side is evaluated.
+        Expression::Coalesce { left, right } => match fold_expression(*left) {
+            Expression::Literal(LiteralValue::Nil) => fold_expression(*right),
+            left @ Expression::Literal(_) => left,
+            left => Expression::Coalesce {
+                left: Box::new(left),
+                right: Box::new(fold_expression(*right)),
+            },
+        },
+        Expression::Call {
+            callee,
+            arguments,
+            named,
+        } => Expression::Call {
+            callee: Box::new(fold_expression(*callee)),
+            arguments: arguments.into_iter().map(fold_expression).collect(),
+            named: named
+                .into_iter()
+                .map(|(name, value)| (name, fold_expression(value)))
+                .collect(),
+        },
+        Expression::Get {
+            object,
+            name,
+            optional,
+        } => Expression::Get {
+            object: Box::new(fold_expression(*object)),
+            name,
+            optional,
+        },
+        Expression::Index { target, index } => Expression::Index {
+            target: Box::new(fold_expression(*target)),
+            index: Box::new(fold_expression(*index)),
+        },
+        // Nested groupings such as `((x))` collapse into one, as the extra
+        // parentheses don't change how the expression evaluates.
+        Expression::Grouping(expression) => match fold_expression(*expression) {
+            Expression::Literal(value) => Expression::Literal(value),
+            grouping @ Expression::Grouping(_) => grouping,
+            expression => Expression::Grouping(Box::new(expression)),
+        },
+        Expression::List(elements) => {
+            Expression::List(elements.into_iter().map(fold_expression).collect())
+        }
+        Expression::Tuple(elements) => {
+            Expression::Tuple(elements.into_iter().map(fold_expression).collect())
+        }
+        Expression::Interpolation(parts) => {
+            Expression::Interpolation(parts.into_iter().map(fold_expression).collect())
+        }
+        Expression::Map(entries) => Expression::Map(
+            entries
+                .into_iter()
+                .map(|(key, value)| (key, fold_expression(value)))
+                .collect(),
+        ),
+        Expression::Literal(value) => Expression::Literal(value),
+        Expression::Variable(identifier) => Expression::Variable(identifier),
+        Expression::Lambda(function) => match Rc::try_unwrap(function) {
+            Ok(function) => Expression::Lambda(Rc::new(fold_function(function))),
+            Err(function) => Expression::Lambda(function),
+        },
+        Expression::Block { body, value } => Expression::Block {
+            body: fold_block(body),
+            value: value.map(|value| Box::new(fold_expression(*value))),
+        },
+        Expression::If {
+            condition,
+            then_branch,
+            else_branch,
+        } => Expression::If {
+            condition: Box::new(fold_expression(*condition)),
+            then_branch: Box::new(fold_expression(*then_branch)),
+            else_branch: else_branch.map(|branch| Box::new(fold_expression(*branch))),
+        },
+        Expression::Unary { right, operator } => {
+            let right = fold_expression(*right);
+
+            match (&right, &operator) {
+                (Expression::Literal(LiteralValue::Number(value)), UnaryOperator::Minus) => {
+                    Expression::Literal(LiteralValue::Number(-value))
+                }
+                (Expression::Literal(LiteralValue::Number(value)), UnaryOperator::Plus) => {
+                    Expression::Literal(LiteralValue::Number(*value))
+                }
+                _ => Expression::Unary {
+                    right: Box::new(right),
+                    operator,
+                },
+            }
+        }
+    }
+}
+
+/// Only arithmetic on numbers and string concatenation are folded, as these
+/// can't fail. Division by zero is left for the interpreter to deal with.
+fn fold_binary(
+    left: &LiteralValue,
+    right: &LiteralValue,
+    operator: &BinaryOperator,
+) -> Option<LiteralValue> {
+    match (left, right) {
+        (LiteralValue::Number(left), LiteralValue::Number(right)) => match operator {
+            BinaryOperator::Plus => Some(LiteralValue::Number(left + right)),
+            BinaryOperator::Minus => Some(LiteralValue::Number(left - right)),
+            BinaryOperator::Star => Some(LiteralValue::Number(left * right)),
+            BinaryOperator::Slash if *right != 0.0 => Some(LiteralValue::Number(left / right)),
+            _ => None,
+        },
+        (LiteralValue::String(left), LiteralValue::String(right)) => match operator {
+            BinaryOperator::Plus => Some(LiteralValue::String(format!("{left}{right}"))),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::environment::Environment;
+    use crate::lib::interpreter::Interpreter;
+    use crate::lib::parser::Parser;
+    use crate::lib::scanner::Scanner;
+    use crate::lib::value::Value;
+    use crate::tokens;
+
+    use super::*;
+
+    fn optimize_source(source: &str) -> String {
+        let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        format!("{:?}", optimize(program))
+    }
+
+    #[test]
+    fn folds_arithmetic() {
+        assert_eq!(
+            optimize_source("2 * 3 + 1;"),
+            "Program([Statement(Expression(Literal(Number(7.0))))])"
+        );
+    }
+
+    #[test]
+    fn folds_groupings() {
+        assert_eq!(
+            optimize_source("print (1 + 2) * -3;"),
+            "Program([Statement(Print([Literal(Number(-9.0))]))])"
+        );
+    }
+
+    #[test]
+    fn folds_coalescing_literals() {
+        assert_eq!(
+            optimize_source("nil ?? x; 1 ?? x; x ?? 1 + 1;"),
+            "Program([Statement(Expression(Variable(\"x\"))), Statement(Expression(Literal(Number(1.0)))), Statement(Expression(Coalesce { left: Variable(\"x\"), right: Literal(Number(2.0)) }))])"
+        );
+    }
+
+    #[test]
+    fn collapses_nested_groupings() {
+        assert_eq!(
+            optimize_source("((1));"),
+            "Program([Statement(Expression(Literal(Number(1.0))))])"
+        );
+        assert_eq!(
+            optimize_source("(((x)));"),
+            "Program([Statement(Expression(Grouping(Variable(\"x\"))))])"
+        );
+    }
+
+    #[test]
+    fn collapsed_groupings_evaluate_the_same() {
+        for (source, expected) in [("((1));", 1.0), ("x = 2; ((x)) * ((x + 1));", 6.0)] {
+            let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+            let program = optimize(
+                Parser::new(&tokens)
+                    .parse()
+                    .expect("Parser should not fail to parse tokens"),
+            );
+
+            let value = Interpreter::new(Environment::new())
+                .run_capturing(&program)
+                .expect("Program should run without errors");
+
+            assert_eq!(value, Some(Value::Number(expected)), "{source}");
+        }
+    }
+
+    #[test]
+    fn folds_string_concatenation() {
+        assert_eq!(
+            optimize_source("print \"a\" + \"b\";"),
+            "Program([Statement(Print([Literal(String(\"ab\"))]))])"
+        );
+    }
+
+    #[test]
+    fn leaves_variables_untouched() {
+        assert_eq!(
+            optimize_source("x + 1;"),
+            "Program([Statement(Expression(Binary { left: Variable(\"x\"), right: Literal(Number(1.0)), operator: Plus }))])"
+        );
+    }
+
+    #[test]
+    fn folds_around_variables() {
+        assert_eq!(
+            optimize_source("x = y * (2 + 3);"),
+            "Program([VariableAssignment { identifier: \"x\", value: Binary { left: Variable(\"y\"), right: Literal(Number(5.0)), operator: Star } }])"
+        );
+    }
+
+    #[test]
+    fn leaves_division_by_zero_untouched() {
+        assert_eq!(
+            optimize_source("1 / 0;"),
+            "Program([Statement(Expression(Binary { left: Literal(Number(1.0)), right: Literal(Number(0.0)), operator: Slash }))])"
+        );
+    }
+
+    #[test]
+    fn leaves_mismatched_types_untouched() {
+        assert_eq!(
+            optimize_source("true - 1;"),
+            "Program([Statement(Expression(Binary { left: Literal(Boolean(true)), right: Literal(Number(1.0)), operator: Minus }))])"
+        );
+    }
+
+    #[test]
+    fn folds_function_bodies() {
+        assert_eq!(
+            optimize_source("fun f() { return 1 + 2; }"),
+            "Program([Function(Function { name: \"f\", parameters: [], defaults: [], rest: None, body: Block([Statement(Return(Some(Literal(Number(3.0)))))]) })])"
+        );
+    }
+}
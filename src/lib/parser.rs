@@ -1,9 +1,19 @@
-use std::ops::{Add, Div, Mul, Sub};
-
-use super::scanner::{Keyword, Token, TokenDirection, Tokens};
-
-#[derive(Debug)]
-pub enum Operator {
+use std::{
+    borrow::Cow,
+    fmt::{self, Debug, Formatter},
+    rc::Rc,
+};
+
+use super::{
+    error::CompilerResult,
+    interner::Symbol,
+    scanner::{Excerpt, Keyword, StringPart, Token, TokenDirection, Tokens},
+};
+
+/// An operator which sits between two operands. `!` is deliberately absent, so
+/// a binary `!` can't be represented at all.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum BinaryOperator {
     BangEquals,
     EqualsEquals,
     Greater,
@@ -14,229 +24,458 @@ pub enum Operator {
     Plus,
     Slash,
     Star,
+}
+
+impl From<&Token> for BinaryOperator {
+    fn from(token: &Token) -> Self {
+        match token {
+            Token::BangEquals => BinaryOperator::BangEquals,
+            Token::EqualsEquals => BinaryOperator::EqualsEquals,
+            Token::Greater => BinaryOperator::Greater,
+            Token::GreaterEqual => BinaryOperator::GreaterEqual,
+            Token::Less => BinaryOperator::Less,
+            Token::LessEqual => BinaryOperator::LessEqual,
+            Token::Minus => BinaryOperator::Minus,
+            Token::Plus => BinaryOperator::Plus,
+            Token::Slash => BinaryOperator::Slash,
+            Token::Star => BinaryOperator::Star,
+            token => panic!("Expected a binary operator token not {token:?}"),
+        }
+    }
+}
+
+/// An operator which precedes its single operand.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UnaryOperator {
     Bang,
+    Minus,
+    Plus,
 }
 
-impl From<&Token> for Operator {
+impl From<&Token> for UnaryOperator {
     fn from(token: &Token) -> Self {
         match token {
-            Token::BangEquals => Operator::BangEquals,
-            Token::EqualsEquals => Operator::EqualsEquals,
-            Token::Greater => Operator::Greater,
-            Token::GreaterEqual => Operator::GreaterEqual,
-            Token::Less => Operator::Less,
-            Token::LessEqual => Operator::LessEqual,
-            Token::Minus => Operator::Minus,
-            Token::Plus => Operator::Plus,
-            Token::Slash => Operator::Slash,
-            Token::Star => Operator::Star,
-            Token::Bang => Operator::Bang,
-            token => panic!("Expected a operator token not {token:?}"),
+            Token::Bang => UnaryOperator::Bang,
+            Token::Minus => UnaryOperator::Minus,
+            Token::Plus => UnaryOperator::Plus,
+            token => panic!("Expected a unary operator token not {token:?}"),
         }
     }
 }
 
-#[derive(PartialEq, PartialOrd, Debug, Clone)]
+/// A literal value as written in the source. Evaluating one produces the
+/// equivalent runtime [`Value`](super::value::Value).
+///
+/// Numbers compare as floats, so a tree containing a `NaN` literal (which
+/// constant folding can produce, e.g. from `inf - inf`) isn't equal to itself.
+#[derive(PartialEq, Debug, Clone)]
 pub enum LiteralValue {
     Boolean(bool),
     String(String),
     Number(f32),
-    Identifier(String),
     Nil,
 }
 
-impl LiteralValue {
-    pub fn to_string(&self) -> String {
-        match self {
-            LiteralValue::Boolean(value) => format!("{value}"),
-            LiteralValue::String(value) => format!("{value}"),
-            LiteralValue::Number(value) => format!("{value}"),
-            LiteralValue::Nil => "nil".to_string(),
-            LiteralValue::Identifier(identifier) => format!("{identifier}"),
-        }
-    }
+#[derive(Clone, Default)]
+pub struct Program {
+    declarations: Vec<Declaration>,
+    /// Where each declaration was written, if it was parsed from source.
+    excerpts: Vec<Option<Excerpt>>,
 }
 
-impl Sub for LiteralValue {
-    type Output = LiteralValue;
+impl Program {
+    pub fn new() -> Self {
+        Self::default()
+    }
 
-    fn sub(self, rhs: LiteralValue) -> Self::Output {
-        match self {
-            LiteralValue::Boolean(_) => panic!("Cannot subtract boolean values"),
-            LiteralValue::String(_) => panic!("Cannot subtract string values"),
-            LiteralValue::Number(lhs_value) => match rhs {
-                LiteralValue::Number(rhs_value) => LiteralValue::Number(lhs_value - rhs_value),
-                _ => panic!("Cannot subtract values with different types"),
-            },
-            LiteralValue::Nil => panic!("Cannot subtract nil values"),
-            LiteralValue::Identifier(_) => panic!("Cannot subtract unresolved identifier"),
-        }
+    #[allow(dead_code)] // The CLI only iterates over the declarations.
+    pub fn len(&self) -> usize {
+        self.declarations.len()
     }
-}
 
-impl Add for LiteralValue {
-    type Output = LiteralValue;
+    #[allow(dead_code)] // Goes with `len`.
+    pub fn is_empty(&self) -> bool {
+        self.declarations.is_empty()
+    }
 
-    fn add(self, rhs: LiteralValue) -> Self::Output {
-        match self {
-            LiteralValue::Boolean(lhs_value) => match rhs {
-                LiteralValue::String(rhs_value) => {
-                    LiteralValue::String(format!("{lhs_value}{rhs_value}"))
-                }
-                _ => panic!("Boolean values can only be added with string values"),
-            },
-            LiteralValue::String(lhs_value) => match rhs {
-                LiteralValue::String(rhs_value) => LiteralValue::String(lhs_value + &rhs_value),
-                LiteralValue::Boolean(rhs_value) => {
-                    LiteralValue::String(format!("{lhs_value}{rhs_value}"))
-                }
-                LiteralValue::Number(rhs_value) => {
-                    LiteralValue::String(format!("{lhs_value}{rhs_value}"))
-                }
-                LiteralValue::Identifier(_) => panic!("Cannot add unresolved identifier to string"),
-                LiteralValue::Nil => LiteralValue::String(format!("{lhs_value}nil")),
-            },
-            LiteralValue::Number(lhs_value) => match rhs {
-                LiteralValue::Number(rhs_value) => LiteralValue::Number(lhs_value + rhs_value),
-                LiteralValue::String(rhs_value) => {
-                    LiteralValue::String(format!("{lhs_value}{rhs_value}"))
-                }
-                _ => panic!("Cannot add values with different types"),
-            },
-            LiteralValue::Nil => match rhs {
-                LiteralValue::String(rhs_value) => LiteralValue::String(format!("nil{rhs_value}")),
-                _ => panic!("Nil values can only be added with string values"),
-            },
-            LiteralValue::Identifier(_) => panic!("Cannot add unresolved identifier"),
-        }
+    #[allow(dead_code)] // For hosts inspecting individual declarations.
+    pub fn get(&self, index: usize) -> Option<&Declaration> {
+        self.declarations.get(index)
     }
-}
 
-impl Div for LiteralValue {
-    type Output = LiteralValue;
+    #[allow(dead_code)] // For hosts building programs up a declaration at a time.
+    pub fn add_declaration(&mut self, declaration: Declaration) {
+        self.declarations.push(declaration);
+        self.excerpts.push(None);
+    }
 
-    fn div(self, rhs: LiteralValue) -> Self::Output {
-        match self {
-            LiteralValue::Boolean(_) => panic!("Cannot divide boolean values"),
-            LiteralValue::String(_) => panic!("Cannot divide string values"),
-            LiteralValue::Number(lhs_value) => match rhs {
-                LiteralValue::Number(rhs_value) => LiteralValue::Number(lhs_value / rhs_value),
-                _ => panic!("Cannot divide values with different types"),
-            },
-            LiteralValue::Nil => panic!("Cannot divide nil values"),
-            LiteralValue::Identifier(_) => panic!("Cannot divide unresolved identifier"),
-        }
+    /// Moves all of `declarations` onto the end of the program, leaving the
+    /// vector empty.
+    #[allow(dead_code)] // For hosts building programs up from several parses.
+    pub fn add_declarations(&mut self, declarations: &mut Vec<Declaration>) {
+        self.excerpts
+            .resize(self.excerpts.len() + declarations.len(), None);
+        self.declarations.append(declarations);
+    }
+
+    pub fn get_declarations(&self) -> &Vec<Declaration> {
+        &self.declarations
     }
-}
 
-impl Mul for LiteralValue {
-    type Output = LiteralValue;
+    /// Where each declaration was written, in the same order.
+    pub fn excerpts(&self) -> &[Option<Excerpt>] {
+        &self.excerpts
+    }
 
-    fn mul(self, rhs: LiteralValue) -> Self::Output {
-        match self {
-            LiteralValue::Boolean(_) => panic!("Cannot multiply boolean values"),
-            LiteralValue::String(lhs_value) => match rhs {
-                LiteralValue::Number(rhs_value) => {
-                    LiteralValue::String(lhs_value.repeat(rhs_value as usize))
-                }
-                _ => panic!("Strings can only be multiplied by a number"),
-            },
-            LiteralValue::Number(lhs_value) => match rhs {
-                LiteralValue::Number(rhs_value) => LiteralValue::Number(lhs_value * rhs_value),
-                LiteralValue::String(rhs_value) => {
-                    LiteralValue::String(rhs_value.repeat(lhs_value as usize))
-                }
-                _ => panic!("Cannot multiply values with different types"),
-            },
-            LiteralValue::Nil => panic!("Cannot multiply nil values"),
-            LiteralValue::Identifier(_) => panic!("Cannot multiply unresolved identifier"),
+    /// Rewrites each declaration, keeping where it was written.
+    pub fn map_declarations(self, f: impl FnMut(Declaration) -> Declaration) -> Self {
+        Self {
+            declarations: self.declarations.into_iter().map(f).collect(),
+            excerpts: self.excerpts,
         }
     }
+
+    /// Splits the program into one program per declaration, so that they can
+    /// be run one at a time.
+    pub fn split(self) -> impl Iterator<Item = Program> {
+        self.declarations
+            .into_iter()
+            .zip(self.excerpts)
+            .map(|(declaration, excerpt)| Program {
+                declarations: vec![declaration],
+                excerpts: vec![excerpt],
+            })
+    }
 }
 
-#[derive(Debug)]
-pub struct Program(Vec<Declaration>);
+impl From<Vec<Declaration>> for Program {
+    fn from(declarations: Vec<Declaration>) -> Self {
+        let excerpts = vec![None; declarations.len()];
+        Program {
+            declarations,
+            excerpts,
+        }
+    }
+}
 
-impl Program {
-    pub fn add_declaration(&mut self, declaration: Declaration) {
-        self.0.push(declaration);
+// Programs which only differ in where they were written are equal, and
+// excerpts are left out to keep dumps of the syntax tree readable.
+impl PartialEq for Program {
+    fn eq(&self, other: &Self) -> bool {
+        self.declarations == other.declarations
     }
+}
 
-    pub fn get_declarations(&self) -> &Vec<Declaration> {
-        &self.0
+impl Debug for Program {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Program").field(&self.declarations).finish()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Declaration {
+    /// `var identifier = value;`, which always binds in the current scope.
+    Variable {
+        identifier: Symbol,
+        value: Expression,
+    },
+    /// `var (a, b) = value;`, which binds each element of a tuple in order.
+    Destructuring {
+        identifiers: Vec<String>,
+        value: Expression,
+    },
     VariableAssignment {
-        identifier: String,
+        identifier: Symbol,
         value: Expression,
     },
+    Function(Rc<Function>),
     Statement(Statement),
     Block(Block),
+    Class {
+        name: String,
+        methods: Vec<Function>,
+    },
+    /// A declaration preceded by one or more `@name` directives. Directives
+    /// have no effect on evaluation; they're a hook for tooling and future
+    /// features such as marking test functions.
+    Annotated {
+        directives: Vec<String>,
+        declaration: Box<Declaration>,
+    },
+}
+
+/// The name given to functions created by `fun(...) { ... }` expressions.
+pub const LAMBDA_NAME: &str = "lambda";
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Function {
+    pub name: String,
+    pub parameters: Vec<String>,
+    /// The default values of the last `defaults.len()` parameters, which are
+    /// evaluated when a call leaves those parameters out.
+    pub defaults: Vec<Expression>,
+    /// A final `...name` parameter, which collects the positional arguments
+    /// left over once `parameters` are bound into a list.
+    pub rest: Option<String>,
+    pub body: Block,
+}
+
+impl Function {
+    /// The number of arguments which must be passed, i.e. the parameters
+    /// without a default.
+    pub fn required_parameters(&self) -> usize {
+        self.parameters.len() - self.defaults.len()
+    }
 }
 
-#[derive(Debug)]
-pub struct Block(Vec<Declaration>);
+#[derive(Clone)]
+pub struct Block {
+    declarations: Vec<Declaration>,
+    /// Where each declaration was written, if it was parsed from source.
+    excerpts: Vec<Option<Excerpt>>,
+}
 
 impl Block {
     pub fn get_declarations(&self) -> &Vec<Declaration> {
-        &self.0
+        &self.declarations
+    }
+
+    /// Where each declaration was written, in the same order.
+    pub fn excerpts(&self) -> &[Option<Excerpt>] {
+        &self.excerpts
+    }
+
+    /// Rewrites each declaration, keeping where it was written.
+    pub fn map_declarations(self, f: impl FnMut(Declaration) -> Declaration) -> Self {
+        Self {
+            declarations: self.declarations.into_iter().map(f).collect(),
+            excerpts: self.excerpts,
+        }
+    }
+}
+
+impl From<Vec<Declaration>> for Block {
+    fn from(declarations: Vec<Declaration>) -> Self {
+        let excerpts = vec![None; declarations.len()];
+        Block {
+            declarations,
+            excerpts,
+        }
+    }
+}
+
+// As with programs, excerpts don't affect equality and are left out of dumps.
+impl PartialEq for Block {
+    fn eq(&self, other: &Self) -> bool {
+        self.declarations == other.declarations
+    }
+}
+
+impl Debug for Block {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Block").field(&self.declarations).finish()
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConditionalBranch {
+    pub condition: Expression,
+    pub declaration: Declaration,
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Statement {
-    Print(Expression),
-    If { condition: Expression, declaration: Box<Declaration> },
+    /// `print a, b;` prints each expression separated by a space.
+    Print(Vec<Expression>),
+    If {
+        branches: Vec<ConditionalBranch>,
+        else_declaration: Option<Box<Declaration>>,
+    },
+    /// Loops may be labeled, e.g. `outer: do { ... } while x;`, so that
+    /// `break outer;` and `continue outer;` can target them from nested loops.
+    DoWhile {
+        body: Block,
+        condition: Expression,
+        label: Option<String>,
+    },
+    /// A `while` loop, or the loop part of a desugared `for` loop, in which
+    /// case `increment` is evaluated after each iteration of the body.
+    While {
+        condition: Expression,
+        body: Box<Declaration>,
+        increment: Option<Expression>,
+        label: Option<String>,
+    },
+    /// `break;` or `break label;`, which exits the innermost loop or the loop
+    /// with that label.
+    Break(Option<String>),
+    /// `continue;` or `continue label;`, which skips to the next iteration of
+    /// the innermost loop or the loop with that label.
+    Continue(Option<String>),
+    Return(Option<Expression>),
+    /// `delete identifier;`, which removes the binding from the current scope.
+    Delete(String),
     Expression(Expression),
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Expression {
+    Assignment {
+        identifier: Symbol,
+        value: Box<Expression>,
+    },
     Binary {
         left: Box<Expression>,
         right: Box<Expression>,
-        operator: Operator,
+        operator: BinaryOperator,
+    },
+    /// `left ?? right`, which evaluates to `left` unless it's nil, in which
+    /// case `right` is evaluated instead.
+    Coalesce {
+        left: Box<Expression>,
+        right: Box<Expression>,
     },
     Grouping(Box<Expression>),
+    List(Vec<Expression>),
+    Tuple(Vec<Expression>),
+    /// `{key: value, ...}`, with entries in source order.
+    Map(Vec<(String, Expression)>),
     Literal(LiteralValue),
+    /// A reference to a variable, which is resolved when evaluated.
+    Variable(Symbol),
+    /// `fun(parameters) { ... }`, an anonymous function which closes over the
+    /// scope it's evaluated in. Its name is always `lambda`.
+    Lambda(Rc<Function>),
+    /// `{ declarations; value }`, which runs its declarations in a new scope
+    /// and evaluates to its final expression, or nil if it ends with a
+    /// semicolon. A `return`, `break` or `continue` inside the block applies
+    /// to the function or loop around it, as it would outside the block.
+    Block {
+        body: Block,
+        value: Option<Box<Expression>>,
+    },
+    /// `if condition { ... } else { ... }`, which evaluates to the value of
+    /// whichever block runs. The `else` is optional, and is nil if skipped.
+    /// An `else if` chain nests another `If` as the `else_branch`.
+    If {
+        condition: Box<Expression>,
+        then_branch: Box<Expression>,
+        else_branch: Option<Box<Expression>>,
+    },
     Unary {
         right: Box<Expression>,
-        operator: Operator,
+        operator: UnaryOperator,
+    },
+    /// `"text ${expression} text"`, which evaluates each part and joins them
+    /// as `print` would display them.
+    Interpolation(Vec<Expression>),
+    /// `callee(arguments, name: value, ...)`. Named arguments are matched to
+    /// the parameters with those names, and always follow the positional ones.
+    Call {
+        callee: Box<Expression>,
+        arguments: Vec<Expression>,
+        named: Vec<(String, Expression)>,
     },
+    Index {
+        target: Box<Expression>,
+        index: Box<Expression>,
+    },
+    /// `object.name`, which reads a key of a map or, as the callee of a call,
+    /// names a method such as `"hi".upper()`. `object?.name` is `optional`,
+    /// and evaluates to nil rather than failing when the object is nil.
+    Get {
+        object: Box<Expression>,
+        name: String,
+        optional: bool,
+    },
+}
+
+impl Expression {
+    /// Whether evaluating the expression could do anything besides produce a
+    /// value (or fail). Calls and blocks are assumed to, since they run code
+    /// which may.
+    pub fn has_side_effects(&self) -> bool {
+        match self {
+            Expression::Assignment { .. } | Expression::Call { .. } | Expression::Block { .. } => {
+                true
+            }
+            Expression::Literal(_) | Expression::Variable(_) | Expression::Lambda(_) => false,
+            Expression::Binary { left, right, .. }
+            | Expression::Coalesce { left, right }
+            | Expression::Index {
+                target: left,
+                index: right,
+            } => left.has_side_effects() || right.has_side_effects(),
+            Expression::Grouping(expression)
+            | Expression::Unary {
+                right: expression, ..
+            }
+            | Expression::Get {
+                object: expression, ..
+            } => expression.has_side_effects(),
+            Expression::List(elements)
+            | Expression::Tuple(elements)
+            | Expression::Interpolation(elements) => {
+                elements.iter().any(Expression::has_side_effects)
+            }
+            Expression::Map(entries) => entries.iter().any(|(_, value)| value.has_side_effects()),
+            Expression::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => {
+                condition.has_side_effects()
+                    || then_branch.has_side_effects()
+                    || else_branch
+                        .as_ref()
+                        .is_some_and(|branch| branch.has_side_effects())
+            }
+        }
+    }
 }
 
+/// The `name: value` arguments of a call, in the order they were written.
+type NamedArguments = Vec<(String, Expression)>;
+
 pub struct Parser<'a> {
-    tokens: &'a Tokens,
+    tokens: Cow<'a, Tokens>,
     current: usize,
 }
 
 impl<'a> Parser<'a> {
     #[cfg(test)]
-    pub fn parse_expr_from_tokens(tokens: &'a Tokens) -> Expression {
-        let mut parser = Self { tokens, current: 0 };
+    pub fn parse_expr_from_tokens(tokens: &'a Tokens) -> CompilerResult<Expression> {
+        let mut parser = Self::new(tokens);
         parser.parse_expression()
     }
 
     #[cfg(test)]
-    pub fn parse_expression(&mut self) -> Expression {
+    pub fn parse_expression(&mut self) -> CompilerResult<Expression> {
         self.expression()
     }
 
+    /// Whitespace and comments are skipped if the tokens were scanned with
+    /// trivia preserved.
     pub fn new(tokens: &'a Tokens) -> Self {
+        let tokens = if tokens.has_trivia() {
+            Cow::Owned(tokens.without_trivia())
+        } else {
+            Cow::Borrowed(tokens)
+        };
         Self { tokens, current: 0 }
     }
 
-    pub fn parse(&mut self) -> Program {
-        let mut program = Program(vec![]);
+    /// Stops at the first syntax error, as `Scanner::scan_tokens` does.
+    pub fn parse(&mut self) -> CompilerResult<Program> {
+        let mut program = Program::new();
 
         while self.current < self.tokens.len() {
-            program.add_declaration(self.declaration());
+            let (declaration, excerpt) = self.written_declaration()?;
+            program.declarations.push(declaration);
+            program.excerpts.push(Some(excerpt));
         }
 
-        program
+        Ok(program)
     }
 
     fn peek(&self) -> Option<&Token> {
@@ -260,135 +499,492 @@ impl<'a> Parser<'a> {
         self.current += n;
     }
 
-    fn consume_semicolon(&mut self) {
-        if matches!(self.peek(), Some(Token::SemiColon)) {
-            self.advance();
-        } else {
-            panic!("Expected a semicolon");
+    fn consume(&mut self, expected: Token, message: &str) -> CompilerResult<()> {
+        if self.peek() != Some(&expected) {
+            return Err(message.to_string());
         }
+        self.advance();
+        Ok(())
     }
 
-    fn consume_brace(&mut self, direction: TokenDirection) {
-        match direction {
-            TokenDirection::Left => if matches!(self.peek(), Some(Token::Brace(TokenDirection::Left))) {
-                self.advance()
-            } else {
-                panic!("Expected left brace")
-            },
-            TokenDirection::Right => if matches!(self.peek(), Some(Token::Brace(TokenDirection::Right))) {
-                self.advance()
-            } else {
-                panic!("Expected right brace")
-            },
+    fn consume_semicolon(&mut self) -> CompilerResult<()> {
+        self.consume(Token::SemiColon, "Expected a semicolon")
+    }
+
+    fn consume_identifier(&mut self) -> CompilerResult<String> {
+        match self.peek_then_advance() {
+            Some(Token::Identifier(identifier)) => Ok(identifier.to_string()),
+            token => Err(format!("Expected an identifier not {token:?}")),
         }
     }
 
-    fn declaration(&mut self) -> Declaration {
+    fn consume_brace(&mut self, direction: TokenDirection) -> CompilerResult<()> {
+        let message = match direction {
+            TokenDirection::Left => "Expected left brace",
+            TokenDirection::Right => "Expected right brace",
+        };
+        self.consume(Token::Brace(direction), message)
+    }
+
+    fn declaration(&mut self) -> CompilerResult<Declaration> {
         match self.peek() {
-            Some(Token::Brace(TokenDirection::Left)) => Declaration::Block(self.block()),
+            Some(Token::At) => self.annotated_declaration(),
+            Some(Token::Brace(TokenDirection::Left)) => Ok(Declaration::Block(self.block()?)),
+            Some(Token::Keyword(Keyword::Class)) => self.class_declaration(),
+            Some(Token::Keyword(Keyword::For)) => self.for_declaration(None),
+            Some(Token::Keyword(Keyword::Function))
+                if self.peek_nth(1) != Some(&Token::Paren(TokenDirection::Left)) =>
+            {
+                self.function_declaration()
+            }
+            Some(Token::Keyword(Keyword::VariableDeclaration)) => self.variable_declaration(),
             Some(Token::Identifier(_)) => self.identifier(),
             _ => self.statement_declaration(),
         }
     }
 
-    fn block(&mut self) -> Block {
-        self.consume_brace(TokenDirection::Left);
-        
+    /// Parses a declaration along with where it was written.
+    fn written_declaration(&mut self) -> CompilerResult<(Declaration, Excerpt)> {
+        let start = self.current;
+        let declaration = self.declaration()?;
+        Ok((declaration, self.tokens.excerpt(start, self.current)))
+    }
+
+    fn annotated_declaration(&mut self) -> CompilerResult<Declaration> {
+        let mut directives = vec![];
+        while matches!(self.peek(), Some(Token::At)) {
+            self.advance();
+            directives.push(self.consume_identifier()?);
+        }
+
+        Ok(Declaration::Annotated {
+            directives,
+            declaration: Box::new(self.declaration()?),
+        })
+    }
+
+    fn block(&mut self) -> CompilerResult<Block> {
+        self.consume_brace(TokenDirection::Left)?;
+
         let mut declarations = vec![];
+        let mut excerpts = vec![];
+        while !matches!(self.peek(), Some(Token::Brace(TokenDirection::Right))) {
+            let (declaration, excerpt) = self.written_declaration()?;
+            declarations.push(declaration);
+            excerpts.push(Some(excerpt));
+        }
+        self.consume_brace(TokenDirection::Right)?;
+
+        Ok(Block {
+            declarations,
+            excerpts,
+        })
+    }
+
+    fn class_declaration(&mut self) -> CompilerResult<Declaration> {
+        self.advance();
+        let name = self.consume_identifier()?;
+        self.consume_brace(TokenDirection::Left)?;
+
+        let mut methods = vec![];
         while !matches!(self.peek(), Some(Token::Brace(TokenDirection::Right))) {
-            declarations.push(self.declaration());
+            methods.push(self.function()?);
+        }
+        self.consume_brace(TokenDirection::Right)?;
+
+        Ok(Declaration::Class { name, methods })
+    }
+
+    fn function_declaration(&mut self) -> CompilerResult<Declaration> {
+        self.advance();
+        Ok(Declaration::Function(Rc::new(self.function()?)))
+    }
+
+    fn function(&mut self) -> CompilerResult<Function> {
+        let name = self.consume_identifier()?;
+        self.parameters_and_body(name)
+    }
+
+    /// Parses `fun(parameters) { ... }`, after the `fun` keyword.
+    fn lambda(&mut self) -> CompilerResult<Expression> {
+        let function = self.parameters_and_body(LAMBDA_NAME.to_string())?;
+        Ok(Expression::Lambda(Rc::new(function)))
+    }
+
+    fn parameters_and_body(&mut self, name: String) -> CompilerResult<Function> {
+        self.consume(
+            Token::Paren(TokenDirection::Left),
+            "Expected '(' after function name",
+        )?;
+        let mut parameters = vec![];
+        let mut defaults = vec![];
+        let mut rest = None;
+        while !matches!(self.peek(), Some(Token::Paren(TokenDirection::Right))) {
+            if matches!(self.peek(), Some(Token::Ellipsis)) {
+                self.advance();
+                let parameter = self.consume_identifier()?;
+                if matches!(self.peek(), Some(Token::Comma)) {
+                    self.advance();
+                }
+                if !matches!(self.peek(), Some(Token::Paren(TokenDirection::Right))) {
+                    return Err(format!(
+                        "Rest parameter {parameter} must be the last parameter"
+                    ));
+                }
+                rest = Some(parameter);
+                break;
+            }
+
+            let parameter = self.consume_identifier()?;
+            if matches!(self.peek(), Some(Token::Equals)) {
+                self.advance();
+                defaults.push(self.expression()?);
+            } else if !defaults.is_empty() {
+                return Err(format!(
+                    "Parameter {parameter} needs a default as it follows one with a default"
+                ));
+            }
+            parameters.push(parameter);
+
+            if !matches!(self.peek(), Some(Token::Comma)) {
+                break;
+            }
+            self.advance();
+        }
+        self.consume(
+            Token::Paren(TokenDirection::Right),
+            "Expected ')' after parameters",
+        )?;
+
+        let body = self.block()?;
+
+        Ok(Function {
+            name,
+            parameters,
+            defaults,
+            rest,
+            body,
+        })
+    }
+
+    fn variable_declaration(&mut self) -> CompilerResult<Declaration> {
+        self.advance();
+
+        if matches!(self.peek(), Some(Token::Paren(TokenDirection::Left))) {
+            return self.destructuring();
         }
-        self.consume_brace(TokenDirection::Right);
 
-        Block(declarations)
+        let identifier = Symbol::intern(&self.consume_identifier()?);
+        self.consume(Token::Equals, "Expected '=' after variable name")?;
+        let value = self.expression()?;
+        self.consume_semicolon()?;
+        Ok(Declaration::Variable { identifier, value })
+    }
+
+    fn destructuring(&mut self) -> CompilerResult<Declaration> {
+        self.advance();
+
+        let mut identifiers = vec![];
+        while !matches!(self.peek(), Some(Token::Paren(TokenDirection::Right))) {
+            identifiers.push(self.consume_identifier()?);
+
+            if !matches!(self.peek(), Some(Token::Comma)) {
+                break;
+            }
+            self.advance();
+        }
+        self.consume(
+            Token::Paren(TokenDirection::Right),
+            "Expected ')' after variable names",
+        )?;
+
+        self.consume(Token::Equals, "Expected '=' after variable names")?;
+        let value = self.expression()?;
+        self.consume_semicolon()?;
+        Ok(Declaration::Destructuring { identifiers, value })
     }
 
-    fn identifier(&mut self) -> Declaration {
+    fn identifier(&mut self) -> CompilerResult<Declaration> {
+        if let Some(Token::Colon) = self.peek_nth(1) {
+            return self.labeled_loop();
+        }
+
         self.variable_assignment()
     }
 
-    fn variable_assignment(&mut self) -> Declaration {
+    /// Parses `label: loop`, where the loop is a `while`, `do while` or `for`.
+    fn labeled_loop(&mut self) -> CompilerResult<Declaration> {
+        let label = Some(self.consume_identifier()?);
+        self.consume(Token::Colon, "Expected ':' after label")?;
+
+        match self.peek() {
+            Some(Token::Keyword(Keyword::While)) => {
+                Ok(Declaration::Statement(self.while_statement(label)?))
+            }
+            Some(Token::Keyword(Keyword::Do)) => {
+                Ok(Declaration::Statement(self.do_while_statement(label)?))
+            }
+            Some(Token::Keyword(Keyword::For)) => self.for_declaration(label),
+            token => Err(format!("Expected a loop after label not {token:?}")),
+        }
+    }
+
+    fn variable_assignment(&mut self) -> CompilerResult<Declaration> {
         if let Some(Token::Identifier(identifier)) = self.peek() {
-            let identifier = identifier.to_string();
+            let identifier = Symbol::intern(identifier);
             if let Some(Token::Equals) = self.peek_nth(1) {
                 self.advance_nth(2);
                 let assignment = Declaration::VariableAssignment {
                     identifier,
-                    value: self.expression(),
+                    value: self.expression()?,
                 };
-                self.consume_semicolon();
-                return assignment;
+                self.consume_semicolon()?;
+                return Ok(assignment);
             }
         }
 
         self.statement_declaration()
     }
 
-    fn statement_declaration(&mut self) -> Declaration {
-        Declaration::Statement(self.statement())
+    fn statement_declaration(&mut self) -> CompilerResult<Declaration> {
+        Ok(Declaration::Statement(self.statement()?))
     }
 
-    fn statement(&mut self) -> Statement {
+    fn statement(&mut self) -> CompilerResult<Statement> {
         match self.peek() {
             Some(Token::Keyword(Keyword::Print)) => self.print(),
             Some(Token::Keyword(Keyword::If)) => self.if_statement(),
+            Some(Token::Keyword(Keyword::Do)) => self.do_while_statement(None),
+            Some(Token::Keyword(Keyword::While)) => self.while_statement(None),
+            Some(Token::Keyword(Keyword::Break)) => Ok(Statement::Break(self.jump_label()?)),
+            Some(Token::Keyword(Keyword::Continue)) => Ok(Statement::Continue(self.jump_label()?)),
+            Some(Token::Keyword(Keyword::Return)) => self.return_statement(),
+            Some(Token::Keyword(Keyword::Delete)) => self.delete_statement(),
             _ => self.expression_statement(),
         }
     }
 
-    fn print(&mut self) -> Statement {
+    fn print(&mut self) -> CompilerResult<Statement> {
+        self.advance();
+        let mut expressions = vec![self.expression()?];
+        while matches!(self.peek(), Some(Token::Comma)) {
+            self.advance();
+            expressions.push(self.expression()?);
+        }
+        self.consume_semicolon()?;
+        Ok(Statement::Print(expressions))
+    }
+
+    fn if_statement(&mut self) -> CompilerResult<Statement> {
+        // `else if` chains are collected into a flat list of branches rather than
+        // nesting a new `If` inside each `else`, so long chains don't recurse.
+        let mut branches = vec![];
+        let mut else_declaration = None;
+
+        loop {
+            self.advance();
+            let condition = self.expression()?;
+            let declaration = self.declaration()?;
+            branches.push(ConditionalBranch {
+                condition,
+                declaration,
+            });
+
+            if !matches!(self.peek(), Some(Token::Keyword(Keyword::Else))) {
+                break;
+            }
+            self.advance();
+
+            if !matches!(self.peek(), Some(Token::Keyword(Keyword::If))) {
+                else_declaration = Some(Box::new(self.declaration()?));
+                break;
+            }
+        }
+
+        Ok(Statement::If {
+            branches,
+            else_declaration,
+        })
+    }
+
+    fn do_while_statement(&mut self, label: Option<String>) -> CompilerResult<Statement> {
+        self.advance();
+        let body = self.block()?;
+        self.consume(
+            Token::Keyword(Keyword::While),
+            "Expected 'while' after do block",
+        )?;
+        let condition = self.expression()?;
+        self.consume_semicolon()?;
+        Ok(Statement::DoWhile {
+            body,
+            condition,
+            label,
+        })
+    }
+
+    fn while_statement(&mut self, label: Option<String>) -> CompilerResult<Statement> {
+        self.advance();
+        let condition = self.expression()?;
+        let body = Box::new(self.declaration()?);
+        Ok(Statement::While {
+            condition,
+            body,
+            increment: None,
+            label,
+        })
+    }
+
+    /// Parses the optional label after `break` or `continue`, along with the
+    /// semicolon ending the statement.
+    fn jump_label(&mut self) -> CompilerResult<Option<String>> {
+        self.advance();
+        let label = match self.peek() {
+            Some(Token::SemiColon) => None,
+            _ => Some(self.consume_identifier()?),
+        };
+        self.consume_semicolon()?;
+        Ok(label)
+    }
+
+    fn return_statement(&mut self) -> CompilerResult<Statement> {
+        self.advance();
+        let value = match self.peek() {
+            Some(Token::SemiColon) => None,
+            _ => Some(self.expression()?),
+        };
+        self.consume_semicolon()?;
+        Ok(Statement::Return(value))
+    }
+
+    fn delete_statement(&mut self) -> CompilerResult<Statement> {
         self.advance();
-        let expr = self.expression();
-        self.consume_semicolon();
-        Statement::Print(expr)
+        let identifier = self.consume_identifier()?;
+        self.consume_semicolon()?;
+        Ok(Statement::Delete(identifier))
     }
 
-    fn if_statement(&mut self) -> Statement {
+    /// Desugars `for (initializer; condition; increment) body` into a block
+    /// containing the initializer followed by a `while` loop, both of which
+    /// are quoted as the `for` loop they were written as.
+    fn for_declaration(&mut self, label: Option<String>) -> CompilerResult<Declaration> {
+        let start = self.current;
         self.advance();
-        let condition = self.expression();
-        let declaration = Box::new(self.declaration());
-        Statement::If { condition, declaration }
+        self.consume(Token::Paren(TokenDirection::Left), "Expected '(' after for")?;
+
+        let initializer = match self.peek() {
+            Some(Token::SemiColon) => {
+                self.advance();
+                None
+            }
+            Some(Token::Keyword(Keyword::VariableDeclaration)) => {
+                Some(self.variable_declaration()?)
+            }
+            _ => Some(self.variable_assignment()?),
+        };
+
+        let condition = match self.peek() {
+            Some(Token::SemiColon) => Expression::Literal(LiteralValue::Boolean(true)),
+            _ => self.expression()?,
+        };
+        self.consume_semicolon()?;
+
+        let increment = match self.peek() {
+            Some(Token::Paren(TokenDirection::Right)) => None,
+            _ => Some(self.expression()?),
+        };
+        self.consume(
+            Token::Paren(TokenDirection::Right),
+            "Expected ')' after for clauses",
+        )?;
+
+        let body = Box::new(self.declaration()?);
+        let while_loop = Declaration::Statement(Statement::While {
+            condition,
+            body,
+            increment,
+            label,
+        });
+
+        let declarations: Vec<_> = initializer.into_iter().chain([while_loop]).collect();
+        let excerpt = self.tokens.excerpt(start, self.current);
+        Ok(Declaration::Block(Block {
+            excerpts: vec![Some(excerpt); declarations.len()],
+            declarations,
+        }))
     }
 
-    fn expression_statement(&mut self) -> Statement {
-        let expr = self.expression();
-        self.consume_semicolon();
-        Statement::Expression(expr)
+    fn expression_statement(&mut self) -> CompilerResult<Statement> {
+        let expr = self.expression()?;
+        self.consume_semicolon()?;
+        Ok(Statement::Expression(expr))
     }
 
-    fn expression(&mut self) -> Expression {
-        self.equality()
+    fn expression(&mut self) -> CompilerResult<Expression> {
+        self.assignment()
+    }
+
+    fn assignment(&mut self) -> CompilerResult<Expression> {
+        let expr = self.coalesce()?;
+
+        if matches!(self.peek(), Some(Token::Equals)) {
+            self.advance();
+            let value = Box::new(self.assignment()?);
+
+            return match expr {
+                Expression::Variable(identifier) => {
+                    Ok(Expression::Assignment { identifier, value })
+                }
+                expr => Err(format!("Invalid assignment target {expr:?}")),
+            };
+        }
+
+        Ok(expr)
+    }
+
+    fn coalesce(&mut self) -> CompilerResult<Expression> {
+        let mut expr = self.equality()?;
+
+        while matches!(self.peek(), Some(Token::QuestionQuestion)) {
+            self.advance();
+            let right = Box::new(self.equality()?);
+            let left = Box::new(expr);
+
+            expr = Expression::Coalesce { left, right };
+        }
+
+        Ok(expr)
     }
 
-    fn equality(&mut self) -> Expression {
-        let mut expr = self.comparison();
+    fn equality(&mut self) -> CompilerResult<Expression> {
+        let mut expr = self.comparison()?;
 
         while matches!(self.peek(), Some(Token::BangEquals | Token::EqualsEquals)) {
-            let operator: Operator = self.peek().unwrap().into();
+            let operator: BinaryOperator = self.peek().unwrap().into();
             self.advance();
-            let right = Box::new(self.comparison());
+            let right = Box::new(self.comparison()?);
             let left = Box::new(expr);
 
             expr = Expression::Binary {
                 left,
                 right,
-                operator: operator.into(),
+                operator,
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn comparison(&mut self) -> Expression {
-        let mut expr = self.term();
+    fn comparison(&mut self) -> CompilerResult<Expression> {
+        let mut expr = self.term()?;
 
         while matches!(
             self.peek(),
             Some(Token::Greater | Token::GreaterEqual | Token::Less | Token::LessEqual)
         ) {
-            let operator: Operator = self.peek().unwrap().into();
+            let operator: BinaryOperator = self.peek().unwrap().into();
             self.advance();
-            let right = Box::new(self.term());
+            let right = Box::new(self.term()?);
             let left = Box::new(expr);
 
             let _result = format!("{left:?}, {operator:?}, {right:?}");
@@ -396,106 +992,389 @@ impl<'a> Parser<'a> {
             expr = Expression::Binary {
                 left,
                 right,
-                operator: operator.into(),
+                operator,
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn term(&mut self) -> Expression {
-        let mut expr = self.factor();
+    fn term(&mut self) -> CompilerResult<Expression> {
+        let mut expr = self.factor()?;
 
         while matches!(self.peek(), Some(Token::Minus | Token::Plus)) {
-            let operator: Operator = self.peek().unwrap().into();
+            let operator: BinaryOperator = self.peek().unwrap().into();
             self.advance();
-            let right = Box::new(self.factor());
+            let right = Box::new(self.factor()?);
             let left = Box::new(expr);
 
             expr = Expression::Binary {
                 left,
                 right,
-                operator: operator.into(),
+                operator,
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn factor(&mut self) -> Expression {
-        let mut expr = self.unary();
+    fn factor(&mut self) -> CompilerResult<Expression> {
+        let mut expr = self.unary()?;
 
         while matches!(self.peek(), Some(Token::Slash | Token::Star)) {
-            let operator: Operator = self.peek().unwrap().into();
+            let operator: BinaryOperator = self.peek().unwrap().into();
             self.advance();
-            let right = Box::new(self.unary());
+            let right = Box::new(self.unary()?);
             let left = Box::new(expr);
 
             expr = Expression::Binary {
                 left,
                 right,
-                operator: operator.into(),
+                operator,
             };
         }
 
-        expr
+        Ok(expr)
     }
 
-    fn unary(&mut self) -> Expression {
+    fn unary(&mut self) -> CompilerResult<Expression> {
         let current = self.peek();
         if matches!(current, Some(Token::Bang | Token::Minus | Token::Plus)) {
-            let operator: Operator = current.unwrap().into();
+            let operator: UnaryOperator = current.unwrap().into();
             self.advance();
-            let right = Box::new(self.unary());
+            let right = Box::new(self.unary()?);
 
-            return Expression::Unary { right, operator };
+            return Ok(Expression::Unary { right, operator });
         }
 
-        self.primary()
+        self.call()
     }
 
-    fn primary(&mut self) -> Expression {
-        match self.peek_then_advance() {
-            Some(Token::Keyword(Keyword::False)) => {
-                Expression::Literal(LiteralValue::Boolean(false))
-            }
-            Some(Token::Keyword(Keyword::True)) => Expression::Literal(LiteralValue::Boolean(true)),
-            Some(Token::Keyword(Keyword::Nil)) => Expression::Literal(LiteralValue::Nil),
-            Some(Token::Number(number)) => {
-                Expression::Literal(LiteralValue::Number(number.clone()))
-            }
-            Some(Token::String(string)) => Expression::Literal(LiteralValue::String(
-                string[1..string.len() - 1].to_string(),
-            )),
-            Some(Token::Identifier(identifier)) => {
-                Expression::Literal(LiteralValue::Identifier(identifier.to_string()))
-            }
-            Some(Token::Paren(TokenDirection::Left)) => {
-                let expr = self.expression();
-                match self.peek_then_advance() {
-                    Some(Token::Paren(TokenDirection::Right)) => {
-                        Expression::Grouping(Box::new(expr))
-                    }
-                    _ => panic!("Expected ')' after expression"),
+    fn call(&mut self) -> CompilerResult<Expression> {
+        let mut expr = self.primary()?;
+
+        loop {
+            match self.peek() {
+                Some(Token::Paren(TokenDirection::Left)) => {
+                    self.advance();
+                    let (arguments, named) = self.arguments()?;
+
+                    expr = Expression::Call {
+                        callee: Box::new(expr),
+                        arguments,
+                        named,
+                    };
                 }
+                Some(Token::Bracket(TokenDirection::Left)) => {
+                    self.advance();
+                    let index = Box::new(self.expression()?);
+                    self.consume(
+                        Token::Bracket(TokenDirection::Right),
+                        "Expected ']' after index",
+                    )?;
+
+                    expr = Expression::Index {
+                        target: Box::new(expr),
+                        index,
+                    };
+                }
+                Some(Token::Dot | Token::QuestionDot) => {
+                    let optional = matches!(self.peek(), Some(Token::QuestionDot));
+                    self.advance();
+                    let name = self.consume_identifier()?;
+
+                    expr = Expression::Get {
+                        object: Box::new(expr),
+                        name,
+                        optional,
+                    };
+                }
+                _ => return Ok(expr),
             }
-            None => panic!("TODO: Handle EOF"),
-            _ => panic!("Syntax error??"),
         }
     }
-}
 
-#[cfg(test)]
-#[macro_export]
-macro_rules! expr {
-    ($source:expr) => {
-        Parser::parse_expr_from_tokens(&tokens!($source).unwrap())
-    };
-}
+    /// Parses the arguments of a call up to and including the closing paren,
+    /// returning the positional and named arguments separately. As in Python,
+    /// named arguments (`name: value`) can't be followed by positional ones.
+    fn arguments(&mut self) -> CompilerResult<(Vec<Expression>, NamedArguments)> {
+        let mut arguments = vec![];
+        let mut named: NamedArguments = vec![];
+
+        while !matches!(self.peek(), Some(Token::Paren(TokenDirection::Right))) {
+            if matches!(self.peek_nth(1), Some(Token::Colon)) {
+                let name = self.consume_identifier()?;
+                self.advance();
+                if named.iter().any(|(existing, _)| *existing == name) {
+                    return Err(format!("Argument {name} is given more than once"));
+                }
+                named.push((name, self.expression()?));
+            } else if named.is_empty() {
+                arguments.push(self.expression()?);
+            } else {
+                return Err("Positional arguments must come before named arguments".to_string());
+            }
 
-#[cfg(test)]
-mod tests {
-    use crate::{lib::scanner::Scanner, tokens};
+            if !matches!(self.peek(), Some(Token::Comma)) {
+                break;
+            }
+            self.advance();
+        }
+
+        match self.peek_then_advance() {
+            Some(Token::Paren(TokenDirection::Right)) => Ok((arguments, named)),
+            _ => Err("Expected ')' after arguments".to_string()),
+        }
+    }
+
+    /// Parses comma separated expressions up to (but not including) the closing
+    /// delimiter, allowing a trailing comma before it.
+    fn comma_separated(
+        &mut self,
+        is_closing: fn(Option<&Token>) -> bool,
+    ) -> CompilerResult<Vec<Expression>> {
+        let mut expressions = vec![];
+
+        while !is_closing(self.peek()) {
+            expressions.push(self.expression()?);
+
+            if !matches!(self.peek(), Some(Token::Comma)) {
+                break;
+            }
+            self.advance();
+        }
+
+        Ok(expressions)
+    }
+
+    /// Whether the tokens after an opening brace are a map literal rather than
+    /// a block expression, i.e. `{}` or a key followed by a colon.
+    fn starts_map(&self) -> bool {
+        match self.peek() {
+            Some(Token::Brace(TokenDirection::Right)) => true,
+            Some(Token::Identifier(_) | Token::String(_)) => {
+                matches!(self.peek_nth(1), Some(Token::Colon))
+            }
+            _ => false,
+        }
+    }
+
+    /// Parses the rest of a block expression, after its opening brace. An
+    /// expression which is directly followed by the closing brace becomes the
+    /// value of the block.
+    fn block_expression(&mut self) -> CompilerResult<Expression> {
+        let mut declarations = vec![];
+        let mut excerpts = vec![];
+        let mut value = None;
+
+        while !matches!(self.peek(), Some(Token::Brace(TokenDirection::Right))) {
+            if !self.starts_expression_statement() {
+                let (declaration, excerpt) = self.written_declaration()?;
+                declarations.push(declaration);
+                excerpts.push(Some(excerpt));
+                continue;
+            }
+
+            let start = self.current;
+            let expression = self.expression()?;
+            if matches!(self.peek(), Some(Token::Brace(TokenDirection::Right))) {
+                value = Some(Box::new(expression));
+                break;
+            }
+            self.consume_semicolon()?;
+            declarations.push(Declaration::Statement(Statement::Expression(expression)));
+            excerpts.push(Some(self.tokens.excerpt(start, self.current)));
+        }
+        self.consume_brace(TokenDirection::Right)?;
+
+        Ok(Expression::Block {
+            body: Block {
+                declarations,
+                excerpts,
+            },
+            value,
+        })
+    }
+
+    /// Parses the rest of an `if` expression, after the `if`. Unlike the
+    /// statement, each branch must be a block, so that it has a value.
+    fn if_expression(&mut self) -> CompilerResult<Expression> {
+        let condition = Box::new(self.expression()?);
+        self.consume_brace(TokenDirection::Left)?;
+        let then_branch = Box::new(self.block_expression()?);
+
+        let else_branch = if matches!(self.peek(), Some(Token::Keyword(Keyword::Else))) {
+            self.advance();
+            match self.peek_then_advance() {
+                Some(Token::Keyword(Keyword::If)) => Some(Box::new(self.if_expression()?)),
+                Some(Token::Brace(TokenDirection::Left)) => {
+                    Some(Box::new(self.block_expression()?))
+                }
+                _ => return Err("Expected left brace or 'if' after 'else'".to_string()),
+            }
+        } else {
+            None
+        };
+
+        Ok(Expression::If {
+            condition,
+            then_branch,
+            else_branch,
+        })
+    }
+
+    /// Whether `declaration` would parse the upcoming tokens as an expression
+    /// statement.
+    fn starts_expression_statement(&self) -> bool {
+        match self.peek() {
+            Some(Token::At | Token::Brace(TokenDirection::Left)) => false,
+            Some(Token::Keyword(Keyword::Function)) => {
+                matches!(self.peek_nth(1), Some(Token::Paren(TokenDirection::Left)))
+            }
+            Some(Token::Keyword(keyword)) => !matches!(
+                keyword,
+                Keyword::Break
+                    | Keyword::Class
+                    | Keyword::Continue
+                    | Keyword::Delete
+                    | Keyword::Do
+                    | Keyword::For
+                    | Keyword::If
+                    | Keyword::Print
+                    | Keyword::Return
+                    | Keyword::VariableDeclaration
+                    | Keyword::While
+            ),
+            Some(Token::Identifier(_)) => {
+                !matches!(self.peek_nth(1), Some(Token::Equals | Token::Colon))
+            }
+            _ => true,
+        }
+    }
+
+    /// Parses the expression inside a `${...}`, which must be all of the
+    /// tokens.
+    fn interpolated(&mut self) -> CompilerResult<Expression> {
+        if self.peek().is_none() {
+            return Err("Expected an expression inside ${}".to_string());
+        }
+        let expression = self.expression()?;
+        if self.current < self.tokens.len() {
+            return Err("Expected '}' after interpolated expression".to_string());
+        }
+        Ok(expression)
+    }
+
+    /// Parses the entries of a map literal, after its opening brace. Keys are
+    /// either identifiers or strings, and a trailing comma is allowed.
+    fn map(&mut self) -> CompilerResult<Expression> {
+        let mut entries = vec![];
+
+        while !matches!(self.peek(), Some(Token::Brace(TokenDirection::Right))) {
+            let key = match self.peek_then_advance() {
+                Some(Token::Identifier(key)) => key.to_string(),
+                Some(Token::String(key)) => key[1..key.len() - 1].to_string(),
+                token => return Err(format!("Expected a map key not {token:?}")),
+            };
+            self.consume(Token::Colon, "Expected ':' after map key")?;
+            entries.push((key, self.expression()?));
+
+            if !matches!(self.peek(), Some(Token::Comma)) {
+                break;
+            }
+            self.advance();
+        }
+        self.consume_brace(TokenDirection::Right)?;
+
+        Ok(Expression::Map(entries))
+    }
+
+    fn primary(&mut self) -> CompilerResult<Expression> {
+        let expression = match self.peek_then_advance() {
+            Some(Token::Keyword(Keyword::False)) => {
+                Expression::Literal(LiteralValue::Boolean(false))
+            }
+            Some(Token::Keyword(Keyword::True)) => Expression::Literal(LiteralValue::Boolean(true)),
+            Some(Token::Keyword(Keyword::Nil)) => Expression::Literal(LiteralValue::Nil),
+            Some(Token::Number(number)) => Expression::Literal(LiteralValue::Number(*number)),
+            Some(Token::String(string)) => Expression::Literal(LiteralValue::String(
+                string[1..string.len() - 1].to_string(),
+            )),
+            Some(Token::Interpolation(parts)) => Expression::Interpolation(
+                parts
+                    .iter()
+                    .map(|part| match part {
+                        StringPart::Text(text) => {
+                            Ok(Expression::Literal(LiteralValue::String(text.clone())))
+                        }
+                        StringPart::Expression(tokens) => Parser::new(tokens).interpolated(),
+                    })
+                    .collect::<CompilerResult<_>>()?,
+            ),
+            Some(Token::Identifier(identifier)) => Expression::Variable(Symbol::intern(identifier)),
+            // A comma after the first expression makes this a tuple rather than
+            // a grouping, e.g. `(a, b)` or `(a,)`.
+            Some(Token::Paren(TokenDirection::Left)) => {
+                let expr = self.expression()?;
+                if !matches!(self.peek(), Some(Token::Comma)) {
+                    self.consume(
+                        Token::Paren(TokenDirection::Right),
+                        "Expected ')' after expression",
+                    )?;
+                    return Ok(Expression::Grouping(Box::new(expr)));
+                }
+                self.advance();
+
+                let mut elements = vec![expr];
+                elements.extend(self.comma_separated(|token| {
+                    matches!(token, Some(Token::Paren(TokenDirection::Right)))
+                })?);
+                self.consume(
+                    Token::Paren(TokenDirection::Right),
+                    "Expected ')' after tuple elements",
+                )?;
+                Expression::Tuple(elements)
+            }
+            Some(Token::Bracket(TokenDirection::Left)) => {
+                let elements = self.comma_separated(|token| {
+                    matches!(token, Some(Token::Bracket(TokenDirection::Right)))
+                })?;
+                match self.peek_then_advance() {
+                    Some(Token::Bracket(TokenDirection::Right)) => Expression::List(elements),
+                    _ => return Err("Expected ']' after list elements".to_string()),
+                }
+            }
+            Some(Token::Keyword(Keyword::Function)) => self.lambda()?,
+            Some(Token::Keyword(Keyword::If)) => self.if_expression()?,
+            Some(Token::Brace(TokenDirection::Left)) => {
+                if self.starts_map() {
+                    self.map()?
+                } else {
+                    self.block_expression()?
+                }
+            }
+            None => {
+                return Err("Expected an expression but reached the end of the input".to_string())
+            }
+            Some(token) => return Err(format!("Expected an expression not {token:?}")),
+        };
+
+        Ok(expression)
+    }
+}
+
+#[cfg(test)]
+#[macro_export]
+macro_rules! expr {
+    ($source:expr) => {
+        Parser::parse_expr_from_tokens(&tokens!($source).unwrap())
+            .expect("Parser should not fail to parse expression")
+    };
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{lib::scanner::Scanner, tokens};
 
     use super::*;
 
@@ -506,12 +1385,42 @@ mod tests {
         assert_eq!(format!("{expression:?}"), "Literal(Boolean(true))");
     }
 
+    #[test]
+    fn cloned_trees_are_equal() {
+        let expression = expr!("f(1 + 2, [a, \"b\"])[0] * -x");
+        let program = Parser::new(
+            &tokens!("fun f(x) { while x > 0 { x = x - 1; } return x; } print f(2);")
+                .expect("Scanner should not fail to parse source"),
+        )
+        .parse()
+        .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(expression.clone(), expression);
+        assert_ne!(expression, expr!("f(1 + 2, [a, \"b\"])[0] * x"));
+        assert_eq!(program.clone(), program);
+    }
+
+    #[test]
+    fn empty_source() {
+        for source in ["", "  \n\t", "// just a comment\n// and another"] {
+            let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+
+            let program = Parser::new(&tokens)
+                .parse()
+                .expect("Parser should not fail to parse tokens");
+
+            assert_eq!(format!("{program:?}"), "Program([])", "{source:?}");
+        }
+    }
+
     #[test]
     fn boolean() {
         let tokens = tokens!("true").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse_expression();
+        let result = parser
+            .parse_expression()
+            .expect("Parser should not fail to parse expression");
 
         assert_eq!(format!("{result:?}"), "Literal(Boolean(true))");
     }
@@ -521,7 +1430,9 @@ mod tests {
         let tokens = tokens!("(true)").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse_expression();
+        let result = parser
+            .parse_expression()
+            .expect("Parser should not fail to parse expression");
 
         assert_eq!(format!("{result:?}"), "Grouping(Literal(Boolean(true)))");
     }
@@ -531,7 +1442,9 @@ mod tests {
         let tokens = tokens!("(true < false)").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse_expression();
+        let result = parser
+            .parse_expression()
+            .expect("Parser should not fail to parse expression");
 
         assert_eq!(format!("{result:?}"), "Grouping(Binary { left: Literal(Boolean(true)), right: Literal(Boolean(false)), operator: Less })");
     }
@@ -541,7 +1454,9 @@ mod tests {
         let tokens = tokens!("123 > 321").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse_expression();
+        let result = parser
+            .parse_expression()
+            .expect("Parser should not fail to parse expression");
 
         assert_eq!(format!("{result:?}"), "Binary { left: Literal(Number(123.0)), right: Literal(Number(321.0)), operator: Greater }");
     }
@@ -551,18 +1466,32 @@ mod tests {
         let tokens = tokens!("!-99").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse_expression();
+        let result = parser
+            .parse_expression()
+            .expect("Parser should not fail to parse expression");
 
         assert_eq!(format!("{result:?}"), "Unary { right: Unary { right: Literal(Number(99.0)), operator: Minus }, operator: Bang }");
     }
 
+    #[test]
+    fn identifier_minus_number_is_binary() {
+        let result = expr!("a-99");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Binary { left: Variable(\"a\"), right: Literal(Number(99.0)), operator: Minus }"
+        );
+    }
+
     #[test]
     fn complex_expression() {
         let tokens =
             tokens!("123 * 2 - 456 < 42 + 99").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse_expression();
+        let result = parser
+            .parse_expression()
+            .expect("Parser should not fail to parse expression");
 
         assert_eq!(format!("{result:?}"), "Binary { left: Binary { left: Binary { left: Literal(Number(123.0)), right: Literal(Number(2.0)), operator: Star }, right: Literal(Number(456.0)), operator: Minus }, right: Binary { left: Literal(Number(42.0)), right: Literal(Number(99.0)), operator: Plus }, operator: Less }");
     }
@@ -572,21 +1501,154 @@ mod tests {
         let tokens = tokens!("(1)+2").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse_expression();
+        let result = parser
+            .parse_expression()
+            .expect("Parser should not fail to parse expression");
 
         assert_eq!(format!("{result:?}"), "Binary { left: Grouping(Literal(Number(1.0))), right: Literal(Number(2.0)), operator: Plus }");
     }
 
+    #[test]
+    fn bang_binds_tighter_than_equality() {
+        let result = expr!("!a == b");
+
+        assert_eq!(format!("{result:?}"), "Binary { left: Unary { right: Variable(\"a\"), operator: Bang }, right: Variable(\"b\"), operator: EqualsEquals }");
+    }
+
+    #[test]
+    fn bang_binds_looser_than_call() {
+        let result = expr!("!f()");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Unary { right: Call { callee: Variable(\"f\"), arguments: [], named: [] }, operator: Bang }"
+        );
+    }
+
+    #[test]
+    fn call_with_arguments() {
+        let result = expr!("f(1, a + 2)(3)");
+
+        assert_eq!(format!("{result:?}"), "Call { callee: Call { callee: Variable(\"f\"), arguments: [Literal(Number(1.0)), Binary { left: Variable(\"a\"), right: Literal(Number(2.0)), operator: Plus }], named: [] }, arguments: [Literal(Number(3.0))], named: [] }");
+    }
+
+    #[test]
+    fn call_with_named_arguments() {
+        let result = expr!("f(1, b: 2, c: x)");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Call { callee: Variable(\"f\"), arguments: [Literal(Number(1.0))], named: [(\"b\", Literal(Number(2.0))), (\"c\", Variable(\"x\"))] }"
+        );
+    }
+
+    #[test]
+    fn positional_argument_after_named_is_a_syntax_error() {
+        assert_eq!(
+            Parser::parse_expr_from_tokens(&tokens!("f(a: 1, 2)").unwrap()),
+            Err("Positional arguments must come before named arguments".to_string())
+        );
+    }
+
+    #[test]
+    fn repeated_named_argument_is_a_syntax_error() {
+        assert_eq!(
+            Parser::parse_expr_from_tokens(&tokens!("f(a: 1, a: 2)").unwrap()),
+            Err("Argument a is given more than once".to_string())
+        );
+    }
+
+    #[test]
+    fn call_trailing_comma() {
+        let result = expr!("f(1,)");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Call { callee: Variable(\"f\"), arguments: [Literal(Number(1.0))], named: [] }"
+        );
+    }
+
+    #[test]
+    fn list() {
+        let result = expr!("[1, 2]");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "List([Literal(Number(1.0)), Literal(Number(2.0))])"
+        );
+    }
+
+    #[test]
+    fn list_trailing_comma() {
+        let result = expr!("[1, 2,]");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "List([Literal(Number(1.0)), Literal(Number(2.0))])"
+        );
+    }
+
+    #[test]
+    fn empty_list() {
+        let result = expr!("[]");
+
+        assert_eq!(format!("{result:?}"), "List([])");
+    }
+
+    #[test]
+    fn assignment_expression() {
+        let result = expr!("(a = 1) + (b = c = 2)");
+
+        assert_eq!(format!("{result:?}"), "Binary { left: Grouping(Assignment { identifier: \"a\", value: Literal(Number(1.0)) }), right: Grouping(Assignment { identifier: \"b\", value: Assignment { identifier: \"c\", value: Literal(Number(2.0)) } }), operator: Plus }");
+    }
+
+    #[test]
+    fn identifiers_are_interned() {
+        let result = expr!("a = a");
+
+        assert_eq!(
+            result,
+            Expression::Assignment {
+                identifier: Symbol::intern("a"),
+                value: Box::new(Expression::Variable(Symbol::intern("a"))),
+            }
+        );
+    }
+
+    #[test]
+    fn coalesce_binds_looser_than_equality() {
+        let result = expr!("a ?? b == c ?? d");
+
+        assert_eq!(format!("{result:?}"), "Coalesce { left: Coalesce { left: Variable(\"a\"), right: Binary { left: Variable(\"b\"), right: Variable(\"c\"), operator: EqualsEquals } }, right: Variable(\"d\") }");
+    }
+
     #[test]
     fn print() {
         let tokens = tokens!("print 42;").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse();
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
 
         assert_eq!(
             format!("{result:?}"),
-            "Program([Statement(Print(Literal(Number(42.0))))])"
+            "Program([Statement(Print([Literal(Number(42.0))]))])"
+        );
+    }
+
+    #[test]
+    fn print_multiple() {
+        let tokens = tokens!("print 1, \"a\";").expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Statement(Print([Literal(Number(1.0)), Literal(String(\"a\"))]))])"
         );
     }
 
@@ -596,11 +1658,159 @@ mod tests {
             tokens!("print 42; print true;").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse();
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Statement(Print([Literal(Number(42.0))])), Statement(Print([Literal(Boolean(true))]))])"
+        );
+    }
+
+    #[test]
+    fn if_else() {
+        let tokens = tokens!("if true print 1; else print 2;")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
 
         assert_eq!(
             format!("{result:?}"),
-            "Program([Statement(Print(Literal(Number(42.0)))), Statement(Print(Literal(Boolean(true))))])"
+            "Program([Statement(If { branches: [ConditionalBranch { condition: Literal(Boolean(true)), declaration: Statement(Print([Literal(Number(1.0))])) }], else_declaration: Some(Statement(Print([Literal(Number(2.0))]))) })])"
+        );
+    }
+
+    #[test]
+    fn else_if_chain_is_flat() {
+        let tokens = tokens!("if a print 1; else if b print 2; else if c print 3; else print 4;")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        match &result.get_declarations()[..] {
+            [Declaration::Statement(Statement::If {
+                branches,
+                else_declaration: Some(_),
+            })] => assert_eq!(branches.len(), 3),
+            _ => panic!("Expected a single if statement, got {result:?}"),
+        }
+    }
+
+    #[test]
+    fn dangling_else_binds_to_nearest_if() {
+        let tokens = tokens!("if a if b print 1; else print 2;")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Statement(If { branches: [ConditionalBranch { condition: Variable(\"a\"), declaration: Statement(If { branches: [ConditionalBranch { condition: Variable(\"b\"), declaration: Statement(Print([Literal(Number(1.0))])) }], else_declaration: Some(Statement(Print([Literal(Number(2.0))]))) }) }], else_declaration: None })])"
+        );
+    }
+
+    #[test]
+    fn do_while() {
+        let tokens = tokens!("do { print 1; } while false;")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Statement(DoWhile { body: Block([Statement(Print([Literal(Number(1.0))]))]), condition: Literal(Boolean(false)), label: None })])"
+        );
+    }
+
+    #[test]
+    fn class() {
+        let tokens =
+            tokens!("class Foo { bar() {} }").expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Class { name: \"Foo\", methods: [Function { name: \"bar\", parameters: [], defaults: [], rest: None, body: Block([]) }] }])"
+        );
+    }
+
+    #[test]
+    fn class_method_parameters() {
+        let tokens = tokens!("class Point { move(x, y) { print x + y; } }")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Class { name: \"Point\", methods: [Function { name: \"move\", parameters: [\"x\", \"y\"], defaults: [], rest: None, body: Block([Statement(Print([Binary { left: Variable(\"x\"), right: Variable(\"y\"), operator: Plus }]))]) }] }])"
+        );
+    }
+
+    #[test]
+    fn while_loop() {
+        let tokens =
+            tokens!("while x < 3 x = x + 1;").expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Statement(While { condition: Binary { left: Variable(\"x\"), right: Literal(Number(3.0)), operator: Less }, body: VariableAssignment { identifier: \"x\", value: Binary { left: Variable(\"x\"), right: Literal(Number(1.0)), operator: Plus } }, increment: None, label: None })])"
+        );
+    }
+
+    #[test]
+    fn for_loop_desugars_to_while() {
+        let tokens = tokens!("for (i = 0; i < 3; i = i + 1) print i;")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Block(Block([VariableAssignment { identifier: \"i\", value: Literal(Number(0.0)) }, Statement(While { condition: Binary { left: Variable(\"i\"), right: Literal(Number(3.0)), operator: Less }, body: Statement(Print([Variable(\"i\")])), increment: Some(Assignment { identifier: \"i\", value: Binary { left: Variable(\"i\"), right: Literal(Number(1.0)), operator: Plus } }), label: None })]))])"
+        );
+    }
+
+    #[test]
+    fn for_loop_empty_clauses() {
+        let tokens = tokens!("for (;;) {}").expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Block(Block([Statement(While { condition: Literal(Boolean(true)), body: Block(Block([])), increment: None, label: None })]))])"
         );
     }
 
@@ -609,11 +1819,369 @@ mod tests {
         let tokens = tokens!("42;").expect("Scanner should not fail to parse source");
         let mut parser = Parser::new(&tokens);
 
-        let result = parser.parse();
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
 
         assert_eq!(
             format!("{result:?}"),
             "Program([Statement(Expression(Literal(Number(42.0))))])"
         );
     }
+
+    #[test]
+    fn function_declaration() {
+        let tokens = tokens!("fun add(a, b) { return a + b; }")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Function(Function { name: \"add\", parameters: [\"a\", \"b\"], defaults: [], rest: None, body: Block([Statement(Return(Some(Binary { left: Variable(\"a\"), right: Variable(\"b\"), operator: Plus })))]) })])"
+        );
+    }
+
+    #[test]
+    fn variable_declaration() {
+        let tokens = tokens!("var x = 1;").expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Variable { identifier: \"x\", value: Literal(Number(1.0)) }])"
+        );
+    }
+
+    #[test]
+    fn index() {
+        let result = expr!("xs[0][1]");
+
+        assert_eq!(format!("{result:?}"), "Index { target: Index { target: Variable(\"xs\"), index: Literal(Number(0.0)) }, index: Literal(Number(1.0)) }");
+    }
+
+    #[test]
+    fn delete() {
+        let tokens = tokens!("delete x;").expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(format!("{result:?}"), "Program([Statement(Delete(\"x\"))])");
+    }
+
+    #[test]
+    fn tuple() {
+        let result = expr!("(1, 2,)");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Tuple([Literal(Number(1.0)), Literal(Number(2.0))])"
+        );
+    }
+
+    #[test]
+    fn single_element_tuple() {
+        let result = expr!("(1,)");
+
+        assert_eq!(format!("{result:?}"), "Tuple([Literal(Number(1.0))])");
+    }
+
+    #[test]
+    fn destructuring() {
+        let tokens =
+            tokens!("var (x, y) = (1, 2);").expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Destructuring { identifiers: [\"x\", \"y\"], value: Tuple([Literal(Number(1.0)), Literal(Number(2.0))]) }])"
+        );
+    }
+
+    #[test]
+    fn binary_operators() {
+        for (source, operator) in [
+            ("1 != 2", "BangEquals"),
+            ("1 == 2", "EqualsEquals"),
+            ("1 > 2", "Greater"),
+            ("1 >= 2", "GreaterEqual"),
+            ("1 < 2", "Less"),
+            ("1 <= 2", "LessEqual"),
+            ("1 - 2", "Minus"),
+            ("1 + 2", "Plus"),
+            ("1 / 2", "Slash"),
+            ("1 * 2", "Star"),
+        ] {
+            let result = expr!(source);
+
+            assert_eq!(
+                format!("{result:?}"),
+                format!("Binary {{ left: Literal(Number(1.0)), right: Literal(Number(2.0)), operator: {operator} }}")
+            );
+        }
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected a binary operator token not Bang")]
+    fn bang_is_not_a_binary_operator() {
+        let _ = BinaryOperator::from(&Token::Bang);
+    }
+
+    #[test]
+    fn bang_in_binary_position_is_a_syntax_error() {
+        let tokens = tokens!("1 ! 2;").expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            Parser::new(&tokens).parse(),
+            Err("Expected a semicolon".to_string())
+        );
+    }
+
+    #[test]
+    fn unary_operators() {
+        for (source, operator) in [("!1", "Bang"), ("-1", "Minus"), ("+1", "Plus")] {
+            let result = expr!(source);
+
+            assert_eq!(
+                format!("{result:?}"),
+                format!("Unary {{ right: Literal(Number(1.0)), operator: {operator} }}")
+            );
+        }
+    }
+
+    #[test]
+    fn minus_is_unary_or_binary_by_position() {
+        let result = expr!("1 - -2");
+
+        assert_eq!(format!("{result:?}"), "Binary { left: Literal(Number(1.0)), right: Unary { right: Literal(Number(2.0)), operator: Minus }, operator: Minus }");
+    }
+
+    #[test]
+    #[should_panic(expected = "Expected a unary operator token not Star")]
+    fn star_is_not_a_unary_operator() {
+        let _ = UnaryOperator::from(&Token::Star);
+    }
+
+    #[test]
+    fn directives_attach_to_next_declaration() {
+        let tokens = tokens!("@test @slow fun f() {} print 1;")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Annotated { directives: [\"test\", \"slow\"], declaration: Function(Function { name: \"f\", parameters: [], defaults: [], rest: None, body: Block([]) }) }, Statement(Print([Literal(Number(1.0))]))])"
+        );
+    }
+
+    #[test]
+    fn method_call() {
+        let result = expr!("[1, 2].len()");
+
+        assert_eq!(format!("{result:?}"), "Call { callee: Get { object: List([Literal(Number(1.0)), Literal(Number(2.0))]), name: \"len\", optional: false }, arguments: [], named: [] }");
+    }
+
+    #[test]
+    fn map_literal() {
+        let result = expr!("{a: 1, \"b c\": 2,}");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Map([(\"a\", Literal(Number(1.0))), (\"b c\", Literal(Number(2.0)))])"
+        );
+    }
+
+    #[test]
+    fn block_expression() {
+        let result = expr!("{ var t = 1; t + 1 }");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Block { body: Block([Variable { identifier: \"t\", value: Literal(Number(1.0)) }]), value: Some(Binary { left: Variable(\"t\"), right: Literal(Number(1.0)), operator: Plus }) }"
+        );
+    }
+
+    #[test]
+    fn block_expression_ending_in_semicolon_has_no_value() {
+        let result = expr!("{ f(); }");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Block { body: Block([Statement(Expression(Call { callee: Variable(\"f\"), arguments: [], named: [] }))]), value: None }"
+        );
+    }
+
+    #[test]
+    fn if_expression() {
+        let result = expr!("if c { 1 } else if d {} else { 2 }");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "If { condition: Variable(\"c\"), then_branch: Block { body: Block([]), value: Some(Literal(Number(1.0))) }, else_branch: Some(If { condition: Variable(\"d\"), then_branch: Block { body: Block([]), value: None }, else_branch: Some(Block { body: Block([]), value: Some(Literal(Number(2.0))) }) }) }"
+        );
+    }
+
+    #[test]
+    fn empty_braces_are_a_map() {
+        let result = expr!("{}");
+
+        assert_eq!(format!("{result:?}"), "Map([])");
+    }
+
+    #[test]
+    fn labeled_loop() {
+        let tokens = tokens!("outer: while true { break outer; continue; }")
+            .expect("Scanner should not fail to parse source");
+        let mut parser = Parser::new(&tokens);
+
+        let result = parser
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Statement(While { condition: Literal(Boolean(true)), body: Block(Block([Statement(Break(Some(\"outer\"))), Statement(Continue(None))])), increment: None, label: Some(\"outer\") })])"
+        );
+    }
+
+    #[test]
+    fn trivia_is_skipped() {
+        let source = "print /* one */ 1; // done\n";
+        let mut scanner = Scanner::from_source(source).with_preserve_trivia(true);
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        let result = Parser::new(tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Program([Statement(Print([Literal(Number(1.0))]))])"
+        );
+    }
+
+    #[test]
+    fn program_declarations() {
+        let tokens =
+            tokens!("print 1; print 2; print 3;").expect("Scanner should not fail to parse source");
+        let mut declarations = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens")
+            .get_declarations()
+            .clone();
+        let mut program = Program::new();
+
+        assert!(program.is_empty());
+        assert!(program.get(0).is_none());
+
+        program.add_declaration(declarations.remove(0));
+        program.add_declarations(&mut declarations);
+
+        assert!(declarations.is_empty());
+        assert_eq!(program.len(), 3);
+        assert_eq!(
+            format!("{:?}", program.get(2)),
+            "Some(Statement(Print([Literal(Number(3.0))])))"
+        );
+        assert!(program.get(3).is_none());
+    }
+
+    #[test]
+    fn lambda() {
+        let result = expr!("fun(a, b) { return a; }");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Lambda(Function { name: \"lambda\", parameters: [\"a\", \"b\"], defaults: [], rest: None, body: Block([Statement(Return(Some(Variable(\"a\"))))]) })"
+        );
+    }
+
+    #[test]
+    fn lambda_default_parameters() {
+        let result = expr!("fun(a, b = a + 1) { return b; }");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Lambda(Function { name: \"lambda\", parameters: [\"a\", \"b\"], defaults: [Binary { left: Variable(\"a\"), right: Literal(Number(1.0)), operator: Plus }], rest: None, body: Block([Statement(Return(Some(Variable(\"b\"))))]) })"
+        );
+    }
+
+    #[test]
+    fn parameter_without_default_after_default_is_a_syntax_error() {
+        assert_eq!(
+            Parser::parse_expr_from_tokens(&tokens!("fun(a, b = 1, c) {}").unwrap()),
+            Err("Parameter c needs a default as it follows one with a default".to_string())
+        );
+    }
+
+    #[test]
+    fn lambda_rest_parameter() {
+        let result = expr!("fun(a, ...rest) { return rest; }");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Lambda(Function { name: \"lambda\", parameters: [\"a\"], defaults: [], rest: Some(\"rest\"), body: Block([Statement(Return(Some(Variable(\"rest\"))))]) })"
+        );
+    }
+
+    #[test]
+    fn parameter_after_rest_parameter_is_a_syntax_error() {
+        assert_eq!(
+            Parser::parse_expr_from_tokens(&tokens!("fun(...rest, a) {}").unwrap()),
+            Err("Rest parameter rest must be the last parameter".to_string())
+        );
+    }
+
+    #[test]
+    fn interpolated_string() {
+        let result = expr!("\"a${b}\"");
+
+        assert_eq!(
+            format!("{result:?}"),
+            "Interpolation([Literal(String(\"a\")), Variable(\"b\")])"
+        );
+    }
+
+    #[test]
+    fn missing_expression_is_a_syntax_error() {
+        for (source, error) in [
+            (
+                "print",
+                "Expected an expression but reached the end of the input",
+            ),
+            ("print ;", "Expected an expression not SemiColon"),
+        ] {
+            let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+
+            assert_eq!(Parser::new(&tokens).parse(), Err(error.to_string()));
+        }
+    }
+
+    #[test]
+    fn empty_interpolation_is_a_syntax_error() {
+        assert_eq!(
+            Parser::parse_expr_from_tokens(&tokens!("\"${}\"").unwrap()),
+            Err("Expected an expression inside ${}".to_string())
+        );
+    }
 }
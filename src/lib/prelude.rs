@@ -0,0 +1,82 @@
+use super::{
+    error::RuntimeResult, interpreter::Interpreter, optimizer::optimize, parser::Parser,
+    scanner::Scanner,
+};
+
+/// Helpers written in the language itself, such as `each`, `any` and `all`.
+/// Anything which needs to be fast, such as `map`, `filter` and `reduce`, is a
+/// native instead.
+const PRELUDE: &str = include_str!("prelude.script");
+
+/// Defines the prelude's helpers in the interpreter's global scope.
+pub fn load_prelude(interpreter: &mut Interpreter) -> RuntimeResult<()> {
+    let mut scanner = Scanner::from_source(PRELUDE);
+    let tokens = scanner
+        .scan_tokens()
+        .expect("Prelude should not fail to scan");
+    let program = Parser::new(tokens)
+        .parse()
+        .expect("Prelude should not fail to parse");
+    let program = optimize(program);
+    interpreter.run(&program)
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::environment::Environment;
+    use crate::lib::testing::SharedOutput;
+    use crate::tokens;
+
+    use super::*;
+
+    fn run_with_prelude(source: &str) -> String {
+        let output = SharedOutput::default();
+        let mut interpreter =
+            Interpreter::new(Environment::new()).with_output(Box::new(output.clone()));
+        load_prelude(&mut interpreter).expect("Prelude should run without errors");
+        let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+        let program = Parser::new(&tokens)
+            .parse()
+            .expect("Parser should not fail to parse tokens");
+
+        interpreter
+            .run(&program)
+            .expect("Program should run without errors");
+
+        output.contents()
+    }
+
+    #[test]
+    fn each_calls_function_with_each_element() {
+        let output = run_with_prelude("fun show(x) { print x; } each([1, 2], show);");
+
+        assert_eq!(output, "1\n2\n");
+    }
+
+    #[test]
+    fn any_and_all_test_elements() {
+        let output = run_with_prelude(
+            "fun small(x) { return x < 3; }
+            print any([5, 2], small), any([5, 6], small), any([], small);
+            print all([1, 2], small), all([1, 5], small), all([], small);",
+        );
+
+        assert_eq!(output, "true false false\ntrue false true\n");
+    }
+
+    #[test]
+    fn prelude_does_not_clobber_globals() {
+        let output = run_with_prelude(
+            "result = \"mine\"; i = 42; fun id(x) { return x; } ys = any([1], id); print result; print i;",
+        );
+
+        assert_eq!(output, "mine\n42\n");
+    }
+
+    #[test]
+    fn lambdas_as_callbacks() {
+        let output = run_with_prelude("print map([1, 2, 3], fun(x) { return x * 2; });");
+
+        assert_eq!(output, "[2, 4, 6]\n");
+    }
+}
@@ -1,35 +1,60 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Debug, Formatter},
+    rc::Rc,
+    sync::OnceLock,
+};
+
 use super::{
-    error::CompilerResult,
+    error::{CompilerError, CompilerResult},
     utils::{is_alpha, is_alpha_numeric, is_digit},
 };
 
-const KW_AND: &str = "and";
-const KW_CLASS: &str = "class";
-const KW_ELSE: &str = "else";
-const KW_FALSE: &str = "false";
-const KW_FOR: &str = "for";
-const KW_FUN: &str = "fun";
-const KW_IF: &str = "if";
-const KW_NIL: &str = "nil";
-const KW_OR: &str = "or";
-const KW_PRINT: &str = "print";
-const KW_RETURN: &str = "return";
-const KW_SUPER: &str = "super";
-const KW_THIS: &str = "this";
-const KW_TRUE: &str = "true";
-const KW_VAR: &str = "var";
-const KW_WHILE: &str = "while";
-
-#[derive(Debug)]
+/// Every keyword and how it's spelled, so that adding a keyword only takes a
+/// new entry here.
+const KEYWORDS: &[(&str, Keyword)] = &[
+    ("and", Keyword::And),
+    ("break", Keyword::Break),
+    ("class", Keyword::Class),
+    ("continue", Keyword::Continue),
+    ("delete", Keyword::Delete),
+    ("do", Keyword::Do),
+    ("else", Keyword::Else),
+    ("false", Keyword::False),
+    ("for", Keyword::For),
+    ("fun", Keyword::Function),
+    ("if", Keyword::If),
+    ("nil", Keyword::Nil),
+    ("or", Keyword::Or),
+    ("print", Keyword::Print),
+    ("return", Keyword::Return),
+    ("super", Keyword::Super),
+    ("this", Keyword::This),
+    ("true", Keyword::True),
+    ("var", Keyword::VariableDeclaration),
+    ("while", Keyword::While),
+];
+
+/// The longest number and identifier lexemes accepted, so that pathological
+/// input is rejected before it's copied out of the source.
+const MAX_NUMBER_LENGTH: usize = 128;
+const MAX_IDENTIFIER_LENGTH: usize = 256;
+pub const BYTE_ORDER_MARK: char = '\u{feff}';
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum TokenDirection {
     Left,
     Right,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub enum Keyword {
     And,
+    Break,
     Class,
+    Continue,
+    Delete,
+    Do,
     Else,
     False,
     For,
@@ -46,12 +71,28 @@ pub enum Keyword {
     While,
 }
 
-#[derive(Debug)]
+impl Keyword {
+    /// Looks up the keyword spelled `name`, using a table which is built from
+    /// `KEYWORDS` the first time it's needed.
+    pub fn from_name(name: &str) -> Option<Keyword> {
+        static TABLE: OnceLock<HashMap<&str, Keyword>> = OnceLock::new();
+        TABLE
+            .get_or_init(|| KEYWORDS.iter().cloned().collect())
+            .get(name)
+            .cloned()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Token {
     Paren(TokenDirection),
     Brace(TokenDirection),
+    Bracket(TokenDirection),
+    At,
+    Colon,
     Comma,
     Dot,
+    Ellipsis,
     Minus,
     Plus,
     Slash,
@@ -65,39 +106,159 @@ pub enum Token {
     Less,
     Greater,
     GreaterEqual,
+    QuestionQuestion,
+    QuestionDot,
     String(String),
     Number(f32),
+    /// A string containing `${...}` interpolations, e.g. `"x=${x}"`.
+    Interpolation(Vec<StringPart>),
     Identifier(String),
     Keyword(Keyword),
+    /// A run of whitespace, only emitted when trivia is preserved.
+    Whitespace,
+    /// A line or block comment including its delimiters, only emitted when
+    /// trivia is preserved.
+    Comment(String),
+}
+
+/// A piece of an interpolated string: either text, with its escapes decoded,
+/// or the tokens of an embedded expression.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StringPart {
+    Text(String),
+    Expression(Tokens),
+}
+
+impl Token {
+    /// Whether the token only matters for layout, i.e. whitespace or a comment.
+    pub fn is_trivia(&self) -> bool {
+        matches!(self, Token::Whitespace | Token::Comment(_))
+    }
+}
+
+/// Where a token was found in its source, as char offsets from the start of
+/// the source. `start` is inclusive and `end` is exclusive.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Span {
+    pub start: usize,
+    pub end: usize,
 }
 
-#[derive(Debug)]
-pub struct Tokens(Vec<Token>);
+/// Part of the source, e.g. where a declaration was written, for quoting it
+/// in diagnostics exactly as it was written.
+#[derive(Clone)]
+pub struct Excerpt {
+    source: Rc<[char]>,
+    span: Span,
+}
+
+impl Excerpt {
+    /// The text of the excerpt. Only the first line of an excerpt spanning
+    /// several lines is kept, followed by `...`, so that quotes stay short.
+    pub fn text(&self) -> String {
+        let text: String = self.source[self.span.start..self.span.end].iter().collect();
+        match text.split_once('\n') {
+            Some((first, _)) => format!("{} ...", first.trim_end()),
+            None => text,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct Tokens {
+    tokens: Vec<Token>,
+    spans: Vec<Span>,
+    /// The source the spans point into.
+    source: Rc<[char]>,
+}
 
 impl Tokens {
-    pub fn new() -> Self {
-        Tokens(vec![])
+    pub fn new(source: Rc<[char]>) -> Self {
+        Tokens {
+            tokens: vec![],
+            spans: vec![],
+            source,
+        }
     }
 
-    pub fn push(&mut self, token: Token) {
-        self.0.push(token);
+    pub fn push(&mut self, token: Token, span: Span) {
+        self.tokens.push(token);
+        self.spans.push(span);
     }
 
     pub fn len(&self) -> usize {
-        self.0.len()
+        self.tokens.len()
     }
 
     pub fn get(&self, index: usize) -> Option<&Token> {
-        self.0.get(index)
+        self.tokens.get(index)
+    }
+
+    pub fn span(&self, index: usize) -> Option<Span> {
+        self.spans.get(index).copied()
+    }
+
+    /// The source from the start of the token at `start` to the end of the
+    /// token before `end`.
+    pub fn excerpt(&self, start: usize, end: usize) -> Excerpt {
+        Excerpt {
+            source: Rc::clone(&self.source),
+            span: Span {
+                start: self.spans[start].start,
+                end: self.spans[end - 1].end,
+            },
+        }
+    }
+
+    fn last(&self) -> Option<&Token> {
+        self.tokens.last()
+    }
+
+    fn pop(&mut self) {
+        self.tokens.pop();
+        self.spans.pop();
+    }
+
+    pub fn has_trivia(&self) -> bool {
+        self.tokens.iter().any(Token::is_trivia)
+    }
+
+    /// Copies the tokens, leaving out whitespace and comments.
+    pub fn without_trivia(&self) -> Self {
+        let mut tokens = Tokens::new(Rc::clone(&self.source));
+        for (token, span) in self.tokens.iter().zip(&self.spans) {
+            if !token.is_trivia() {
+                tokens.push(token.clone(), *span);
+            }
+        }
+        tokens
+    }
+}
+
+// Token streams which only differ in layout are equal, and spans are left out
+// to keep token dumps readable.
+impl PartialEq for Tokens {
+    fn eq(&self, other: &Self) -> bool {
+        self.tokens == other.tokens
+    }
+}
+
+impl Debug for Tokens {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Tokens").field(&self.tokens).finish()
     }
 }
 
 pub struct Scanner {
     tokens: Tokens,
-    source_chars: Vec<char>,
+    source_chars: Rc<[char]>,
     start: usize,
     current: usize,
     line: usize,
+    case_insensitive_keywords: bool,
+    strict_numbers: bool,
+    preserve_trivia: bool,
+    warnings: Vec<String>,
 }
 
 impl Scanner {
@@ -109,15 +270,57 @@ impl Scanner {
     }
 
     pub fn from_source(source: &str) -> Self {
+        Self::from_source_with_line(source, 1)
+    }
+
+    /// Scans `source` as if it began on `start_line`, so that a REPL can keep
+    /// counting lines across inputs. A byte order mark at the very start of
+    /// `source`, which some editors save, is skipped.
+    pub fn from_source_with_line(source: &str, start_line: usize) -> Self {
+        let source = source.strip_prefix(BYTE_ORDER_MARK).unwrap_or(source);
+        let source_chars: Rc<[char]> = source.chars().collect();
         Self {
-            tokens: Tokens::new(),
-            source_chars: source.chars().collect(),
+            tokens: Tokens::new(Rc::clone(&source_chars)),
+            source_chars,
             start: 0,
             current: 0,
-            line: 1,
+            line: start_line,
+            case_insensitive_keywords: false,
+            strict_numbers: false,
+            preserve_trivia: false,
+            warnings: vec![],
         }
     }
 
+    /// Matches keywords regardless of case, so `PRINT` scans the same as
+    /// `print`. Identifiers keep their original case either way.
+    #[allow(dead_code)] // No command line flag enables this yet.
+    pub fn with_case_insensitive_keywords(mut self, case_insensitive_keywords: bool) -> Self {
+        self.case_insensitive_keywords = case_insensitive_keywords;
+        self
+    }
+
+    /// Rejects number literals which cannot be represented exactly, such as
+    /// `123456789012345`, instead of only warning about them in `warnings`.
+    pub fn with_strict_numbers(mut self, strict_numbers: bool) -> Self {
+        self.strict_numbers = strict_numbers;
+        self
+    }
+
+    /// Emits `Whitespace` and `Comment` tokens instead of discarding them, so
+    /// that tools such as formatters can reproduce the source exactly. The
+    /// parser skips them.
+    pub fn with_preserve_trivia(mut self, preserve_trivia: bool) -> Self {
+        self.preserve_trivia = preserve_trivia;
+        self
+    }
+
+    /// Warnings about the source scanned so far, e.g. number literals which
+    /// lose precision, for the caller to report.
+    pub fn warnings(&self) -> &[String] {
+        &self.warnings
+    }
+
     pub fn scan_tokens(&mut self) -> CompilerResult<&Tokens> {
         while !self.is_at_end() {
             self.scan_token()?;
@@ -126,6 +329,23 @@ impl Scanner {
         Ok(&self.tokens)
     }
 
+    /// Reports every lexical error rather than stopping at the first, e.g.
+    /// for editor diagnostics.
+    #[allow(dead_code)] // The CLI reports errors one at a time.
+    pub fn scan_tokens_collecting(&mut self) -> (Tokens, Vec<CompilerError>) {
+        let mut errors = vec![];
+
+        while !self.is_at_end() {
+            if let Err(error) = self.scan_token() {
+                errors.push(error);
+                self.skip_unexpected();
+            }
+        }
+
+        let tokens = Tokens::new(Rc::clone(&self.source_chars));
+        (std::mem::replace(&mut self.tokens, tokens), errors)
+    }
+
     fn scan_token(&mut self) -> CompilerResult<()> {
         self.start = self.current;
         let char = self.advance();
@@ -133,24 +353,47 @@ impl Scanner {
         let token = {
             if let Some(char) = char {
                 match char {
-                    ' ' | '\r' | '\t' => None,
-                    '\n' => {
-                        self.line += 1;
-                        None
+                    ' ' | '\r' | '\t' | '\n' => {
+                        if char == '\n' {
+                            self.line += 1;
+                        }
+                        if self.preserve_trivia {
+                            while let Some(next @ (' ' | '\r' | '\t' | '\n')) = self.peek() {
+                                if next == '\n' {
+                                    self.line += 1;
+                                }
+                                self.current += 1;
+                            }
+                            Some(Token::Whitespace)
+                        } else {
+                            None
+                        }
                     }
                     '(' => Some(Token::Paren(TokenDirection::Left)),
                     ')' => Some(Token::Paren(TokenDirection::Right)),
                     '{' => Some(Token::Brace(TokenDirection::Left)),
                     '}' => Some(Token::Brace(TokenDirection::Right)),
+                    '[' => Some(Token::Bracket(TokenDirection::Left)),
+                    ']' => Some(Token::Bracket(TokenDirection::Right)),
+                    '@' => Some(Token::At),
+                    ':' => Some(Token::Colon),
                     ',' => Some(Token::Comma),
-                    '.' => Some(Token::Dot),
+                    '.' => {
+                        if self.peek() == Some('.') && self.peek_nth(1) == Some('.') {
+                            self.current += 2;
+                            Some(Token::Ellipsis)
+                        } else {
+                            Some(Token::Dot)
+                        }
+                    }
                     '-' => Some(Token::Minus),
                     '+' => Some(Token::Plus),
                     '*' => Some(Token::Star),
                     ';' => Some(Token::SemiColon),
+                    '?' if self.match_next('?') => Some(Token::QuestionQuestion),
+                    '?' if self.match_next('.') => Some(Token::QuestionDot),
                     '!' => {
                         if self.match_next('=') {
-                            self.current += 1;
                             Some(Token::BangEquals)
                         } else {
                             Some(Token::Bang)
@@ -158,7 +401,6 @@ impl Scanner {
                     }
                     '=' => {
                         if self.match_next('=') {
-                            self.current += 1;
                             Some(Token::EqualsEquals)
                         } else {
                             Some(Token::Equals)
@@ -166,7 +408,6 @@ impl Scanner {
                     }
                     '<' => {
                         if self.match_next('=') {
-                            self.current += 1;
                             Some(Token::LessEqual)
                         } else {
                             Some(Token::Less)
@@ -174,7 +415,6 @@ impl Scanner {
                     }
                     '>' => {
                         if self.match_next('=') {
-                            self.current += 1;
                             Some(Token::GreaterEqual)
                         } else {
                             Some(Token::Greater)
@@ -182,89 +422,39 @@ impl Scanner {
                     }
                     '/' => {
                         if self.match_next('/') {
-                            loop {
-                                match self.advance() {
-                                    Some('\n') => break,
-                                    Some(_) => {}
-                                    None => break,
-                                }
-                            }
-                            None
+                            // The newline is left to be scanned so it's counted.
+                            self.skip_while(|char| char != '\n');
+                            self.comment()
+                        } else if self.match_next('*') {
+                            self.block_comment()?;
+                            self.comment()
                         } else {
                             Some(Token::Slash)
                         }
                     }
-                    '"' => {
-                        loop {
-                            match self.advance() {
-                                Some('"') => break,
-                                Some('\n') => {
-                                    self.line += 1;
-                                }
-                                Some(_) => {}
-                                None => return Err("Unterminated string".to_string()),
-                            }
-                        }
-                        Some(Token::String(
-                            self.source_chars[self.start..self.current]
-                                .into_iter()
-                                .collect(),
-                        ))
-                    }
+                    '"' => Some(self.string()?),
                     char => {
                         if is_digit(char) {
-                            loop {
-                                match self.advance() {
-                                    Some('.') => {}
-                                    Some(char) => {
-                                        if !is_digit(char) {
-                                            break;
-                                        }
-                                    }
-                                    None => break,
-                                }
-                            }
-                            self.current -= 1;
-                            Some(Token::Number(
-                                self.source_chars[self.start..self.current]
-                                    .into_iter()
-                                    .collect::<String>()
-                                    .parse()
-                                    .expect("Failed to parse float"),
-                            ))
+                            self.scan_number();
+                            Some(Token::Number(self.number()?))
                         } else if is_alpha(char) {
-                            loop {
-                                match self.advance() {
-                                    Some(char) => {
-                                        if !is_alpha_numeric(char) {
-                                            break;
-                                        }
-                                    }
-                                    None => break,
-                                }
+                            self.skip_while(is_alpha_numeric);
+                            if self.current - self.start > MAX_IDENTIFIER_LENGTH {
+                                return Err(format!(
+                                    "Identifier on line {} is longer than {MAX_IDENTIFIER_LENGTH} characters",
+                                    self.line
+                                ));
                             }
-                            self.current -= 1;
-                            let alpha_numeric: String = self.source_chars[self.start..self.current]
-                                .into_iter()
-                                .collect();
-                            Some(match &alpha_numeric[..] {
-                                KW_AND => Token::Keyword(Keyword::And),
-                                KW_CLASS => Token::Keyword(Keyword::Class),
-                                KW_ELSE => Token::Keyword(Keyword::Else),
-                                KW_FALSE => Token::Keyword(Keyword::False),
-                                KW_FOR => Token::Keyword(Keyword::For),
-                                KW_FUN => Token::Keyword(Keyword::Function),
-                                KW_IF => Token::Keyword(Keyword::If),
-                                KW_NIL => Token::Keyword(Keyword::Nil),
-                                KW_OR => Token::Keyword(Keyword::Or),
-                                KW_PRINT => Token::Keyword(Keyword::Print),
-                                KW_RETURN => Token::Keyword(Keyword::Return),
-                                KW_SUPER => Token::Keyword(Keyword::Super),
-                                KW_THIS => Token::Keyword(Keyword::This),
-                                KW_TRUE => Token::Keyword(Keyword::True),
-                                KW_VAR => Token::Keyword(Keyword::VariableDeclaration),
-                                KW_WHILE => Token::Keyword(Keyword::While),
-                                _ => Token::Identifier(alpha_numeric),
+                            let alpha_numeric: String =
+                                self.source_chars[self.start..self.current].iter().collect();
+                            let keyword = if self.case_insensitive_keywords {
+                                alpha_numeric.to_lowercase()
+                            } else {
+                                alpha_numeric.clone()
+                            };
+                            Some(match Keyword::from_name(&keyword) {
+                                Some(keyword) => Token::Keyword(keyword),
+                                None => Token::Identifier(alpha_numeric),
                             })
                         } else {
                             return Err(format!(
@@ -286,8 +476,224 @@ impl Scanner {
         Ok(())
     }
 
+    /// The comment just skipped, if trivia is being preserved.
+    fn comment(&self) -> Option<Token> {
+        self.preserve_trivia
+            .then(|| Token::Comment(self.source_chars[self.start..self.current].iter().collect()))
+    }
+
+    /// Skips the rest of a `/* ... */` comment, counting the lines it spans.
+    /// Block comments don't nest.
+    fn block_comment(&mut self) -> CompilerResult<()> {
+        let start_line = self.line;
+        loop {
+            match self.advance() {
+                Some('*') if self.match_next('/') => return Ok(()),
+                Some('\n') => self.line += 1,
+                Some(_) => {}
+                None => {
+                    return Err(format!(
+                        "Unterminated block comment starting on line {start_line}"
+                    ))
+                }
+            }
+        }
+    }
+
+    /// Scans the rest of a string literal, decoding any escape sequences. A
+    /// plain string token keeps its surrounding quotes, whereas a string with
+    /// `${...}` in it becomes an interpolation.
+    fn string(&mut self) -> CompilerResult<Token> {
+        let start_line = self.line;
+        let unterminated = || format!("Unterminated string starting on line {start_line}");
+        let mut parts = vec![];
+        let mut string = String::new();
+        loop {
+            match self.advance() {
+                Some('"') => break,
+                Some('$') if self.match_next('{') => {
+                    parts.push(StringPart::Text(std::mem::take(&mut string)));
+                    parts.push(StringPart::Expression(self.interpolation()?));
+                }
+                Some('\\') => match self.escape() {
+                    Some(escaped) => string.push(escaped?),
+                    None => return Err(unterminated()),
+                },
+                Some(char) => {
+                    if char == '\n' {
+                        self.line += 1;
+                    }
+                    string.push(char);
+                }
+                None => return Err(unterminated()),
+            }
+        }
+        if parts.is_empty() {
+            return Ok(Token::String(format!("\"{string}\"")));
+        }
+        parts.push(StringPart::Text(string));
+        parts.retain(|part| *part != StringPart::Text(String::new()));
+        Ok(Token::Interpolation(parts))
+    }
+
+    /// Scans the expression in a `${...}` up to its closing brace, which is
+    /// left out of the tokens. The expression may contain braces and strings
+    /// of its own.
+    fn interpolation(&mut self) -> CompilerResult<Tokens> {
+        let start = self.start;
+        let start_line = self.line;
+        let tokens = Tokens::new(Rc::clone(&self.source_chars));
+        let outer = std::mem::replace(&mut self.tokens, tokens);
+
+        let mut depth = 0;
+        let result = loop {
+            if self.is_at_end() {
+                break Err(format!(
+                    "Unterminated interpolation starting on line {start_line}"
+                ));
+            }
+            let scanned = self.tokens.len();
+            if let Err(error) = self.scan_token() {
+                break Err(error);
+            }
+            if self.tokens.len() == scanned {
+                continue;
+            }
+            match self.tokens.last() {
+                Some(Token::Brace(TokenDirection::Left)) => depth += 1,
+                Some(Token::Brace(TokenDirection::Right)) if depth == 0 => {
+                    self.tokens.pop();
+                    break Ok(());
+                }
+                Some(Token::Brace(TokenDirection::Right)) => depth -= 1,
+                _ => {}
+            }
+        };
+
+        self.start = start;
+        let tokens = std::mem::replace(&mut self.tokens, outer);
+        result.map(|()| tokens)
+    }
+
+    /// Decodes the escape sequence after a `\\`, or returns `None` if the
+    /// source ends first.
+    fn escape(&mut self) -> Option<CompilerResult<char>> {
+        Some(match self.advance()? {
+            'n' => Ok('\n'),
+            'r' => Ok('\r'),
+            't' => Ok('\t'),
+            '0' => Ok('\0'),
+            '\\' => Ok('\\'),
+            '"' => Ok('"'),
+            '$' => Ok('$'),
+            'u' => self.unicode_escape(),
+            char => Err(format!(
+                "Unknown escape sequence \\{char} on line {}",
+                self.line
+            )),
+        })
+    }
+
+    /// Decodes the `{1F600}` part of a `\u{1F600}` escape.
+    fn unicode_escape(&mut self) -> CompilerResult<char> {
+        let malformed = format!("Malformed unicode escape on line {}", self.line);
+        if self.advance() != Some('{') {
+            return Err(malformed);
+        }
+
+        let mut digits = String::new();
+        loop {
+            match self.advance() {
+                Some('}') if !digits.is_empty() => break,
+                Some(char) if char.is_ascii_hexdigit() && digits.len() < 6 => digits.push(char),
+                _ => return Err(malformed),
+            }
+        }
+
+        let code_point = u32::from_str_radix(&digits, 16).expect("Digits should be hexadecimal");
+        char::from_u32(code_point)
+            .ok_or_else(|| format!("Invalid unicode code point {digits} on line {}", self.line))
+    }
+
+    /// Scans the rest of a number whose first digit has been consumed.
+    fn scan_number(&mut self) {
+        self.skip_while(is_digit);
+
+        // A number has at most one decimal point, so any further `.` ends the
+        // number: `1.2.3` scans as `1.2`, `.`, `3`.
+        if self.match_next('.') {
+            self.skip_while(is_digit);
+        }
+
+        // An exponent is only part of the number if digits follow it, so `2e`
+        // scans as `2` followed by the identifier `e`.
+        let exponent_digit = match self.peek_nth(1) {
+            Some('+' | '-') => 2,
+            _ => 1,
+        };
+        if matches!(self.peek(), Some('e' | 'E'))
+            && self.peek_nth(exponent_digit).is_some_and(is_digit)
+        {
+            self.current += exponent_digit;
+            self.skip_while(is_digit);
+        }
+    }
+
+    fn skip_while(&mut self, predicate: fn(char) -> bool) {
+        while self.peek().is_some_and(predicate) {
+            self.current += 1;
+        }
+    }
+
+    fn number(&mut self) -> CompilerResult<f32> {
+        if self.current - self.start > MAX_NUMBER_LENGTH {
+            return Err(format!(
+                "Number on line {} is longer than {MAX_NUMBER_LENGTH} characters",
+                self.line
+            ));
+        }
+
+        let lexeme: String = self.source_chars[self.start..self.current].iter().collect();
+        let value: f32 = lexeme.parse().expect("Failed to parse float");
+        if value.is_infinite() {
+            return Err(format!(
+                "Number {lexeme} on line {} is too large",
+                self.line
+            ));
+        }
+
+        if loses_precision(&lexeme, value) {
+            let message = format!(
+                "Number {lexeme} on line {} cannot be represented exactly and becomes {value}",
+                self.line
+            );
+            if self.strict_numbers {
+                return Err(message);
+            }
+            self.warnings.push(message);
+        }
+
+        Ok(value)
+    }
+
     fn add_token(&mut self, token: Token) {
-        self.tokens.push(token);
+        let span = Span {
+            start: self.start,
+            end: self.current,
+        };
+        self.tokens.push(token, span);
+    }
+
+    /// Skips the rest of a run of unexpected characters, up to the next
+    /// whitespace or character which could start a token, so that a run of
+    /// garbage is only reported once.
+    fn skip_unexpected(&mut self) {
+        while let Some(char) = self.peek() {
+            if char.is_whitespace() || starts_token(char) {
+                break;
+            }
+            self.current += 1;
+        }
     }
 
     fn is_at_end(&self) -> bool {
@@ -301,21 +707,37 @@ impl Scanner {
     }
 
     fn peek(&self) -> Option<char> {
-        if self.is_at_end() {
-            None
-        } else {
-            Some(self.source_chars[self.current])
-        }
+        self.peek_nth(0)
     }
 
-    fn match_next(&self, maybe_next: char) -> bool {
-        match self.peek() {
-            Some(next) => next == maybe_next,
-            None => false,
+    /// Looks `n` chars past the next one without consuming anything.
+    fn peek_nth(&self, n: usize) -> Option<char> {
+        self.source_chars.get(self.current + n).copied()
+    }
+
+    /// Consumes the next char if it is `expected`.
+    fn match_next(&mut self, expected: char) -> bool {
+        let matches = self.peek() == Some(expected);
+        if matches {
+            self.current += 1;
         }
+        matches
     }
 }
 
+fn starts_token(char: char) -> bool {
+    is_alpha_numeric(char) || "(){}[]@:,.-+*;!=<>/?\"".contains(char)
+}
+
+/// Whether `value` differs from the number written as `lexeme`. Display prints
+/// the shortest decimal which parses back to `value`, so any difference from
+/// the lexeme means digits were lost.
+fn loses_precision(lexeme: &str, value: f32) -> bool {
+    let written: f64 = lexeme.parse().expect("Failed to parse float");
+    let stored: f64 = value.to_string().parse().expect("Failed to parse float");
+    written != stored
+}
+
 #[cfg(test)]
 #[macro_export]
 macro_rules! tokens {
@@ -339,6 +761,20 @@ mod tests {
         assert_eq!(format!("{tokens:?}"), "Tokens([Paren(Left), Paren(Right), Brace(Left), Brace(Right), Comma, Dot, Minus, Plus, Star, SemiColon])");
     }
 
+    #[test]
+    fn brackets() {
+        let mut scanner = Scanner::from_source("[]");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Bracket(Left), Bracket(Right)])"
+        );
+    }
+
     #[test]
     fn maybe_single_chars() {
         let mut scanner = Scanner::from_source("> < = !");
@@ -367,6 +803,45 @@ mod tests {
         );
     }
 
+    #[test]
+    fn question_question() {
+        let mut scanner = Scanner::from_source("a??b");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Identifier(\"a\"), QuestionQuestion, Identifier(\"b\")])"
+        );
+    }
+
+    #[test]
+    fn question_dot() {
+        let mut scanner = Scanner::from_source("a?.b ??.");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Identifier(\"a\"), QuestionDot, Identifier(\"b\"), QuestionQuestion, Dot])"
+        );
+    }
+
+    #[test]
+    fn ellipsis() {
+        let mut scanner = Scanner::from_source("... .. .");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(format!("{tokens:?}"), "Tokens([Ellipsis, Dot, Dot, Dot])");
+    }
+
     #[test]
     fn comments() {
         let mut scanner = Scanner::from_source("!\n// Hello World!\n!");
@@ -378,6 +853,40 @@ mod tests {
         assert_eq!(format!("{tokens:?}"), "Tokens([Bang, Bang])");
     }
 
+    #[test]
+    fn preserved_trivia() {
+        let mut scanner = Scanner::from_source("a // c\nb /* d */").with_preserve_trivia(true);
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Identifier(\"a\"), Whitespace, Comment(\"// c\"), Whitespace, Identifier(\"b\"), Whitespace, Comment(\"/* d */\")])"
+        );
+        assert_eq!(tokens.span(3), Some(Span { start: 6, end: 7 }));
+    }
+
+    #[test]
+    fn leading_byte_order_mark_is_skipped() {
+        let source = "print \"a\";\nvar b = 1;";
+        let with_mark = format!("{BYTE_ORDER_MARK}{source}");
+
+        let tokens = Scanner::parse_tokens_from_source(&with_mark)
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            tokens,
+            Scanner::parse_tokens_from_source(source)
+                .expect("Scanner should not fail to parse source")
+        );
+        assert_eq!(
+            Scanner::parse_tokens_from_source(&format!("print 1;{BYTE_ORDER_MARK}")),
+            Err("Unexpected character (\u{feff}) on line 1".to_string())
+        );
+    }
+
     #[test]
     fn unexpected_char() {
         let mut scanner = Scanner::from_source("\n#");
@@ -389,6 +898,25 @@ mod tests {
         assert_eq!(error, "Unexpected character (#) on line 2");
     }
 
+    #[test]
+    fn unexpected_char_from_start_line() {
+        let mut scanner = Scanner::from_source_with_line("#", 5);
+
+        let error = scanner
+            .scan_tokens()
+            .expect_err("Scanner should return an error when it encounters an unexpected error");
+
+        assert_eq!(error, "Unexpected character (#) on line 5");
+
+        let mut scanner = Scanner::from_source_with_line("1;\n#", 5);
+
+        let error = scanner
+            .scan_tokens()
+            .expect_err("Scanner should return an error when it encounters an unexpected error");
+
+        assert_eq!(error, "Unexpected character (#) on line 6");
+    }
+
     #[test]
     fn unexpected_char_after_number_new_line() {
         let mut scanner = Scanner::from_source("123\n#");
@@ -400,6 +928,44 @@ mod tests {
         assert_eq!(error, "Unexpected character (#) on line 2");
     }
 
+    #[test]
+    fn collect_unexpected_chars() {
+        let mut scanner = Scanner::from_source("# 1 +\n$ 2");
+
+        let (tokens, errors) = scanner.scan_tokens_collecting();
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Number(1.0), Plus, Number(2.0)])"
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Unexpected character (#) on line 1",
+                "Unexpected character ($) on line 2"
+            ]
+        );
+    }
+
+    #[test]
+    fn collecting_skips_runs_of_unexpected_chars() {
+        let mut scanner = Scanner::from_source("$$$ 1 + 2 #~#+3");
+
+        let (tokens, errors) = scanner.scan_tokens_collecting();
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Number(1.0), Plus, Number(2.0), Plus, Number(3.0)])"
+        );
+        assert_eq!(
+            errors,
+            vec![
+                "Unexpected character ($) on line 1",
+                "Unexpected character (#) on line 1"
+            ]
+        );
+    }
+
     #[test]
     fn ignore_white_space() {
         let mut scanner = Scanner::from_source("\n\t!\r");
@@ -418,7 +984,7 @@ mod tests {
         let tokens = scanner
             .scan_tokens()
             .expect("Scanner should not fail to parse source");
-        let token = tokens.0.get(0).expect("Expected a token");
+        let token = tokens.tokens.first().expect("Expected a token");
 
         assert_eq!(
             format!("{tokens:?}"),
@@ -433,6 +999,47 @@ mod tests {
         }
     }
 
+    #[test]
+    fn interpolated_string() {
+        let mut scanner = Scanner::from_source("\"x=${1+1}!\" \"\\${}\"");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Interpolation([Text(\"x=\"), Expression(Tokens([Number(1.0), Plus, Number(1.0)])), Text(\"!\")]), String(\"\\\"${}\\\"\")])"
+        );
+        assert_eq!(tokens.span(0), Some(Span { start: 0, end: 11 }));
+    }
+
+    #[test]
+    fn interpolation_may_contain_strings_and_braces() {
+        let mut scanner = Scanner::from_source("\"${ {a: \"}\"} }\"");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Interpolation([Expression(Tokens([Brace(Left), Identifier(\"a\"), Colon, String(\"\\\"}\\\"\"), Brace(Right)]))])])"
+        );
+    }
+
+    #[test]
+    fn unterminated_interpolation() {
+        let mut scanner = Scanner::from_source("\"${1 + 2\n");
+
+        let result = scanner.scan_tokens();
+
+        assert_eq!(
+            result,
+            Err("Unterminated interpolation starting on line 1".to_string())
+        );
+    }
+
     #[test]
     fn multi_line_string() {
         let mut scanner = Scanner::from_source("\"Hello\nWorld!\"");
@@ -440,7 +1047,7 @@ mod tests {
         let tokens = scanner
             .scan_tokens()
             .expect("Scanner should not fail to parse source");
-        let token = tokens.0.get(0).expect("Expected a token");
+        let token = tokens.tokens.first().expect("Expected a token");
 
         assert_eq!(
             format!("{tokens:?}"),
@@ -462,7 +1069,7 @@ mod tests {
         let tokens = scanner
             .scan_tokens()
             .expect("Scanner should not fail to parse source");
-        let token = tokens.0.get(0).expect("Expected a token");
+        let token = tokens.tokens.first().expect("Expected a token");
 
         assert_eq!(format!("{tokens:?}"), "Tokens([Number(123.0)])");
 
@@ -481,7 +1088,7 @@ mod tests {
         let tokens = scanner
             .scan_tokens()
             .expect("Scanner should not fail to parse source");
-        let token = tokens.0.get(0).expect("Expected a token");
+        let token = tokens.tokens.first().expect("Expected a token");
 
         assert_eq!(format!("{tokens:?}"), "Tokens([Number(123.456)])");
 
@@ -507,6 +1114,20 @@ mod tests {
         );
     }
 
+    #[test]
+    fn number_minus_number() {
+        let mut scanner = Scanner::from_source("5-3 -99");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Number(5.0), Minus, Number(3.0), Minus, Number(99.0)])"
+        );
+    }
+
     #[test]
     fn number_trailing_dot() {
         let mut scanner = Scanner::from_source("123.");
@@ -518,6 +1139,31 @@ mod tests {
         assert_eq!(format!("{tokens:?}"), "Tokens([Number(123.0)])");
     }
 
+    #[test]
+    fn number_single_decimal_point() {
+        let mut scanner = Scanner::from_source("1.2");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(format!("{tokens:?}"), "Tokens([Number(1.2)])");
+    }
+
+    #[test]
+    fn number_second_decimal_point_ends_number() {
+        let mut scanner = Scanner::from_source("1.2.3");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Number(1.2), Dot, Number(3.0)])"
+        );
+    }
+
     #[test]
     fn identifier() {
         let mut scanner = Scanner::from_source("Hello World!");
@@ -525,8 +1171,8 @@ mod tests {
         let tokens = scanner
             .scan_tokens()
             .expect("Scanner should not fail to parse source");
-        let token_0 = tokens.0.get(0).expect("Expected a token");
-        let token_1 = tokens.0.get(1).expect("Expected a token");
+        let token_0 = tokens.tokens.first().expect("Expected a token");
+        let token_1 = tokens.tokens.get(1).expect("Expected a token");
 
         assert_eq!(
             format!("{tokens:?}"),
@@ -548,6 +1194,27 @@ mod tests {
         }
     }
 
+    #[test]
+    fn token_streams_compare_equal() {
+        let tokens = Scanner::parse_tokens_from_source("x = 1.5 + y;")
+            .expect("Scanner should not fail to parse source");
+        let spaced_tokens = Scanner::parse_tokens_from_source("x=1.5\n+\ty ;")
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(tokens, spaced_tokens);
+        assert_eq!(tokens.get(2), Some(&Token::Number(1.5)),);
+    }
+
+    #[test]
+    fn token_streams_compare_unequal() {
+        let tokens = Scanner::parse_tokens_from_source("x = 1;")
+            .expect("Scanner should not fail to parse source");
+        let other_tokens = Scanner::parse_tokens_from_source("x = 2;")
+            .expect("Scanner should not fail to parse source");
+
+        assert_ne!(tokens, other_tokens);
+    }
+
     #[test]
     fn keywords() {
         let mut scanner = Scanner::from_source("Hello super World!");
@@ -561,4 +1228,339 @@ mod tests {
             "Tokens([Identifier(\"Hello\"), Keyword(Super), Identifier(\"World\"), Bang])"
         );
     }
+
+    #[test]
+    fn at() {
+        let mut scanner = Scanner::from_source("@test");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(format!("{tokens:?}"), "Tokens([At, Identifier(\"test\")])");
+    }
+
+    #[test]
+    fn every_keyword_scans_as_itself() {
+        // Written out rather than taken from `KEYWORDS`, so that a mistake in
+        // the table doesn't go unnoticed.
+        let keywords = [
+            ("and", Keyword::And),
+            ("break", Keyword::Break),
+            ("class", Keyword::Class),
+            ("continue", Keyword::Continue),
+            ("delete", Keyword::Delete),
+            ("do", Keyword::Do),
+            ("else", Keyword::Else),
+            ("false", Keyword::False),
+            ("for", Keyword::For),
+            ("fun", Keyword::Function),
+            ("if", Keyword::If),
+            ("nil", Keyword::Nil),
+            ("or", Keyword::Or),
+            ("print", Keyword::Print),
+            ("return", Keyword::Return),
+            ("super", Keyword::Super),
+            ("this", Keyword::This),
+            ("true", Keyword::True),
+            ("var", Keyword::VariableDeclaration),
+            ("while", Keyword::While),
+        ];
+        let source: Vec<&str> = keywords.iter().map(|(name, _)| *name).collect();
+        let mut scanner = Scanner::from_source(&source.join(" "));
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(tokens.len(), keywords.len());
+        assert_eq!(KEYWORDS.len(), keywords.len());
+        for (index, (_, keyword)) in keywords.into_iter().enumerate() {
+            assert_eq!(tokens.get(index), Some(&Token::Keyword(keyword)));
+        }
+        assert_eq!(Keyword::from_name("fun"), Some(Keyword::Function));
+        assert_eq!(Keyword::from_name("function"), None);
+    }
+
+    #[test]
+    fn case_insensitive_keywords() {
+        let mut scanner =
+            Scanner::from_source("PRINT While Foo").with_case_insensitive_keywords(true);
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Keyword(Print), Keyword(While), Identifier(\"Foo\")])"
+        );
+    }
+
+    #[test]
+    fn keywords_are_case_sensitive_by_default() {
+        let mut scanner = Scanner::from_source("PRINT print");
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Identifier(\"PRINT\"), Keyword(Print)])"
+        );
+    }
+
+    #[test]
+    fn exact_numbers_do_not_lose_precision() {
+        assert!(!loses_precision("123", 123.0));
+        assert!(!loses_precision("0.1", 0.1));
+        assert!(!loses_precision("1.50", 1.5));
+    }
+
+    #[test]
+    fn strict_numbers_reject_precision_loss() {
+        let mut scanner = Scanner::from_source("123456789012345").with_strict_numbers(true);
+
+        let error = scanner
+            .scan_tokens()
+            .expect_err("Scanner should reject a number which loses precision");
+
+        assert_eq!(
+            error,
+            "Number 123456789012345 on line 1 cannot be represented exactly and becomes 123456790000000"
+        );
+    }
+
+    #[test]
+    fn strict_numbers_accept_exact_numbers() {
+        let mut scanner = Scanner::from_source("16777216 0.25").with_strict_numbers(true);
+
+        let tokens = scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Number(16777216.0), Number(0.25)])"
+        );
+    }
+
+    #[test]
+    fn lenient_numbers_warn_about_precision_loss() {
+        let mut scanner = Scanner::from_source("123456789012345 0.25");
+
+        scanner
+            .scan_tokens()
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            scanner.warnings(),
+            ["Number 123456789012345 on line 1 cannot be represented exactly and becomes 123456790000000"]
+        );
+    }
+
+    #[test]
+    fn lenient_numbers_keep_imprecise_value() {
+        let tokens = tokens!("16777217").expect("Scanner should not fail to parse source");
+
+        assert_eq!(format!("{tokens:?}"), "Tokens([Number(16777216.0)])");
+    }
+
+    #[test]
+    fn tokens_record_spans() {
+        let tokens = tokens!("ab + cd").expect("Scanner should not fail to parse source");
+
+        let spans: Vec<_> = (0..tokens.len())
+            .map(|index| tokens.span(index).unwrap())
+            .collect();
+
+        assert_eq!(
+            spans,
+            [
+                Span { start: 0, end: 2 },
+                Span { start: 3, end: 4 },
+                Span { start: 5, end: 7 }
+            ]
+        );
+    }
+
+    #[test]
+    fn string_span_includes_quotes() {
+        let tokens = tokens!("x = \"hi\";").expect("Scanner should not fail to parse source");
+
+        assert_eq!(tokens.span(2), Some(Span { start: 4, end: 8 }));
+        assert_eq!(tokens.span(3), Some(Span { start: 8, end: 9 }));
+    }
+
+    #[test]
+    fn string_escapes() {
+        let tokens = tokens!(r#""a\n\t\"\\b""#).expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            tokens.get(0),
+            Some(&Token::String("\"a\n\t\"\\b\"".to_string()))
+        );
+    }
+
+    #[test]
+    fn unicode_escapes() {
+        let tokens =
+            tokens!(r#""\u{41}\u{1F600}""#).expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            tokens.get(0),
+            Some(&Token::String("\"A\u{1F600}\"".to_string()))
+        );
+    }
+
+    #[test]
+    fn invalid_unicode_escapes() {
+        for (source, expected) in [
+            (r#""\u{D800}""#, "Invalid unicode code point D800 on line 1"),
+            (
+                r#""\u{110000}""#,
+                "Invalid unicode code point 110000 on line 1",
+            ),
+            (r#""\u41""#, "Malformed unicode escape on line 1"),
+            (r#""\u{}""#, "Malformed unicode escape on line 1"),
+            (r#""\u{41""#, "Malformed unicode escape on line 1"),
+            (r#""\u{1234567}""#, "Malformed unicode escape on line 1"),
+            (r#""\q""#, "Unknown escape sequence \\q on line 1"),
+        ] {
+            let error = tokens!(source).expect_err("Scanner should reject the escape");
+
+            assert_eq!(error, expected, "{source}");
+        }
+    }
+
+    #[test]
+    fn two_char_operators() {
+        let tokens = tokens!("! != = == < <= > >= !== <<=")
+            .expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Bang, BangEquals, Equals, EqualsEquals, Less, LessEqual, Greater, GreaterEqual, BangEquals, Equals, Less, LessEqual])"
+        );
+    }
+
+    #[test]
+    fn numbers() {
+        let tokens =
+            tokens!("0 12 3.5 6. 7.25.upper 8;").expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Number(0.0), Number(12.0), Number(3.5), Number(6.0), Number(7.25), Dot, Identifier(\"upper\"), Number(8.0), SemiColon])"
+        );
+    }
+
+    #[test]
+    fn number_exponents() {
+        let tokens =
+            tokens!("1e3 2.5E-1 4e+2 5e e6 7e-").expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Number(1000.0), Number(0.25), Number(400.0), Number(5.0), Identifier(\"e\"), Identifier(\"e6\"), Number(7.0), Identifier(\"e\"), Minus])"
+        );
+    }
+
+    #[test]
+    fn comment_counts_its_newline() {
+        let error = tokens!("// comment\n#").expect_err("Scanner should reject #");
+
+        assert_eq!(error, "Unexpected character (#) on line 2");
+    }
+
+    #[test]
+    fn comments_at_end_of_source() {
+        for source in [
+            "print 1; // comment",
+            "print 1; /* comment */",
+            "print 1; /**/",
+            "print /* inline */ 1; /* spans\n\nlines */",
+        ] {
+            let tokens = tokens!(source).expect("Scanner should not fail to parse source");
+
+            assert_eq!(
+                format!("{tokens:?}"),
+                "Tokens([Keyword(Print), Number(1.0), SemiColon])",
+                "{source:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn block_comment_counts_its_lines() {
+        let error = tokens!("/* one\ntwo\n*/ #").expect_err("Scanner should reject #");
+
+        assert_eq!(error, "Unexpected character (#) on line 3");
+    }
+
+    #[test]
+    fn block_comments_do_not_nest() {
+        let tokens = tokens!("/* a /* b */ 1 */").expect("Scanner should not fail to parse source");
+
+        assert_eq!(format!("{tokens:?}"), "Tokens([Number(1.0), Star, Slash])");
+    }
+
+    #[test]
+    fn unterminated_block_comment_is_an_error() {
+        for source in ["print 1;\n/* comment", "/* comment *", "/*"] {
+            let line = source.matches('\n').count() + 1;
+
+            let error = tokens!(source).expect_err("Scanner should reject the comment");
+
+            assert_eq!(
+                error,
+                format!("Unterminated block comment starting on line {line}"),
+                "{source:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn string_at_end_of_source() {
+        let tokens = tokens!("print \"a\"").expect("Scanner should not fail to parse source");
+
+        assert_eq!(
+            format!("{tokens:?}"),
+            "Tokens([Keyword(Print), String(\"\\\"a\\\"\")])"
+        );
+        for source in ["\n\"abc", "\n\"abc\\", "\n\"a\nb"] {
+            let error = tokens!(source).expect_err("Scanner should reject the string");
+
+            assert_eq!(
+                error, "Unterminated string starting on line 2",
+                "{source:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn over_long_number_is_an_error() {
+        let source = "1".repeat(1_000_000);
+
+        let error = tokens!(&source).expect_err("Scanner should reject the number");
+
+        assert_eq!(error, "Number on line 1 is longer than 128 characters");
+    }
+
+    #[test]
+    fn over_long_identifier_is_an_error() {
+        let source = format!("print 1;\n{};", "a".repeat(1_000_000));
+
+        let error = tokens!(&source).expect_err("Scanner should reject the identifier");
+
+        assert_eq!(error, "Identifier on line 2 is longer than 256 characters");
+    }
+
+    #[test]
+    fn overflowing_number_is_an_error() {
+        let error = tokens!("1e39").expect_err("Scanner should reject the number");
+
+        assert_eq!(error, "Number 1e39 on line 1 is too large");
+    }
 }
@@ -0,0 +1,56 @@
+//! Helpers shared by the unit tests of several modules.
+
+use std::{
+    cell::RefCell,
+    io::{self, Write},
+    path::PathBuf,
+    rc::Rc,
+};
+
+/// A writer which can be handed to the interpreter while the test keeps a
+/// handle to inspect what was written.
+#[derive(Clone, Default)]
+pub struct SharedOutput(Rc<RefCell<Vec<u8>>>);
+
+impl SharedOutput {
+    pub fn contents(&self) -> String {
+        String::from_utf8(self.0.borrow().clone()).expect("Output should be valid UTF-8")
+    }
+}
+
+impl Write for SharedOutput {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.0.borrow_mut().write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// A directory for a test's files, unique to the test and to this run of the
+/// tests, which is removed when it's dropped.
+pub struct TempDir(PathBuf);
+
+impl TempDir {
+    pub fn new(name: &str) -> Self {
+        let path = std::env::temp_dir().join(format!("interpreter_{name}_{}", std::process::id()));
+        // Left over from an earlier run which didn't finish.
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// The path of `name` inside the directory, which scripts should be
+    /// given as a value rather than have pasted into their source, where
+    /// backslashes would need escaping.
+    pub fn file(&self, name: &str) -> String {
+        self.0.join(name).display().to_string()
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
@@ -1,9 +1,9 @@
 pub fn is_digit(char: char) -> bool {
-    char >= '0' && char <= '9'
+    char.is_ascii_digit()
 }
 
 pub fn is_alpha(char: char) -> bool {
-    (char >= 'a' && char <= 'z') || (char >= 'A' && char <= 'Z') || char == '_'
+    char.is_ascii_alphabetic() || char == '_'
 }
 
 pub fn is_alpha_numeric(char: char) -> bool {
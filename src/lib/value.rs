@@ -0,0 +1,594 @@
+use std::{
+    collections::HashMap,
+    fmt::{self, Display, Formatter},
+    ops::{Add, Div, Mul, Sub},
+};
+
+use super::{
+    error::{RuntimeError, RuntimeResult},
+    function::UserFunction,
+    interpreter::TruthinessMode,
+    list::List,
+    map::Map,
+    natives::NativeFunction,
+    parser::LiteralValue,
+};
+
+/// A value produced by evaluating an expression. Unlike a `LiteralValue`, a
+/// value may be a collection or a function, and never refers to a variable.
+#[derive(PartialEq, PartialOrd, Debug, Clone)]
+pub enum Value {
+    Boolean(bool),
+    String(String),
+    Number(f32),
+    List(List),
+    Tuple(Vec<Value>),
+    Map(Map),
+    NativeFunction(NativeFunction),
+    Function(UserFunction),
+    Nil,
+}
+
+impl From<&LiteralValue> for Value {
+    fn from(literal: &LiteralValue) -> Self {
+        match literal {
+            LiteralValue::Boolean(value) => Value::Boolean(*value),
+            LiteralValue::String(value) => Value::String(value.clone()),
+            LiteralValue::Number(value) => Value::Number(*value),
+            LiteralValue::Nil => Value::Nil,
+        }
+    }
+}
+
+impl Value {
+    /// The name of the value's type, as used in error messages.
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Boolean(_) => "boolean",
+            Value::String(_) => "string",
+            Value::Number(_) => "number",
+            Value::List(_) => "list",
+            Value::Tuple(_) => "tuple",
+            Value::Map(_) => "map",
+            Value::NativeFunction(_) | Value::Function(_) => "function",
+            Value::Nil => "nil",
+        }
+    }
+
+    pub fn is_truthy(&self, truthiness_mode: TruthinessMode) -> bool {
+        let loose = truthiness_mode == TruthinessMode::Loose;
+        match self {
+            Value::Boolean(value) => *value,
+            Value::String(value) => !(loose && value.is_empty()),
+            Value::Number(value) => !(loose && *value == 0.0),
+            Value::List(list) => !(loose && list.is_empty()),
+            Value::Tuple(values) => !(loose && values.is_empty()),
+            Value::Map(map) => !(loose && map.is_empty()),
+            Value::NativeFunction(_) | Value::Function(_) => true,
+            Value::Nil => false,
+        }
+    }
+
+    pub fn is_nil(&self) -> bool {
+        matches!(self, Value::Nil)
+    }
+
+    pub fn as_number(&self) -> Option<f32> {
+        match self {
+            Value::Number(value) => Some(*value),
+            _ => None,
+        }
+    }
+
+    pub fn as_string(&self) -> Option<&str> {
+        match self {
+            Value::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// Copies the value along with any lists inside it, so that the copy
+    /// doesn't share lists with the original the way `clone` does. Each list
+    /// is copied once: a list which appears several times, or inside itself,
+    /// is shared the same way within the copy.
+    pub fn deep_clone(&self) -> Value {
+        self.deep_clone_with(&mut HashMap::new())
+    }
+
+    /// `copies` maps the address of each list copied so far to its copy.
+    fn deep_clone_with(&self, copies: &mut HashMap<usize, List>) -> Value {
+        match self {
+            Value::List(list) => {
+                if let Some(copy) = copies.get(&list.address()) {
+                    return Value::List(copy.clone());
+                }
+                // The copy is recorded before its elements are, so that a list
+                // containing itself refers to the copy rather than copying again.
+                let copy = List::default();
+                copies.insert(list.address(), copy.clone());
+                for value in list.borrow().iter() {
+                    copy.push(value.deep_clone_with(copies));
+                }
+                Value::List(copy)
+            }
+            Value::Tuple(values) => Value::Tuple(
+                values
+                    .iter()
+                    .map(|value| value.deep_clone_with(copies))
+                    .collect(),
+            ),
+            Value::Map(map) => {
+                let mut copy = Map::new();
+                for (key, value) in map.iter() {
+                    copy.insert(key.clone(), value.deep_clone_with(copies));
+                }
+                Value::Map(copy)
+            }
+            value => value.clone(),
+        }
+    }
+
+    /// Formats strings the way they would be written in source: quoted, with
+    /// quotes, backslashes and control characters escaped. Other values are
+    /// displayed as usual.
+    pub fn escaped(&self) -> String {
+        let Value::String(value) = self else {
+            return self.to_string();
+        };
+
+        let mut escaped = String::from('"');
+        for c in value.chars() {
+            match c {
+                '\n' => escaped.push_str("\\n"),
+                '\r' => escaped.push_str("\\r"),
+                '\t' => escaped.push_str("\\t"),
+                '\0' => escaped.push_str("\\0"),
+                '\\' => escaped.push_str("\\\\"),
+                '"' => escaped.push_str("\\\""),
+                c if c.is_control() => escaped.push_str(&format!("\\u{{{:x}}}", c as u32)),
+                c => escaped.push(c),
+            }
+        }
+        escaped.push('"');
+        escaped
+    }
+}
+
+/// Numbers are displayed in their shortest form unless a precision is given
+/// (e.g. `{:.2}`), in which case they're displayed with that many decimal
+/// places. The precision only applies to numbers, including those in lists,
+/// tuples and maps.
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        match self {
+            Value::Boolean(value) => write!(f, "{value}"),
+            Value::String(value) => write!(f, "{value}"),
+            Value::Number(value) => match f.precision() {
+                Some(precision) => write!(f, "{value:.precision$}"),
+                None => write!(f, "{value}"),
+            },
+            Value::Nil => write!(f, "nil"),
+            Value::List(list) => match list.start_display() {
+                Some(_visit) => {
+                    write!(f, "[")?;
+                    fmt_elements(f, &list.borrow())?;
+                    write!(f, "]")
+                }
+                // The list contains itself.
+                None => write!(f, "[...]"),
+            },
+            // A single element tuple keeps its trailing comma so it can't be
+            // mistaken for a grouping.
+            Value::Tuple(values) if values.len() == 1 => {
+                write!(f, "(")?;
+                fmt_elements(f, values)?;
+                write!(f, ",)")
+            }
+            Value::Tuple(values) => {
+                write!(f, "(")?;
+                fmt_elements(f, values)?;
+                write!(f, ")")
+            }
+            Value::Map(map) => {
+                write!(f, "{{")?;
+                for (i, (key, value)) in map.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    match f.precision() {
+                        Some(precision) => write!(f, "{key}: {value:.precision$}")?,
+                        None => write!(f, "{key}: {value}")?,
+                    }
+                }
+                write!(f, "}}")
+            }
+            Value::NativeFunction(native) => write!(f, "<native fn {}>", native.name),
+            Value::Function(function) => write!(f, "<fn {}>", function.name()),
+        }
+    }
+}
+
+/// Displays a number as usual but with its whole part split into groups of
+/// three digits by commas, e.g. `1234567.5` as `1,234,567.5`.
+pub fn group_digits(value: f32) -> String {
+    let number = value.to_string();
+    let (sign, unsigned) = match number.strip_prefix('-') {
+        Some(unsigned) => ("-", unsigned),
+        None => ("", &number[..]),
+    };
+    let (whole, fraction) = match unsigned.find('.') {
+        Some(point) => unsigned.split_at(point),
+        None => (unsigned, ""),
+    };
+    // `inf` and `NaN` have no digits to group.
+    if !whole.chars().all(|c| c.is_ascii_digit()) {
+        return number;
+    }
+
+    let mut grouped = String::from(sign);
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    grouped + fraction
+}
+
+fn fmt_elements(f: &mut Formatter<'_>, values: &[Value]) -> fmt::Result {
+    for (i, value) in values.iter().enumerate() {
+        if i > 0 {
+            write!(f, ", ")?;
+        }
+        match f.precision() {
+            Some(precision) => write!(f, "{value:.precision$}")?,
+            None => write!(f, "{value}")?,
+        }
+    }
+    Ok(())
+}
+
+/// The longest string, in bytes, that repeating a string may build.
+pub const MAX_REPEAT_LENGTH: usize = 1 << 26;
+
+/// Fails with `RuntimeError::StringTooLong` if repeating `string` `count`
+/// times would exceed `MAX_REPEAT_LENGTH`, rather than letting the allocation
+/// abort the process.
+pub fn check_repeat_length(string: &str, count: f32) -> RuntimeResult<()> {
+    if f64::from(count) * string.len() as f64 > MAX_REPEAT_LENGTH as f64 {
+        return Err(RuntimeError::StringTooLong {
+            max: MAX_REPEAT_LENGTH,
+        });
+    }
+    Ok(())
+}
+
+fn invalid_operands(operation: &'static str, left: &Value, right: &Value) -> RuntimeError {
+    RuntimeError::InvalidOperands {
+        operation,
+        left: left.type_name(),
+        right: right.type_name(),
+    }
+}
+
+impl Sub for Value {
+    type Output = RuntimeResult<Value>;
+
+    fn sub(self, rhs: Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Number(lhs_value), Value::Number(rhs_value)) => {
+                Ok(Value::Number(lhs_value - rhs_value))
+            }
+            (lhs, rhs) => Err(invalid_operands("subtract", &lhs, &rhs)),
+        }
+    }
+}
+
+impl Add for Value {
+    type Output = RuntimeResult<Value>;
+
+    fn add(self, rhs: Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Number(lhs_value), Value::Number(rhs_value)) => {
+                Ok(Value::Number(lhs_value + rhs_value))
+            }
+            (Value::String(lhs_value), Value::String(rhs_value)) => {
+                Ok(Value::String(lhs_value + &rhs_value))
+            }
+            // Anything can be appended to a string.
+            (Value::String(lhs_value), rhs) => Ok(Value::String(format!("{lhs_value}{rhs}"))),
+            // Only booleans, numbers and nil can be prepended to a string.
+            (
+                lhs @ (Value::Boolean(_) | Value::Number(_) | Value::Nil),
+                Value::String(rhs_value),
+            ) => Ok(Value::String(format!("{lhs}{rhs_value}"))),
+            // Concatenating builds a new list, leaving both operands as they were.
+            (Value::List(lhs_list), Value::List(rhs_list)) => {
+                let mut values = lhs_list.to_vec();
+                values.extend(rhs_list.borrow().iter().cloned());
+                Ok(Value::List(List::new(values)))
+            }
+            (lhs, rhs) => Err(invalid_operands("add", &lhs, &rhs)),
+        }
+    }
+}
+
+impl Div for Value {
+    type Output = RuntimeResult<Value>;
+
+    fn div(self, rhs: Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Number(lhs_value), Value::Number(rhs_value)) => {
+                Ok(Value::Number(lhs_value / rhs_value))
+            }
+            (lhs, rhs) => Err(invalid_operands("divide", &lhs, &rhs)),
+        }
+    }
+}
+
+impl Mul for Value {
+    type Output = RuntimeResult<Value>;
+
+    fn mul(self, rhs: Value) -> Self::Output {
+        match (self, rhs) {
+            (Value::Number(lhs_value), Value::Number(rhs_value)) => {
+                Ok(Value::Number(lhs_value * rhs_value))
+            }
+            (Value::String(string), Value::Number(count))
+            | (Value::Number(count), Value::String(string)) => {
+                check_repeat_length(&string, count)?;
+                Ok(Value::String(string.repeat(count as usize)))
+            }
+            (lhs, rhs) => Err(invalid_operands("multiply", &lhs, &rhs)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{borrow::Cow, cell::RefCell, rc::Rc};
+
+    use super::*;
+    use crate::lib::{
+        environment::Environment,
+        natives::{NativeBody, Parameters},
+        parser::{Block, Function},
+    };
+
+    #[test]
+    fn display_number_default_precision() {
+        let value = Value::Number(1.23456);
+
+        assert_eq!(format!("{value}"), "1.23456");
+    }
+
+    #[test]
+    fn display_number_with_precision() {
+        let value = Value::List(List::from(vec![
+            Value::Number(1.23456),
+            Value::String("1.23456".to_string()),
+        ]));
+
+        assert_eq!(format!("{value:.2}"), "[1.23, 1.23456]");
+    }
+
+    #[test]
+    fn display_map_in_insertion_order() {
+        let mut map = Map::new();
+        for (key, value) in [("z", 1.0), ("a", 2.0), ("m", 3.0)] {
+            map.insert(key.to_string(), Value::Number(value));
+        }
+
+        assert_eq!(format!("{}", Value::Map(map)), "{z: 1, a: 2, m: 3}");
+    }
+
+    /// A value along with the expected results of each helper for it.
+    struct Sample {
+        value: Value,
+        lox_truthy: bool,
+        loose_truthy: bool,
+        number: Option<f32>,
+        string: Option<&'static str>,
+    }
+
+    /// One or two values of every variant which can be evaluated.
+    fn samples() -> Vec<Sample> {
+        let sample = |value, lox_truthy, loose_truthy| Sample {
+            value,
+            lox_truthy,
+            loose_truthy,
+            number: None,
+            string: None,
+        };
+        let mut map = Map::new();
+        map.insert("a".to_string(), Value::Nil);
+        let function = UserFunction::new(
+            Rc::new(Function {
+                name: "f".to_string(),
+                parameters: vec![],
+                defaults: vec![],
+                rest: None,
+                body: Block::from(vec![]),
+            }),
+            Rc::new(RefCell::new(Environment::new())),
+        );
+        let native = NativeFunction {
+            name: Cow::Borrowed("native"),
+            parameters: Parameters::Variadic,
+            function: NativeBody::Pure(|_| Ok(Value::Nil)),
+        };
+
+        vec![
+            sample(Value::Boolean(true), true, true),
+            sample(Value::Boolean(false), false, false),
+            Sample {
+                string: Some("a"),
+                ..sample(Value::String("a".to_string()), true, true)
+            },
+            Sample {
+                string: Some(""),
+                ..sample(Value::String("".to_string()), true, false)
+            },
+            Sample {
+                number: Some(2.5),
+                ..sample(Value::Number(2.5), true, true)
+            },
+            Sample {
+                number: Some(0.0),
+                ..sample(Value::Number(0.0), true, false)
+            },
+            sample(Value::List(List::from(vec![Value::Nil])), true, true),
+            sample(Value::List(List::from(vec![])), true, false),
+            sample(Value::Tuple(vec![Value::Nil]), true, true),
+            sample(Value::Tuple(vec![]), true, false),
+            sample(Value::Map(map), true, true),
+            sample(Value::Map(Map::new()), true, false),
+            sample(Value::Function(function), true, true),
+            sample(Value::NativeFunction(native), true, true),
+            sample(Value::Nil, false, false),
+        ]
+    }
+
+    #[test]
+    fn is_truthy() {
+        for sample in samples() {
+            let value = sample.value;
+            assert_eq!(
+                value.is_truthy(TruthinessMode::Lox),
+                sample.lox_truthy,
+                "{value:?}"
+            );
+            assert_eq!(
+                value.is_truthy(TruthinessMode::Loose),
+                sample.loose_truthy,
+                "{value:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn is_nil() {
+        for sample in samples() {
+            let value = sample.value;
+            assert_eq!(value.is_nil(), value == Value::Nil, "{value:?}");
+        }
+    }
+
+    #[test]
+    fn as_number() {
+        for sample in samples() {
+            assert_eq!(
+                sample.value.as_number(),
+                sample.number,
+                "{:?}",
+                sample.value
+            );
+        }
+    }
+
+    #[test]
+    fn as_string() {
+        for sample in samples() {
+            assert_eq!(
+                sample.value.as_string(),
+                sample.string,
+                "{:?}",
+                sample.value
+            );
+        }
+    }
+
+    #[test]
+    fn from_literal() {
+        let literals = [
+            LiteralValue::Boolean(true),
+            LiteralValue::String("a".to_string()),
+            LiteralValue::Number(1.5),
+            LiteralValue::Nil,
+        ];
+
+        let values: Vec<_> = literals.iter().map(Value::from).collect();
+
+        assert_eq!(
+            values,
+            [
+                Value::Boolean(true),
+                Value::String("a".to_string()),
+                Value::Number(1.5),
+                Value::Nil
+            ]
+        );
+    }
+
+    #[test]
+    fn group_digits_in_threes() {
+        for (value, expected) in [
+            (0.0, "0"),
+            (999.0, "999"),
+            (1000.0, "1,000"),
+            (1000000.0, "1,000,000"),
+            (-1234567.0, "-1,234,567"),
+            (12345.5, "12,345.5"),
+            (f32::INFINITY, "inf"),
+            (f32::NAN, "NaN"),
+        ] {
+            assert_eq!(group_digits(value), expected);
+        }
+    }
+
+    #[test]
+    fn escaped_strings() {
+        let value = Value::String("a\nb\t\"c\"\\\u{1b}".to_string());
+
+        assert_eq!(value.escaped(), "\"a\\nb\\t\\\"c\\\"\\\\\\u{1b}\"");
+    }
+
+    #[test]
+    fn escaped_non_strings_display_as_usual() {
+        let value = Value::List(List::from(vec![
+            Value::Number(1.0),
+            Value::String("a\nb".to_string()),
+        ]));
+
+        assert_eq!(value.escaped(), "[1, a\nb]");
+    }
+
+    #[test]
+    fn deep_clone_copies_nested_lists() {
+        let inner = List::from(vec![Value::Number(1.0)]);
+        let value = Value::Tuple(vec![Value::List(inner.clone())]);
+
+        let copy = value.deep_clone();
+        inner.push(Value::Number(2.0));
+
+        assert_eq!(copy.to_string(), "([1],)");
+        assert_eq!(value.to_string(), "([1, 2],)");
+    }
+
+    #[test]
+    fn deep_clone_copies_shared_and_cyclic_lists_once() {
+        let shared = List::from(vec![Value::Number(1.0)]);
+        let cyclic = List::default();
+        cyclic.push(Value::List(cyclic.clone()));
+        let value = Value::Tuple(vec![
+            Value::List(shared.clone()),
+            Value::List(shared.clone()),
+            Value::List(cyclic.clone()),
+        ]);
+
+        let Value::Tuple(copy) = value.deep_clone() else {
+            panic!("Copy of a tuple should be a tuple");
+        };
+        let [Value::List(first), Value::List(second), Value::List(cycle)] = &copy[..] else {
+            panic!("Copy should have the same elements");
+        };
+
+        assert_eq!(first.address(), second.address());
+        assert_ne!(first.address(), shared.address());
+        assert_ne!(cycle.address(), cyclic.address());
+        let Value::List(inner) = &cycle.borrow()[0] else {
+            panic!("Copy of a cyclic list should contain a list");
+        };
+        assert_eq!(inner.address(), cycle.address());
+    }
+}
@@ -0,0 +1,215 @@
+use std::rc::Rc;
+
+use super::{
+    interner::Symbol,
+    parser::{BinaryOperator, Block, Expression, Function, LiteralValue, UnaryOperator},
+};
+
+/// A pass over the expression tree. Each method handles one kind of
+/// expression and decides for itself whether (and in which order) to visit
+/// the children, by calling `accept` on them.
+pub trait Visitor<T> {
+    fn visit_assignment(&mut self, identifier: Symbol, value: &Expression) -> T;
+    fn visit_binary(
+        &mut self,
+        left: &Expression,
+        right: &Expression,
+        operator: &BinaryOperator,
+    ) -> T;
+    fn visit_coalesce(&mut self, left: &Expression, right: &Expression) -> T;
+    fn visit_call(
+        &mut self,
+        callee: &Expression,
+        arguments: &[Expression],
+        named: &[(String, Expression)],
+    ) -> T;
+    fn visit_get(&mut self, object: &Expression, name: &str, optional: bool) -> T;
+    fn visit_grouping(&mut self, expression: &Expression) -> T;
+    fn visit_index(&mut self, target: &Expression, index: &Expression) -> T;
+    fn visit_list(&mut self, elements: &[Expression]) -> T;
+    fn visit_tuple(&mut self, elements: &[Expression]) -> T;
+    fn visit_map(&mut self, entries: &[(String, Expression)]) -> T;
+    fn visit_interpolation(&mut self, parts: &[Expression]) -> T;
+    fn visit_literal(&mut self, value: &LiteralValue) -> T;
+    fn visit_variable(&mut self, identifier: Symbol) -> T;
+    fn visit_block(&mut self, body: &Block, value: Option<&Expression>) -> T;
+    fn visit_lambda(&mut self, function: &Rc<Function>) -> T;
+    fn visit_if(
+        &mut self,
+        condition: &Expression,
+        then_branch: &Expression,
+        else_branch: Option<&Expression>,
+    ) -> T;
+    fn visit_unary(&mut self, right: &Expression, operator: &UnaryOperator) -> T;
+}
+
+impl Expression {
+    pub fn accept<T>(&self, visitor: &mut impl Visitor<T>) -> T {
+        match self {
+            Expression::Assignment { identifier, value } => {
+                visitor.visit_assignment(*identifier, value)
+            }
+            Expression::Binary {
+                left,
+                right,
+                operator,
+            } => visitor.visit_binary(left, right, operator),
+            Expression::Coalesce { left, right } => visitor.visit_coalesce(left, right),
+            Expression::Call {
+                callee,
+                arguments,
+                named,
+            } => visitor.visit_call(callee, arguments, named),
+            Expression::Get {
+                object,
+                name,
+                optional,
+            } => visitor.visit_get(object, name, *optional),
+            Expression::Grouping(expression) => visitor.visit_grouping(expression),
+            Expression::Index { target, index } => visitor.visit_index(target, index),
+            Expression::List(elements) => visitor.visit_list(elements),
+            Expression::Tuple(elements) => visitor.visit_tuple(elements),
+            Expression::Map(entries) => visitor.visit_map(entries),
+            Expression::Interpolation(parts) => visitor.visit_interpolation(parts),
+            Expression::Literal(value) => visitor.visit_literal(value),
+            Expression::Variable(identifier) => visitor.visit_variable(*identifier),
+            Expression::Block { body, value } => visitor.visit_block(body, value.as_deref()),
+            Expression::Lambda(function) => visitor.visit_lambda(function),
+            Expression::If {
+                condition,
+                then_branch,
+                else_branch,
+            } => visitor.visit_if(condition, then_branch, else_branch.as_deref()),
+            Expression::Unary { right, operator } => visitor.visit_unary(right, operator),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::lib::parser::Parser;
+    use crate::lib::scanner::Scanner;
+    use crate::{expr, tokens};
+
+    use super::*;
+
+    #[derive(Default)]
+    struct NodeCounter {
+        literals: usize,
+        operators: usize,
+        variables: usize,
+    }
+
+    impl Visitor<()> for NodeCounter {
+        fn visit_assignment(&mut self, _: Symbol, value: &Expression) {
+            value.accept(self);
+        }
+
+        fn visit_binary(&mut self, left: &Expression, right: &Expression, _: &BinaryOperator) {
+            self.operators += 1;
+            left.accept(self);
+            right.accept(self);
+        }
+
+        fn visit_coalesce(&mut self, left: &Expression, right: &Expression) {
+            self.operators += 1;
+            left.accept(self);
+            right.accept(self);
+        }
+
+        fn visit_call(
+            &mut self,
+            callee: &Expression,
+            arguments: &[Expression],
+            named: &[(String, Expression)],
+        ) {
+            callee.accept(self);
+            for argument in arguments.iter().chain(named.iter().map(|(_, value)| value)) {
+                argument.accept(self);
+            }
+        }
+
+        fn visit_get(&mut self, object: &Expression, _: &str, _: bool) {
+            object.accept(self);
+        }
+
+        fn visit_grouping(&mut self, expression: &Expression) {
+            expression.accept(self);
+        }
+
+        fn visit_index(&mut self, target: &Expression, index: &Expression) {
+            target.accept(self);
+            index.accept(self);
+        }
+
+        fn visit_list(&mut self, elements: &[Expression]) {
+            for element in elements {
+                element.accept(self);
+            }
+        }
+
+        fn visit_tuple(&mut self, elements: &[Expression]) {
+            for element in elements {
+                element.accept(self);
+            }
+        }
+
+        fn visit_map(&mut self, entries: &[(String, Expression)]) {
+            for (_, value) in entries {
+                value.accept(self);
+            }
+        }
+
+        fn visit_interpolation(&mut self, parts: &[Expression]) {
+            for part in parts {
+                part.accept(self);
+            }
+        }
+
+        fn visit_literal(&mut self, _: &LiteralValue) {
+            self.literals += 1;
+        }
+
+        fn visit_variable(&mut self, _: Symbol) {
+            self.variables += 1;
+        }
+
+        fn visit_block(&mut self, _: &Block, value: Option<&Expression>) {
+            if let Some(value) = value {
+                value.accept(self);
+            }
+        }
+
+        fn visit_lambda(&mut self, _: &Rc<Function>) {}
+
+        fn visit_if(
+            &mut self,
+            condition: &Expression,
+            then_branch: &Expression,
+            else_branch: Option<&Expression>,
+        ) {
+            condition.accept(self);
+            then_branch.accept(self);
+            if let Some(else_branch) = else_branch {
+                else_branch.accept(self);
+            }
+        }
+
+        fn visit_unary(&mut self, right: &Expression, _: &UnaryOperator) {
+            self.operators += 1;
+            right.accept(self);
+        }
+    }
+
+    #[test]
+    fn counting_visitor() {
+        let expression = expr!("1 + (2 * -f(3, [4]))");
+        let mut counter = NodeCounter::default();
+
+        expression.accept(&mut counter);
+
+        assert_eq!(counter.literals, 4);
+        assert_eq!(counter.operators, 3);
+        assert_eq!(counter.variables, 1);
+    }
+}
@@ -1,88 +1,592 @@
-use std::io::*;
-use std::{env, fs, io};
+#![allow(special_module_name)]
+
+use std::io::{BufRead, Write};
+use std::{env, fs, io, process};
 
 use lib::environment::Environment;
-use lib::parser::LiteralValue;
+use lib::error::{RuntimeError, RuntimeResult};
+use lib::formatter::format_source;
+use lib::interpreter::Interpreter;
+use lib::list::List;
+use lib::optimizer::optimize;
+use lib::parser::{Parser, Program};
+use lib::prelude::load_prelude;
+use lib::scanner::Scanner;
+use lib::value::Value;
 
 mod lib;
 
+/// Guards REPL users against accidentally entering an infinite loop.
+const REPL_MAX_ITERATIONS: usize = 1_000_000;
+
+/// Guards REPL users against runaway recursion overflowing the stack, which
+/// would end the session.
+const REPL_MAX_CALL_DEPTH: usize = 200;
+
+/// Skips loading the prelude, leaving only natives and globals defined.
+const NO_PRELUDE_FLAG: &str = "--no-prelude";
+
+/// Logs each declaration to stderr as it runs.
+const TRACE_FLAG: &str = "--trace";
+
+/// Denies natives access to files, environment variables and the process.
+const SANDBOX_FLAG: &str = "--sandbox";
+
+/// Warns about expression statements which have no effect, e.g. `1 + 2;`.
+const WARN_FLAG: &str = "--warn";
+
+/// Prints the script with a consistent layout instead of running it.
+const FORMAT_FLAG: &str = "--format";
+
+/// Runs the code given after it instead of a script, e.g. `-e "print 1;"`.
+const EVAL_FLAGS: [&str; 2] = ["-e", "--eval"];
+
+/// Rejects number literals which can't be represented exactly, such as
+/// `123456789012345`, rather than warning about them.
+const STRICT_NUMBERS_FLAG: &str = "--strict-numbers";
+
+/// Prints numbers with the given number of decimal places, e.g.
+/// `--precision 2`.
+const PRECISION_FLAG: &str = "--precision";
+
+/// Ends the options, so that the arguments after it are passed to the script
+/// even if they look like options.
+const END_OF_OPTIONS: &str = "--";
+
+const USAGE: &str = "usage: interpreter [--no-prelude] [--trace] [--sandbox] [--warn] [--format] \
+[--strict-numbers] [--precision <places>] [-e <code> | <script>] [--] [arguments...]";
+
+/// Options set by command line flags.
+#[derive(Debug)]
+struct Options {
+    precision: Option<usize>,
+    eval: Option<String>,
+    prelude: bool,
+    trace: bool,
+    sandbox: bool,
+    warn: bool,
+    format: bool,
+    strict_numbers: bool,
+}
+
+impl Options {
+    /// Removes the flags from `args`, wherever they are, leaving the script
+    /// path and its arguments.
+    fn from_args(args: &mut Vec<String>) -> Result<Self, String> {
+        let mut options = Self {
+            precision: None,
+            eval: None,
+            prelude: true,
+            trace: false,
+            sandbox: false,
+            warn: false,
+            format: false,
+            strict_numbers: false,
+        };
+        let mut arguments = std::mem::take(args).into_iter();
+        // The program's own name.
+        args.extend(arguments.next());
+
+        while let Some(arg) = arguments.next() {
+            match arg.as_str() {
+                END_OF_OPTIONS => args.extend(arguments.by_ref()),
+                NO_PRELUDE_FLAG => options.prelude = false,
+                TRACE_FLAG => options.trace = true,
+                SANDBOX_FLAG => options.sandbox = true,
+                WARN_FLAG => options.warn = true,
+                FORMAT_FLAG => options.format = true,
+                STRICT_NUMBERS_FLAG => options.strict_numbers = true,
+                PRECISION_FLAG => {
+                    let digits = option_value(&arg, arguments.next())?;
+                    let precision = digits.parse().map_err(|_| {
+                        format!("{PRECISION_FLAG} expects a number of decimal places, not {digits}")
+                    })?;
+                    options.precision = Some(precision);
+                }
+                flag if EVAL_FLAGS.contains(&flag) => {
+                    options.eval = Some(option_value(&arg, arguments.next())?);
+                }
+                flag if flag.starts_with('-') => {
+                    return Err(format!("unknown option {flag}\n{USAGE}"));
+                }
+                _ => args.push(arg),
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+/// The value given after `flag`, which options can't do without.
+fn option_value(flag: &str, value: Option<String>) -> Result<String, String> {
+    value.ok_or_else(|| format!("{flag} needs a value"))
+}
+
+fn new_interpreter(environment: Environment, options: &Options) -> Result<Interpreter, String> {
+    let mut interpreter = Interpreter::new(environment);
+    if options.prelude {
+        load_prelude(&mut interpreter).map_err(|error| error.to_string())?;
+    }
+    // Enabled after loading the prelude so that only the user's code is traced.
+    let interpreter = interpreter
+        .with_trace(options.trace)
+        .with_sandbox(options.sandbox)
+        .with_warnings(options.warn);
+    Ok(match options.precision {
+        Some(precision) => interpreter.with_precision(precision),
+        None => interpreter,
+    })
+}
+
 fn environment_with_globals() -> Environment {
     let mut env = Environment::new();
 
     env.assign(
-        &"VERSION".to_string(),
-        LiteralValue::String(env!("CARGO_PKG_VERSION").to_string()),
+        "VERSION",
+        Value::String(env!("CARGO_PKG_VERSION").to_string()),
     );
+    env.assign("PI", Value::Number(std::f32::consts::PI));
+    env.assign("E", Value::Number(std::f32::consts::E));
+    env.assign("MAX_NUMBER", Value::Number(f32::MAX));
+    env.assign("NEWLINE", Value::String("\n".to_string()));
 
     env
 }
 
-fn environment_from_args(args: &Vec<String>) -> Environment {
+fn environment_from_args(args: &[String]) -> Environment {
     let mut env = environment_with_globals();
+    let mut values = vec![];
 
     for (i, arg) in args.iter().enumerate() {
         let identifier = format!("ARG_{}", i).to_string();
         let value = {
             if let Ok(value) = arg.parse() {
-                LiteralValue::Number(value)
+                Value::Number(value)
             } else {
-                LiteralValue::String(arg.to_string())
+                Value::String(arg.to_string())
             }
         };
-        env.assign(&identifier, value);
+        env.assign(&identifier, value.clone());
+        values.push(value);
     }
+    env.assign("ARGS", Value::List(List::new(values)));
 
     env
 }
 
-fn repl() {
+/// Turns the outcome of running a program into the code the process should
+/// exit with, or the error it failed with.
+fn exit_code(result: RuntimeResult<()>, interpreter: &Interpreter) -> Result<i32, String> {
+    match result {
+        Ok(()) => Ok(0),
+        Err(RuntimeError::Exit(code)) => Ok(code),
+        Err(error) => Err(describe_error(error, interpreter)),
+    }
+}
+
+/// Describes an error along with the declaration it happened in, if known.
+fn describe_error(error: RuntimeError, interpreter: &Interpreter) -> String {
+    match interpreter.failed_declaration() {
+        Some(declaration) => format!("{error} in `{declaration}`"),
+        None => error.to_string(),
+    }
+}
+
+/// Commands entered at the REPL prompt which start with `:` and control the
+/// REPL itself rather than being run as code.
+#[derive(Debug, PartialEq)]
+enum MetaCommand {
+    /// `:del name` removes a variable.
+    Delete(String),
+    /// `:ast on|off` toggles printing the parse tree of each line.
+    Ast(bool),
+    /// `:help` lists the meta-commands.
+    Help,
+}
+
+/// Usage and a short description of everything the REPL understands besides
+/// code, in the order `:help` lists them.
+const META_COMMANDS: &[(&str, &str)] = &[
+    (":del <name>", "remove a variable"),
+    (
+        ":ast on|off",
+        "toggle printing the parse tree of each input",
+    ),
+    (":help", "show this help"),
+    ("exit", "leave the REPL"),
+];
+
+/// The text printed by `:help`.
+fn help_text() -> String {
+    let width = META_COMMANDS
+        .iter()
+        .map(|(usage, _)| usage.len())
+        .max()
+        .unwrap_or(0);
+
+    META_COMMANDS
+        .iter()
+        .map(|(usage, description)| format!("{usage:<width$}  {description}\n"))
+        .collect()
+}
+
+/// Returns `None` if the input isn't a meta-command and should be run.
+fn parse_meta_command(input: &str) -> Option<Result<MetaCommand, String>> {
+    let input = input.trim().strip_prefix(':')?;
+    let (command, argument) = input.split_once(' ').unwrap_or((input, ""));
+
+    Some(match (command, argument.trim()) {
+        ("del", "") => Err("usage: :del <name>".to_string()),
+        ("del", identifier) => Ok(MetaCommand::Delete(identifier.to_string())),
+        ("ast", "on") => Ok(MetaCommand::Ast(true)),
+        ("ast", "off") => Ok(MetaCommand::Ast(false)),
+        ("ast", _) => Err("usage: :ast on|off".to_string()),
+        ("help", _) => Ok(MetaCommand::Help),
+        (command, _) => Err(format!("unknown command :{command}")),
+    })
+}
+
+/// Parses `source`, printing any warnings about it to stderr.
+fn parse_source(source: &str, start_line: usize, options: &Options) -> Result<Program, String> {
+    let mut scanner = Scanner::from_source_with_line(source, start_line)
+        .with_strict_numbers(options.strict_numbers);
+    let program = Parser::new(scanner.scan_tokens()?).parse();
+    for warning in scanner.warnings() {
+        eprintln!("warning: {warning}");
+    }
+    program
+}
+
+/// Reads a line of input, or returns `None` once the input has ended, e.g.
+/// because the user pressed Ctrl-D.
+fn read_input(reader: &mut impl BufRead) -> io::Result<Option<String>> {
+    let mut input = String::new();
+    match reader.read_line(&mut input)? {
+        0 => Ok(None),
+        _ => Ok(Some(input)),
+    }
+}
+
+fn repl(options: &Options) -> Result<i32, String> {
     let mut stdout = io::stdout().lock();
     let mut stdin = io::stdin().lock();
-    let mut interpreter = lib::interpreter::Interpreter::new(environment_with_globals());
+    let mut interpreter = new_interpreter(environment_with_globals(), options)?
+        .with_max_iterations(REPL_MAX_ITERATIONS)
+        .with_max_call_depth(REPL_MAX_CALL_DEPTH)
+        .with_echo_expressions(true);
+    let mut show_ast = false;
+    let mut line = 1;
 
     loop {
-        let mut input = String::new();
+        let start_line = line;
 
-        stdout.write(">> ".as_bytes()).unwrap();
+        stdout.write_all(">> ".as_bytes()).unwrap();
         stdout.flush().unwrap();
-        stdin
-            .read_line(&mut input)
-            .expect("error: unable to read user input");
+        let input = match read_input(&mut stdin) {
+            Ok(Some(input)) => input,
+            // End the prompt's line, as the user's Ctrl-D didn't.
+            Ok(None) => {
+                println!();
+                break;
+            }
+            Err(error) => {
+                eprintln!("error: unable to read user input: {error}");
+                break;
+            }
+        };
 
         if input == "exit\n" {
             break;
         }
+        line += input.matches('\n').count();
+        if input.trim().is_empty() {
+            continue;
+        }
+
+        if let Some(command) = parse_meta_command(&input) {
+            match command {
+                Ok(MetaCommand::Delete(identifier)) => {
+                    if !interpreter.undefine(&identifier) {
+                        eprintln!("error: {identifier} is not defined");
+                    }
+                }
+                Ok(MetaCommand::Ast(enabled)) => show_ast = enabled,
+                Ok(MetaCommand::Help) => print!("{}", help_text()),
+                Err(error) => eprintln!("error: {error}"),
+            }
+            continue;
+        }
 
-        let mut scanner = lib::scanner::Scanner::from_source(&input);
-        let mut parser =
-            lib::parser::Parser::new(scanner.scan_tokens().expect("Failed at scanner"));
-        let declarations = parser.parse();
-        interpreter.run(&declarations);
+        let program = match parse_source(&input, start_line, options) {
+            Ok(program) => program,
+            Err(error) => {
+                eprintln!("error: {error}");
+                continue;
+            }
+        };
+        if show_ast {
+            println!("{program:#?}");
+        }
+        // Each declaration runs on its own, so one which fails doesn't stop
+        // the rest of the input from running. A `return` does.
+        for declaration in optimize(program).split() {
+            match interpreter.run(&declaration) {
+                Ok(()) if interpreter.returned() => break,
+                Ok(()) => {}
+                Err(RuntimeError::Exit(code)) => return Ok(code),
+                Err(error) => eprintln!("error: {}", describe_error(error, &interpreter)),
+            }
+        }
     }
+
+    Ok(0)
 }
 
-fn run_script(script_file: &String, environment: Environment) {
-    let input = fs::read_to_string(script_file).expect("Something went wrong reading the file");
-    let mut interpreter = lib::interpreter::Interpreter::new(environment);
-    let mut scanner = lib::scanner::Scanner::from_source(&input);
-    let mut parser = lib::parser::Parser::new(scanner.scan_tokens().expect("Failed at scanner"));
-    let program = parser.parse();
-    interpreter.run(&program);
+fn run_script(
+    script_file: &str,
+    environment: Environment,
+    options: &Options,
+) -> Result<i32, String> {
+    let input = fs::read_to_string(script_file)
+        .map_err(|error| format!("unable to read {script_file}: {error}"))?;
+    run_source(&input, environment, options)
 }
 
-fn main() {
-    let args: Vec<String> = env::args().collect();
-    let no_args = args.len();
+/// Runs `input` the way a script is run, for both script files and code
+/// passed with `--eval`.
+fn run_source(input: &str, environment: Environment, options: &Options) -> Result<i32, String> {
+    if input.trim().is_empty() {
+        return Ok(0);
+    }
+    let mut interpreter = new_interpreter(environment, options)?;
+    let program = optimize(parse_source(input, 1, options)?);
+    exit_code(interpreter.run_script(&program), &interpreter)
+}
+
+fn format_script(script_file: &str) -> Result<i32, String> {
+    let input = fs::read_to_string(script_file)
+        .map_err(|error| format!("unable to read {script_file}: {error}"))?;
+    print!("{}", format_source(&input)?);
+    Ok(0)
+}
+
+fn run(args: &[String], options: &Options) -> Result<i32, String> {
+    if let Some(source) = &options.eval {
+        if options.format {
+            print!("{}", format_source(source)?);
+            return Ok(0);
+        }
+        return run_source(source, environment_from_args(args), options);
+    }
 
-    match no_args {
-        1 => repl(),
+    match args.len() {
+        0 | 1 if options.format => Err(format!("{FORMAT_FLAG} needs a script to format")),
+        0 | 1 => repl(options),
+        _ if options.format => format_script(&args[1]),
         _ => {
-            let env = environment_from_args(&args);
-            run_script(&args[1], env);
+            let env = environment_from_args(args);
+            run_script(&args[1], env, options)
+        }
+    }
+}
+
+fn main() {
+    let mut args: Vec<String> = env::args().collect();
+    let result = Options::from_args(&mut args).and_then(|options| run(&args, &options));
+
+    match result {
+        Ok(0) => {}
+        Ok(code) => process::exit(code),
+        Err(error) => {
+            eprintln!("error: {error}");
+            process::exit(1);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn globals_include_constants() {
+        let env = environment_with_globals();
+
+        match env.resolve("PI") {
+            Value::Number(value) => assert_eq!(format!("{value:.5}"), "3.14159"),
+            value => panic!("Expected PI to be a number, got {value:?}"),
+        }
+        assert_eq!(
+            env.resolve("VERSION"),
+            Value::String(env!("CARGO_PKG_VERSION").to_string())
+        );
+        assert_eq!(env.resolve("NEWLINE"), Value::String("\n".to_string()));
+    }
+
+    #[test]
+    fn args_are_collected_into_a_list() {
+        let env = environment_from_args(&["script".to_string(), "2".to_string()]);
+
+        assert_eq!(
+            env.resolve("ARGS"),
+            Value::List(List::from(vec![
+                Value::String("script".to_string()),
+                Value::Number(2.0)
+            ]))
+        );
+    }
+
+    fn args(args: &[&str]) -> Vec<String> {
+        args.iter().map(|arg| arg.to_string()).collect()
+    }
+
+    #[test]
+    fn flags_are_taken_in_any_position() {
+        let mut args = args(&["interpreter", "script", "--no-prelude", "1", "--trace"]);
+
+        let options = Options::from_args(&mut args).expect("Arguments should be valid");
+
+        assert!(!options.prelude);
+        assert!(options.trace);
+        assert_eq!(args, ["interpreter", "script", "1"]);
+    }
+
+    #[test]
+    fn arguments_after_end_of_options_are_left_to_the_script() {
+        let mut args = args(&["interpreter", "--trace", "script", "--", "--trace", "-x"]);
+
+        let options = Options::from_args(&mut args).expect("Arguments should be valid");
+
+        assert!(options.trace);
+        assert_eq!(args, ["interpreter", "script", "--trace", "-x"]);
+    }
+
+    #[test]
+    fn unknown_flags_are_rejected() {
+        let mut args = args(&["interpreter", "--nope", "script"]);
+
+        assert_eq!(
+            Options::from_args(&mut args).unwrap_err(),
+            format!("unknown option --nope\n{USAGE}")
+        );
+    }
+
+    #[test]
+    fn eval_flag_takes_code() {
+        let mut args = args(&["interpreter", "--trace", "-e", "print 1;", "extra"]);
+
+        let options = Options::from_args(&mut args).expect("Arguments should be valid");
+
+        assert_eq!(options.eval.as_deref(), Some("print 1;"));
+        assert!(options.trace);
+        assert_eq!(args, ["interpreter", "extra"]);
+    }
+
+    #[test]
+    fn eval_flag_needs_code() {
+        let mut args = args(&["interpreter", "--eval"]);
+
+        assert_eq!(
+            Options::from_args(&mut args).unwrap_err(),
+            "--eval needs a value"
+        );
+    }
+
+    #[test]
+    fn read_input_stops_at_end_of_input() {
+        let mut reader = io::Cursor::new("print 1;\n");
+
+        assert_eq!(
+            read_input(&mut reader).unwrap(),
+            Some("print 1;\n".to_string())
+        );
+        assert_eq!(read_input(&mut reader).unwrap(), None);
+    }
+
+    #[test]
+    fn meta_command_ast_toggle() {
+        assert_eq!(
+            parse_meta_command(":ast on\n"),
+            Some(Ok(MetaCommand::Ast(true)))
+        );
+        assert_eq!(
+            parse_meta_command(":ast off"),
+            Some(Ok(MetaCommand::Ast(false)))
+        );
+        assert_eq!(
+            parse_meta_command(":ast maybe"),
+            Some(Err("usage: :ast on|off".to_string()))
+        );
+    }
+
+    #[test]
+    fn meta_command_delete() {
+        assert_eq!(
+            parse_meta_command(":del x\n"),
+            Some(Ok(MetaCommand::Delete("x".to_string())))
+        );
+        assert_eq!(
+            parse_meta_command(":del"),
+            Some(Err("usage: :del <name>".to_string()))
+        );
+    }
+
+    #[test]
+    fn meta_command_help() {
+        assert_eq!(parse_meta_command(":help\n"), Some(Ok(MetaCommand::Help)));
+    }
+
+    #[test]
+    fn help_text_lists_every_command() {
+        let help = help_text();
+
+        for command in [":del", ":ast", ":help", "exit"] {
+            assert!(help.contains(command), "{command} missing from {help}");
         }
+        assert_eq!(help.lines().count(), META_COMMANDS.len());
+    }
+
+    #[test]
+    fn non_meta_commands_are_run() {
+        assert_eq!(parse_meta_command("print 1;\n"), None);
+        assert_eq!(
+            parse_meta_command(":nope"),
+            Some(Err("unknown command :nope".to_string()))
+        );
     }
 
-    if no_args == 0 {
-        repl();
+    #[test]
+    fn parse_source_produces_ast() {
+        let options =
+            Options::from_args(&mut args(&["interpreter"])).expect("Arguments should be valid");
+
+        let program = parse_source("print 1 + 2;", 1, &options).expect("Source should parse");
+
+        assert_eq!(
+            format!("{program:?}"),
+            "Program([Statement(Print([Binary { left: Literal(Number(1.0)), right: Literal(Number(2.0)), operator: Plus }]))])"
+        );
+    }
+
+    #[test]
+    fn parse_source_reports_syntax_errors() {
+        let options =
+            Options::from_args(&mut args(&["interpreter"])).expect("Arguments should be valid");
+
+        assert_eq!(
+            parse_source("f(1, a: 1, a: 2);", 1, &options),
+            Err("Argument a is given more than once".to_string())
+        );
+    }
+
+    #[test]
+    fn strict_numbers_flag_rejects_imprecise_numbers() {
+        let options = Options::from_args(&mut args(&["interpreter", "--strict-numbers"]))
+            .expect("Arguments should be valid");
+
+        assert_eq!(
+            parse_source("print 123456789012345;", 1, &options),
+            Err("Number 123456789012345 on line 1 cannot be represented exactly and becomes 123456790000000".to_string())
+        );
     }
 }
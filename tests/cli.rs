@@ -0,0 +1,519 @@
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+/// A directory for a test's scripts, unique to the test and to this run of
+/// the tests, which is removed when it's dropped.
+struct TempDir(PathBuf);
+
+impl TempDir {
+    fn new(name: &str) -> Self {
+        let path =
+            std::env::temp_dir().join(format!("interpreter_cli_{name}_{}", std::process::id()));
+        // Left over from an earlier run which didn't finish.
+        let _ = std::fs::remove_dir_all(&path);
+        std::fs::create_dir_all(&path).unwrap();
+        Self(path)
+    }
+
+    /// Writes a script into the directory, returning its path.
+    fn script(&self, name: &str, source: &str) -> PathBuf {
+        let path = self.0.join(name);
+        std::fs::write(&path, source).unwrap();
+        path
+    }
+}
+
+impl Drop for TempDir {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.0);
+    }
+}
+
+#[test]
+fn missing_script_reports_error() {
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("does/not/exist.script")
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(stderr.starts_with("error: unable to read does/not/exist.script"));
+    assert!(!stderr.contains("panicked"));
+}
+
+#[test]
+fn scanner_error_reports_error() {
+    let dir = TempDir::new("scanner_error");
+    let script = dir.script("bad.script", "print 1;\n#");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(stderr, "error: Unexpected character (#) on line 2\n");
+}
+
+#[test]
+fn prelude_helpers_are_absent_with_no_prelude() {
+    let dir = TempDir::new("prelude");
+    let script = dir.script("any.script", "print filter; print any;");
+
+    let with_prelude = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+    let without_prelude = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("--no-prelude")
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(
+        String::from_utf8_lossy(&with_prelude.stdout),
+        "<native fn filter>\n<fn any>\n"
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&without_prelude.stdout),
+        "<native fn filter>\nnil\n"
+    );
+}
+
+#[test]
+fn exit_native_sets_exit_code() {
+    let dir = TempDir::new("exit");
+    let script = dir.script("exit.script", "print 1; exit(3); print 2;");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(3));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn repl_exit_native_ends_session() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"exit(0);\nprint 1;\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), ">> ");
+}
+
+#[test]
+fn repl_limits_recursion() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"fun f() { return 1 + f(); }\nf();\nprint 1;\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), ">> >> >> 1\n>> \n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Calls exceeded the limit of 200 nested calls in `return 1 + f();`\n"
+    );
+}
+
+#[test]
+fn repl_ast_toggle_prints_parse_tree() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b":ast on\nprint 1;\n:ast off\nprint 2;\nexit\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert_eq!(stdout.matches("Program(").count(), 1);
+    assert!(stdout.contains("Print(\n"));
+    assert!(stdout.contains("1\n"));
+    assert!(stdout.contains("2\n"));
+}
+
+#[test]
+fn repl_reports_lines_across_inputs() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"print 1;\nprint 2;\n#\nexit\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Unexpected character (#) on line 3\n"
+    );
+}
+
+#[test]
+fn trace_flag_logs_to_stderr() {
+    let dir = TempDir::new("trace");
+    let script = dir.script("trace.script", "print 1;");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("--trace")
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "trace: Statement(Print([Literal(Number(1.0))]))\n"
+    );
+}
+
+#[test]
+fn empty_script_does_nothing() {
+    let dir = TempDir::new("empty");
+    let script = dir.script("empty.script", "\n  \n");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&output.stderr), "");
+}
+
+#[test]
+fn expression_statements_only_echo_in_repl() {
+    let dir = TempDir::new("echo");
+    let script = dir.script("echo.script", "42;");
+
+    let script_output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"42;\nexit\n")
+        .unwrap();
+    let repl_output = child.wait_with_output().unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&script_output.stdout), "");
+    assert_eq!(String::from_utf8_lossy(&repl_output.stdout), ">> 42\n>> ");
+}
+
+#[test]
+fn repl_help_lists_commands() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b":help\nexit\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+    let stdout = String::from_utf8_lossy(&output.stdout);
+
+    assert!(stdout.contains(":ast on|off"));
+    assert!(stdout.contains(":help"));
+}
+
+#[test]
+fn sandbox_flag_denies_exit() {
+    let dir = TempDir::new("sandbox");
+    let script = dir.script("sandbox.script", "print 1; exit(3);");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("--sandbox")
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "1\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: exit requires process access, which is not allowed in `exit(3);`\n"
+    );
+}
+
+#[test]
+fn warn_flag_reports_statements_without_effect() {
+    let dir = TempDir::new("warn");
+    let script = dir.script("warn.script", "fun f() {} 1e1 + 2; f();");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("--warn")
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+    let stderr = String::from_utf8_lossy(&output.stderr);
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        stderr,
+        "warning: expression statement has no effect: `1e1 + 2;`\n"
+    );
+}
+
+#[test]
+fn format_flag_prints_formatted_script() {
+    let dir = TempDir::new("format");
+    let script = dir.script("format.script", "if true{print 1+2;}");
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("--format")
+        .arg(&script)
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        "if true {\n    print 1 + 2;\n}\n"
+    );
+}
+
+#[test]
+fn repl_keeps_running_input_after_a_failing_declaration() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"var x = 1; print -\"a\"; print x;\nexit\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), ">> 1\n>> ");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Cannot negate string in `print -\"a\";`\n"
+    );
+}
+
+#[test]
+fn repl_stops_input_at_return_and_reports_syntax_errors() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"print 1; return; print 2;\nf(a: 1, a: 2);\nprint 3;\nexit\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stdout),
+        ">> 1\n>> >> 3\n>> "
+    );
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Argument a is given more than once\n"
+    );
+}
+
+#[test]
+fn eval_flag_runs_inline_code() {
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("-e")
+        .arg("print 1 + 2; exit(4);")
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(4));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3\n");
+}
+
+#[test]
+fn errors_quote_declarations_as_written_before_folding() {
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .arg("-e")
+        .arg("x = \"s\"; print (1 + 2) * 1e20 - x;")
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        "error: Cannot subtract number and string in `print (1 + 2) * 1e20 - x;`\n"
+    );
+}
+
+#[test]
+fn format_flag_formats_inline_code() {
+    for args in [
+        ["--format", "-e", "if true{print 1+2;}"],
+        ["-e", "if true{print 1+2;}", "--format"],
+    ] {
+        let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+            .args(args)
+            .output()
+            .expect("Failed to run interpreter binary");
+
+        assert_eq!(output.status.code(), Some(0), "{args:?}");
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "if true {\n    print 1 + 2;\n}\n",
+            "{args:?}"
+        );
+    }
+}
+
+#[test]
+fn precision_flag_sets_decimal_places() {
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .args(["--precision", "2", "-e", "print 3.14159, [1];"])
+        .output()
+        .expect("Failed to run interpreter binary");
+    let invalid = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .args(["--precision", "two", "-e", "print 1;"])
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3.14 [1.00]\n");
+    assert_eq!(invalid.status.code(), Some(1));
+    assert_eq!(
+        String::from_utf8_lossy(&invalid.stderr),
+        "error: --precision expects a number of decimal places, not two\n"
+    );
+}
+
+#[test]
+fn options_after_eval_code_apply() {
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .args(["-e", "print 3.14159, ARG_1;", "--precision", "2", "x"])
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "3.14 x\n");
+}
+
+#[test]
+fn unknown_options_are_rejected() {
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .args(["-e", "print 1;", "--precison", "2"])
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8_lossy(&output.stderr)
+        .starts_with("error: unknown option --precison\nusage: "));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+}
+
+#[test]
+fn imprecise_numbers_warn_unless_strict() {
+    let warning = "Number 123456789012345 on line 1 cannot be represented exactly and becomes 123456790000000";
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .args(["-e", "print 123456789012345;"])
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "123456790000000\n");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!("warning: {warning}\n")
+    );
+
+    let output = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .args(["-e", "print 123456789012345;", "--strict-numbers"])
+        .output()
+        .expect("Failed to run interpreter binary");
+
+    assert_eq!(output.status.code(), Some(1));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), "");
+    assert_eq!(
+        String::from_utf8_lossy(&output.stderr),
+        format!("error: {warning}\n")
+    );
+}
+
+#[test]
+fn repl_ends_at_end_of_input() {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_interpreter"))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .spawn()
+        .expect("Failed to run interpreter binary");
+    child
+        .stdin
+        .take()
+        .unwrap()
+        .write_all(b"print 1;\n")
+        .unwrap();
+
+    let output = child.wait_with_output().unwrap();
+
+    assert_eq!(output.status.code(), Some(0));
+    assert_eq!(String::from_utf8_lossy(&output.stdout), ">> 1\n>> \n");
+}